@@ -0,0 +1,1629 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    iter::Peekable,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    rc::Rc,
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    error::ServerError,
+    message::{
+        answer::Answer,
+        dnssec::{base32hex, is_subdomain, Nsec3, Nsec3Param},
+        edns::ExtendedError,
+        header::ResponseCode,
+        question::QuestionType,
+        rdata::{RData, Serial},
+        types::{DnsClass, DnsType, DomainName},
+        update::Update,
+    },
+    signer::{delegations, is_occluded, SigningKey, ZoneSigner},
+    update::{apply, check_prerequisites, prescan},
+    zone::{SerialPolicy, Zone, ZoneFileParser},
+};
+
+/// Online signatures are made afresh for every response, so they only need to outlive caches.
+const ONLINE_SIGNATURE_VALIDITY: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// CNAME chains inside a zone are followed this many times at most, which also ends loops.
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// TTL of the HINFO record that stands in for the answer to an ANY question.
+const MINIMAL_ANY_TTL: u32 = 3600;
+
+/// Changes kept for incremental zone transfers. Secondaries further behind get the whole zone.
+const MAX_JOURNAL_LENGTH: usize = 64;
+
+/// How questions of type ANY are answered.
+///
+/// https://www.rfc-editor.org/rfc/rfc8482
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnyPolicy {
+    /// Every RRset at the name.
+    All,
+    /// A single synthesized HINFO record, so ANY questions cannot be used to amplify traffic.
+    Hinfo,
+}
+
+impl FromStr for AnyPolicy {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "all" => Ok(AnyPolicy::All),
+            "hinfo" => Ok(AnyPolicy::Hinfo),
+            _ => Err(ServerError::InvalidAnyPolicy(format!(
+                "{} is not one of all or hinfo",
+                value
+            ))),
+        }
+    }
+}
+
+/// Where a local zone comes from, in the `<origin>:<master file>` form.
+pub struct ZoneSource {
+    pub origin: String,
+    pub path: String,
+}
+
+impl FromStr for ZoneSource {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some((origin, path)) if !origin.is_empty() && !path.is_empty() => Ok(Self {
+                origin: origin.trim_end_matches('.').to_lowercase(),
+                path: path.to_string(),
+            }),
+            _ => Err(ServerError::ParseZoneFile(format!(
+                "{} is not in the origin:file form",
+                value
+            ))),
+        }
+    }
+}
+
+/// Result of looking up a question in a local zone.
+pub struct Lookup {
+    pub code: ResponseCode,
+    /// Unset for referrals to a child zone, whose data this server does not have.
+    pub authoritative: bool,
+    pub answers: Vec<Answer>,
+    pub authorities: Vec<Answer>,
+    pub additionals: Vec<Answer>,
+    /// Why the question was not answered, for SERVFAIL and REFUSED.
+    pub extended_error: Option<ExtendedError>,
+}
+
+impl Lookup {
+    fn new() -> Self {
+        Self {
+            code: ResponseCode::NoErrorCondition,
+            authoritative: true,
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            extended_error: None,
+        }
+    }
+
+    // The same NSEC or NSEC3 record may prove several things at once, it is only sent once
+    fn add_authorities(&mut self, records: Vec<Answer>) {
+        for record in records {
+            let is_present = self.authorities.iter().any(|authority| {
+                authority.kind == record.kind
+                    && authority.name.eq_ignore_ascii_case(&record.name)
+                    && authority.data == record.data
+            });
+
+            if !is_present {
+                self.authorities.push(record);
+            }
+        }
+    }
+}
+
+/// The changes between two versions of a zone, in the order an IXFR response sends them.
+///
+/// https://www.rfc-editor.org/rfc/rfc1995#section-4
+#[derive(Clone)]
+pub struct ZoneDiff {
+    pub old_soa: Answer,
+    pub deleted: Vec<Answer>,
+    pub new_soa: Answer,
+    pub added: Vec<Answer>,
+}
+
+impl ZoneDiff {
+    /// The changes in a list of records that holds each of them in IXFR order, as read from a
+    /// journal or an incremental transfer. None when the list stops in the middle of a change.
+    pub fn read_all(records: &[Answer]) -> Option<Vec<ZoneDiff>> {
+        let mut records = records.iter().peekable();
+        let mut diffs = Vec::new();
+
+        while let Some(old_soa) = records.next() {
+            let deleted = read_section(&mut records);
+            let new_soa = records.next()?;
+            let added = read_section(&mut records);
+
+            diffs.push(ZoneDiff {
+                old_soa: old_soa.clone(),
+                deleted,
+                new_soa: new_soa.clone(),
+                added,
+            });
+        }
+
+        Some(diffs)
+    }
+
+    /// The records of the change in IXFR order.
+    pub fn records(&self) -> impl Iterator<Item = &Answer> {
+        std::iter::once(&self.old_soa)
+            .chain(&self.deleted)
+            .chain(std::iter::once(&self.new_soa))
+            .chain(&self.added)
+    }
+}
+
+/// A zone the server answers for from a master file or a zone transfer.
+///
+/// Zones with keys in the key directory are signed online: the DNSKEY records and the NSEC or
+/// NSEC3 chain are added once when the zone is loaded, and RRsets are signed as they are sent
+/// to clients that set the DO bit. Zones without keys are served as they are, so a zone signed
+/// with `sign-zone` is served with its signatures.
+///
+/// https://www.rfc-editor.org/rfc/rfc1034#section-4.3.2
+pub struct AuthoritativeZone {
+    /// Apex of the zone, lowercase and without the trailing dot.
+    origin: String,
+    /// The zone as read from the master file, before online signing. Dynamic updates apply to
+    /// it.
+    source: Zone,
+    /// Master file the zone was loaded from. Changes made by dynamic updates go to a journal
+    /// next to it, see `journal_path`.
+    path: Option<String>,
+    /// Records by lowercase owner name. Empty non-terminals are there without records, and
+    /// NSEC3 records are kept apart.
+    names: HashMap<String, Vec<Answer>>,
+    /// NSEC3 records by hashed owner name, in hash order.
+    nsec3: Vec<(String, Answer)>,
+    nsec3_param: Option<Nsec3Param>,
+    delegations: HashSet<String>,
+    /// Whether PTR records are synthesized from the zone's address records.
+    ptr_synthesis: bool,
+    /// Changes made by dynamic updates and incremental transfers, oldest first. Online-signed
+    /// zones keep none, their signatures and denial records are not part of the changes.
+    journal: Vec<ZoneDiff>,
+    // Shared with the zone that replaces this one after an update
+    signer: Option<Rc<ZoneSigner>>,
+}
+
+impl AuthoritativeZone {
+    /// Reads the master file, applies the changes in its journal and, when `key_directory`
+    /// holds keys for the zone, prepares it for online signing. The zone is signed with NSEC3
+    /// when it has an NSEC3PARAM record.
+    pub fn load(source: &ZoneSource, key_directory: Option<&str>) -> Result<Self, ServerError> {
+        let input = fs::read_to_string(&source.path)
+            .map_err(|err| ServerError::ParseZoneFile(format!("{}: {}", source.path, err)))?;
+        let mut zone = ZoneFileParser::new(&input, &source.origin).parse()?;
+        let changes = read_journal(&source.path, &zone)?;
+
+        apply_changes(&mut zone, &changes)?;
+
+        let keys = match key_directory {
+            Some(directory) => SigningKey::load_directory(directory, &source.origin)?,
+            None => Vec::new(),
+        };
+
+        let signer = (!keys.is_empty()).then(|| {
+            let signer = ZoneSigner::new(keys, ONLINE_SIGNATURE_VALIDITY);
+
+            match zone.records.iter().find_map(|record| match &record.data {
+                RData::Nsec3Param(param) => Some(param),
+                _ => None,
+            }) {
+                Some(param) => signer.with_nsec3(param.iterations, param.salt.clone()),
+                None => signer,
+            }
+        });
+
+        let mut authority = Self::new(zone, signer.map(Rc::new))?;
+        authority.path = Some(source.path.clone());
+
+        if authority.signer.is_none() {
+            authority.journal = authority.journal_with(changes);
+        }
+
+        Ok(authority)
+    }
+
+    fn new(mut source: Zone, signer: Option<Rc<ZoneSigner>>) -> Result<Self, ServerError> {
+        source.normalize_ttls();
+
+        let zone = match &signer {
+            Some(signer) => signer.prepare(source.clone())?,
+            None => source.clone(),
+        };
+
+        let origin = zone.origin.trim_end_matches('.').to_lowercase();
+
+        let has_soa = zone
+            .records
+            .iter()
+            .any(|record| record.kind == DnsType::SOA && record.name.eq_ignore_ascii_case(&origin));
+
+        if !has_soa {
+            return Err(ServerError::ParseZoneFile(format!(
+                "{} has no SOA record",
+                origin
+            )));
+        }
+
+        let delegations = delegations(&zone.records, &origin);
+        let mut names: HashMap<String, Vec<Answer>> = HashMap::new();
+        let mut nsec3 = Vec::new();
+
+        for record in zone.records {
+            let name = record.name.to_lowercase();
+
+            if !is_subdomain(&name, &origin) {
+                return Err(ServerError::ParseZoneFile(format!(
+                    "{} is outside of the zone {}",
+                    record.name, origin
+                )));
+            }
+
+            // The hashed owner name is the first label
+            if record.kind == DnsType::NSEC3 {
+                let hash = name.split('.').next().unwrap_or_default().to_string();
+                nsec3.push((hash, record));
+                continue;
+            }
+
+            // Ancestors up to the apex exist too, if only as empty non-terminals
+            let mut ancestor = name.as_str();
+
+            while ancestor != origin {
+                ancestor = ancestor.split_once('.').map_or("", |(_, parent)| parent);
+                names.entry(ancestor.to_string()).or_default();
+            }
+
+            names.entry(name).or_default().push(record);
+        }
+
+        nsec3.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // The hash parameters are the same in every record of the chain
+        let nsec3_param = nsec3.first().and_then(|(_, record)| match &record.data {
+            RData::Nsec3(nsec3) => Some(Nsec3Param {
+                hash_algorithm: nsec3.hash_algorithm,
+                flags: 0,
+                iterations: nsec3.iterations,
+                salt: nsec3.salt.clone(),
+            }),
+            _ => None,
+        });
+
+        Ok(Self {
+            origin,
+            source,
+            path: None,
+            names,
+            nsec3,
+            nsec3_param,
+            delegations,
+            ptr_synthesis: false,
+            journal: Vec::new(),
+            signer,
+        })
+    }
+
+    /// A zone received with a full zone transfer, served as it is.
+    pub fn transferred(origin: &str, records: Vec<Answer>) -> Result<Self, ServerError> {
+        Self::new(
+            Zone {
+                origin: origin.to_string(),
+                records,
+            },
+            None,
+        )
+    }
+
+    /// Answers reverse lookups for the addresses of the zone's A and AAAA records, see
+    /// `reverse_lookup`.
+    pub fn with_ptr_synthesis(mut self) -> Self {
+        self.ptr_synthesis = true;
+        self
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn soa_record(&self) -> Option<&Answer> {
+        self.source.records.iter().find(|record| {
+            record.kind == DnsType::SOA && record.name.eq_ignore_ascii_case(&self.origin)
+        })
+    }
+
+    pub fn serial(&self) -> Option<Serial> {
+        soa_serial(&self.source)
+    }
+
+    /// Applies a dynamic update once its prerequisites hold, and returns the RCODE of the
+    /// response. The serial is incremented unless the update sets a newer one itself, and the
+    /// change is added to the journal next to the master file before the updated zone is
+    /// served. The master file itself is left as the operator wrote it.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc2136#section-3
+    pub fn update(&mut self, update: &Update) -> Result<ResponseCode, ServerError> {
+        // Signatures made offline cannot be made again for the changed records
+        if self.signer.is_none() && self.is_signed() {
+            return Ok(ResponseCode::Refused);
+        }
+
+        for code in [
+            check_prerequisites(&self.source, &update.prerequisites),
+            prescan(&self.origin, &update.updates),
+        ] {
+            if !matches!(code, ResponseCode::NoErrorCondition) {
+                return Ok(code);
+            }
+        }
+
+        let mut zone = self.source.clone();
+
+        if !apply(&mut zone, &update.updates) {
+            return Ok(ResponseCode::NoErrorCondition);
+        }
+
+        if soa_serial(&zone) == soa_serial(&self.source) {
+            zone.update_serial(SerialPolicy::Increment)?;
+        }
+
+        let mut updated = Self::new(zone, self.signer.clone())?;
+        updated.path = self.path.clone();
+        updated.ptr_synthesis = self.ptr_synthesis;
+
+        let change = diff(&self.source, &updated.source);
+
+        if let (Some(path), Some(change)) = (&updated.path, &change) {
+            append_journal(path, change)?;
+        }
+
+        // Online-signed zones never get a journal to answer IXFR from: their RRSIG and NSEC or
+        // NSEC3 records change with every update but are not in the changes, which are taken
+        // from the unsigned zone. Every IXFR for them falls back to the whole zone.
+        if updated.signer.is_none() {
+            updated.journal = self.journal_with(change);
+        }
+
+        *self = updated;
+
+        Ok(ResponseCode::NoErrorCondition)
+    }
+
+    /// The zone with the changes of an incremental transfer applied, oldest first. Each change
+    /// must start at the version the one before it ends at.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc1995#section-5
+    pub fn with_changes(&self, diffs: Vec<ZoneDiff>) -> Result<Self, ServerError> {
+        let mut zone = self.source.clone();
+
+        apply_changes(&mut zone, &diffs)?;
+
+        let mut updated = Self::new(zone, None)?;
+        updated.ptr_synthesis = self.ptr_synthesis;
+        updated.journal = self.journal_with(diffs);
+
+        Ok(updated)
+    }
+
+    /// Every record of the zone, in the form of an AXFR response that starts and ends with the
+    /// SOA record. Signed zones are sent with their signatures.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc5936#section-2.2
+    pub fn transfer(&self) -> Result<Vec<Answer>, ServerError> {
+        let soa: u16 = DnsType::SOA.into();
+        let rrsig: u16 = DnsType::RRSIG.into();
+        let is_signed = self.is_signed();
+
+        let apex_soa = self.signed(self.records(&self.origin, soa), is_signed)?;
+        let mut records = apex_soa.clone();
+
+        let mut names: Vec<&String> = self.names.keys().collect();
+        names.sort_by(|a, b| DomainName::canonical_cmp(a, b));
+
+        for name in names {
+            let mut kinds: Vec<u16> = self.names[name]
+                .iter()
+                .map(|record| record.kind.into())
+                .filter(|kind| *kind != rrsig && !(*kind == soa && *name == self.origin))
+                .collect();
+
+            kinds.sort();
+            kinds.dedup();
+
+            // Delegation NS records and glue are not signed (RFC 4035 section 2.2)
+            let is_authoritative = !is_occluded(&self.delegations, name);
+
+            for kind in kinds {
+                let is_delegation_ns =
+                    kind == u16::from(DnsType::NS) && self.delegations.contains(name);
+
+                records.extend(self.signed(
+                    self.records(name, kind),
+                    is_signed && is_authoritative && !is_delegation_ns,
+                )?);
+            }
+        }
+
+        for (_, record) in &self.nsec3 {
+            records.extend(self.signed(vec![record.clone()], is_signed)?);
+        }
+
+        records.extend(
+            apex_soa
+                .into_iter()
+                .filter(|record| record.kind == DnsType::SOA),
+        );
+
+        Ok(records)
+    }
+
+    /// The changes since version `serial`, in the form of an IXFR response: the SOA record,
+    /// every change, and the SOA record again. A secondary that is up to date only gets the SOA
+    /// record. None when the journal does not go back that far.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc1995#section-4
+    pub fn changes_since(&self, serial: Serial) -> Option<Vec<Answer>> {
+        let soa = self.soa_record()?;
+
+        if self.serial() == Some(serial) {
+            return Some(vec![soa.clone()]);
+        }
+
+        let start = self
+            .journal
+            .iter()
+            .position(|diff| record_serial(&diff.old_soa) == Some(serial))?;
+
+        let mut records = vec![soa.clone()];
+
+        for diff in &self.journal[start..] {
+            records.extend(diff.records().cloned());
+        }
+
+        records.push(soa.clone());
+
+        Some(records)
+    }
+
+    // The journal with more changes, without the oldest ones beyond MAX_JOURNAL_LENGTH
+    fn journal_with(&self, diffs: impl IntoIterator<Item = ZoneDiff>) -> Vec<ZoneDiff> {
+        let mut journal = self.journal.clone();
+
+        journal.extend(diffs);
+        journal.drain(..journal.len().saturating_sub(MAX_JOURNAL_LENGTH));
+
+        journal
+    }
+
+    /// Answers a question about `name`, a name in the zone. DNSSEC records are only added when
+    /// `dnssec_ok` is set and the zone is signed. `any_policy` decides what an ANY question
+    /// gets.
+    pub fn lookup(
+        &self,
+        name: &str,
+        kind: u16,
+        dnssec_ok: bool,
+        any_policy: AnyPolicy,
+    ) -> Result<Lookup, ServerError> {
+        let name = name.trim_end_matches('.').to_lowercase();
+        let dnssec = dnssec_ok && self.is_signed();
+        let mut lookup = Lookup::new();
+
+        if let Some(cut) = self.delegation_of(&name, kind) {
+            self.refer(&cut, dnssec, &mut lookup)?;
+            return Ok(lookup);
+        }
+
+        let mut owner = name;
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            match self.resolve(&owner, kind, dnssec, any_policy, &mut lookup)? {
+                // Targets outside the zone or below a delegation are left to the client
+                Some(target)
+                    if is_subdomain(&target, &self.origin)
+                        && self.delegation_of(&target, kind).is_none() =>
+                {
+                    owner = target
+                }
+                _ => break,
+            }
+        }
+
+        self.add_additionals(dnssec, &mut lookup)?;
+
+        Ok(lookup)
+    }
+
+    // Looks up one name of a CNAME chain, returning the CNAME target to follow next
+    fn resolve(
+        &self,
+        name: &str,
+        kind: u16,
+        dnssec: bool,
+        any_policy: AnyPolicy,
+        lookup: &mut Lookup,
+    ) -> Result<Option<String>, ServerError> {
+        let cname: u16 = DnsType::CNAME.into();
+
+        // A DNAME takes precedence over anything below its owner (RFC 6672 section 2.4)
+        if let Some(owner) = self.dname_owner(name) {
+            return self.redirect(name, &owner, dnssec, lookup);
+        }
+
+        if self.names.contains_key(name) {
+            let records = self.answer_records(name, kind, dnssec, any_policy)?;
+
+            if !records.is_empty() {
+                lookup.answers.extend(records);
+                return Ok(None);
+            }
+
+            let aliases = self.records(name, cname);
+
+            if let Some(target) = aliases.first().and_then(cname_target) {
+                lookup.answers.extend(self.signed(aliases, dnssec)?);
+                return Ok(Some(target));
+            }
+
+            // The name exists without the type. Empty non-terminals have no NSEC record of
+            // their own, the one covering them proves there is nothing at the name.
+            lookup.add_authorities(self.negative_soa(dnssec)?);
+
+            if dnssec {
+                let mut proof = self.matching_proof(name)?;
+
+                if proof.is_empty() {
+                    proof = self.covering_proof(name)?;
+                }
+
+                lookup.add_authorities(proof);
+            }
+
+            return Ok(None);
+        }
+
+        let encloser = self.closest_encloser(name);
+        let wildcard = wildcard_name(&encloser);
+
+        // After a CNAME the RCODE still describes the last name of the chain (RFC 6604)
+        if !self.names.contains_key(&wildcard) {
+            lookup.code = ResponseCode::NameError;
+
+            lookup.add_authorities(self.negative_soa(dnssec)?);
+
+            if dnssec {
+                lookup.add_authorities(self.non_existence_proof(name, &encloser)?);
+                lookup.add_authorities(self.covering_proof(&wildcard)?);
+            }
+
+            return Ok(None);
+        }
+
+        // The answer is synthesized from the wildcard, along with proof that the name itself
+        // does not exist (RFC 4035 section 3.1.3.3)
+        let mut records = self.answer_records(&wildcard, kind, dnssec, any_policy)?;
+        let mut target = None;
+
+        if records.is_empty() {
+            records = self.signed(self.records(&wildcard, cname), dnssec)?;
+            target = records.first().and_then(cname_target);
+        }
+
+        if records.is_empty() {
+            lookup.add_authorities(self.negative_soa(dnssec)?);
+
+            if dnssec {
+                lookup.add_authorities(self.non_existence_proof(name, &encloser)?);
+                lookup.add_authorities(self.matching_proof(&wildcard)?);
+            }
+
+            return Ok(None);
+        }
+
+        for mut record in records {
+            record.name = name.to_string();
+            lookup.answers.push(record);
+        }
+
+        if dnssec {
+            lookup.add_authorities(self.non_existence_proof(name, &encloser)?);
+        }
+
+        Ok(target)
+    }
+
+    /// PTR records from the reverse name of `addr` to every name of the zone with that address,
+    /// so hosts have reverse lookups without a reverse zone. Empty unless the zone was loaded
+    /// with PTR synthesis. Wildcards and glue below delegations name no host of this zone.
+    pub fn reverse_lookup(&self, name: &str, addr: IpAddr) -> Vec<Answer> {
+        if !self.ptr_synthesis {
+            return Vec::new();
+        }
+
+        let mut hosts: Vec<(&String, &Answer)> = self
+            .names
+            .iter()
+            .filter(|(owner, _)| !owner.starts_with("*.") && !is_occluded(&self.delegations, owner))
+            .flat_map(|(owner, records)| records.iter().map(move |record| (owner, record)))
+            .filter(|(_, record)| match (&record.data, addr) {
+                (RData::A(a), IpAddr::V4(addr)) => *a == addr,
+                (RData::Aaaa(aaaa), IpAddr::V6(addr)) => *aaaa == addr,
+                _ => false,
+            })
+            .collect();
+
+        hosts.sort_by(|a, b| DomainName::canonical_cmp(a.0, b.0));
+        hosts.dedup_by_key(|(owner, _)| *owner);
+
+        hosts
+            .into_iter()
+            .map(|(owner, record)| Answer {
+                name: name.to_string(),
+                kind: DnsType::PTR,
+                class: record.class,
+                ttl: record.ttl,
+                data: RData::DomainName(owner.clone()),
+            })
+            .collect()
+    }
+
+    // The records at a name answering a question of type `kind`, with their signatures. ANY
+    // gets every RRset, or the HINFO record of RFC 8482 section 4.2. Zones signed offline
+    // cannot sign that record, they send one of the RRsets instead (section 4.1).
+    fn answer_records(
+        &self,
+        name: &str,
+        kind: u16,
+        dnssec: bool,
+        any_policy: AnyPolicy,
+    ) -> Result<Vec<Answer>, ServerError> {
+        if kind != u16::from(QuestionType::ALL) {
+            return self.signed(self.records(name, kind), dnssec);
+        }
+
+        let mut kinds: Vec<u16> = self
+            .names
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|record| record.kind.into())
+            .filter(|kind| *kind != u16::from(DnsType::RRSIG))
+            .collect();
+
+        kinds.sort();
+        kinds.dedup();
+
+        if kinds.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        match any_policy {
+            AnyPolicy::Hinfo if self.signer.is_some() || !self.is_signed() => {
+                self.signed(vec![minimal_any(name)], dnssec)
+            }
+            AnyPolicy::Hinfo => self.signed(self.records(name, kinds[0]), dnssec),
+            AnyPolicy::All => {
+                let mut records = Vec::new();
+
+                for kind in kinds {
+                    records.extend(self.signed(self.records(name, kind), dnssec)?);
+                }
+
+                Ok(records)
+            }
+        }
+    }
+
+    // A name below a DNAME is answered with the DNAME and a CNAME synthesized from it, to the
+    // same name below the DNAME target. The CNAME is never signed, validators check it against
+    // the DNAME instead (RFC 6672 sections 3.1 and 5.3.1).
+    fn redirect(
+        &self,
+        name: &str,
+        owner: &str,
+        dnssec: bool,
+        lookup: &mut Lookup,
+    ) -> Result<Option<String>, ServerError> {
+        let dnames = self.records(owner, DnsType::DNAME.into());
+        let Some(dname) = dnames.first().cloned() else {
+            return Ok(None);
+        };
+
+        // A chain may pass through the same DNAME more than once
+        let is_present = lookup.answers.iter().any(|answer| {
+            answer.kind == DnsType::DNAME && answer.name.eq_ignore_ascii_case(&dname.name)
+        });
+
+        if !is_present {
+            lookup.answers.extend(self.signed(dnames, dnssec)?);
+        }
+
+        let target = cname_target(&dname).unwrap_or_default();
+
+        let Some(synthesized) = DomainName::substitute(name, owner, &target) else {
+            lookup.code = ResponseCode::YXDomain;
+            return Ok(None);
+        };
+
+        lookup.answers.push(Answer {
+            name: name.to_string(),
+            kind: DnsType::CNAME,
+            class: dname.class,
+            ttl: dname.ttl,
+            data: RData::DomainName(synthesized.clone()),
+        });
+
+        Ok(Some(synthesized))
+    }
+
+    // Referrals carry the NS records of the child zone, unsigned and without the AA bit, and
+    // the DS records or proof that there are none (RFC 4035 section 3.1.4)
+    fn refer(&self, cut: &str, dnssec: bool, lookup: &mut Lookup) -> Result<(), ServerError> {
+        let ns = self.records(cut, DnsType::NS.into());
+        let ds = self.records(cut, DnsType::DS.into());
+
+        lookup.authoritative = false;
+        lookup.authorities.extend(ns.iter().cloned());
+
+        if dnssec {
+            if ds.is_empty() {
+                lookup.add_authorities(self.matching_proof(cut)?);
+            } else {
+                lookup.authorities.extend(self.signed(ds, true)?);
+            }
+        }
+
+        // Glue for name servers inside the child zone
+        for record in &ns {
+            if let Some(target) = ns_target(record) {
+                if is_subdomain(&target, cut) {
+                    lookup
+                        .additionals
+                        .extend(self.records(&target, DnsType::A.into()));
+                    lookup
+                        .additionals
+                        .extend(self.records(&target, DnsType::AAAA.into()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Addresses of the zone's own names that answers point to, such as MX exchanges
+    fn add_additionals(&self, dnssec: bool, lookup: &mut Lookup) -> Result<(), ServerError> {
+        let mut targets: Vec<String> = lookup
+            .answers
+            .iter()
+            .filter_map(|answer| answer.data.target_name().map(str::to_string))
+            .chain(lookup.answers.iter().filter_map(ns_target))
+            .map(|target| target.to_lowercase())
+            .collect();
+
+        targets.sort();
+        targets.dedup();
+
+        for target in targets {
+            if !is_subdomain(&target, &self.origin) || is_occluded(&self.delegations, &target) {
+                continue;
+            }
+
+            for kind in [DnsType::A, DnsType::AAAA] {
+                let records = self.records(&target, kind.into());
+
+                if !records.is_empty() {
+                    lookup.additionals.extend(self.signed(records, dnssec)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The closest delegation at or above `name`. A DS question at the delegation itself is
+    // answered by the parent side.
+    fn delegation_of(&self, name: &str, kind: u16) -> Option<String> {
+        let ds: u16 = DnsType::DS.into();
+        let labels: Vec<&str> = name.split('.').collect();
+        let origin_labels = self
+            .origin
+            .split('.')
+            .filter(|label| !label.is_empty())
+            .count();
+
+        (origin_labels + 1..=labels.len())
+            .map(|depth| labels[labels.len() - depth..].join("."))
+            .find(|ancestor| {
+                self.delegations.contains(ancestor) && !(*ancestor == name && kind == ds)
+            })
+    }
+
+    // The closest strict ancestor of a name, up to the apex, that owns a DNAME
+    fn dname_owner(&self, name: &str) -> Option<String> {
+        let dname: u16 = DnsType::DNAME.into();
+        let mut ancestor = name;
+
+        while ancestor != self.origin {
+            ancestor = ancestor.split_once('.')?.1;
+
+            if !self.records(ancestor, dname).is_empty() {
+                return Some(ancestor.to_string());
+            }
+        }
+
+        None
+    }
+
+    // The closest ancestor of a name that does not exist
+    fn closest_encloser(&self, name: &str) -> String {
+        let mut ancestor = name;
+
+        while ancestor != self.origin && !self.names.contains_key(ancestor) {
+            ancestor = ancestor.split_once('.').map_or("", |(_, parent)| parent);
+        }
+
+        ancestor.to_string()
+    }
+
+    fn records(&self, name: &str, kind: u16) -> Vec<Answer> {
+        self.names
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|record| u16::from(record.kind) == kind)
+            .cloned()
+            .collect()
+    }
+
+    fn is_signed(&self) -> bool {
+        self.signer.is_some()
+            || !self
+                .records(&self.origin, DnsType::DNSKEY.into())
+                .is_empty()
+    }
+
+    // The records of one RRset, followed by their signatures when DNSSEC records are wanted
+    fn signed(&self, records: Vec<Answer>, dnssec: bool) -> Result<Vec<Answer>, ServerError> {
+        if !dnssec || records.is_empty() {
+            return Ok(records);
+        }
+
+        let signatures = match &self.signer {
+            Some(signer) => {
+                let rrset: Vec<&Answer> = records.iter().collect();
+                signer.sign_rrset(&rrset, &self.origin)?
+            }
+            None => {
+                let owner = records[0].name.to_lowercase();
+                let kind: u16 = records[0].kind.into();
+
+                self.records(&owner, DnsType::RRSIG.into())
+                    .into_iter()
+                    .filter(|record| {
+                        matches!(&record.data, RData::Rrsig(rrsig) if rrsig.type_covered == kind)
+                    })
+                    .collect()
+            }
+        };
+
+        Ok(records.into_iter().chain(signatures).collect())
+    }
+
+    // Negative answers carry the SOA record with the smaller of its TTL and minimum field
+    // (RFC 2308 section 3)
+    fn negative_soa(&self, dnssec: bool) -> Result<Vec<Answer>, ServerError> {
+        let mut records = self.records(&self.origin, DnsType::SOA.into());
+
+        for record in &mut records {
+            if let RData::Soa(soa) = &record.data {
+                record.ttl = record.ttl.min(soa.minimum);
+            }
+        }
+
+        self.signed(records, dnssec)
+    }
+
+    // NSEC or NSEC3 record of a name that exists, showing the types it has
+    fn matching_proof(&self, name: &str) -> Result<Vec<Answer>, ServerError> {
+        match &self.nsec3_param {
+            Some(param) => {
+                let hash = nsec3_hash(name, param);
+
+                let records = self
+                    .nsec3
+                    .iter()
+                    .filter(|(owner_hash, _)| *owner_hash == hash)
+                    .map(|(_, record)| record.clone())
+                    .collect();
+
+                self.signed(records, true)
+            }
+            None => self.signed(self.records(name, DnsType::NSEC.into()), true),
+        }
+    }
+
+    // Proof that `name` does not exist: the NSEC record covering it, or the NSEC3 closest
+    // encloser proof (RFC 5155 section 7.2.1)
+    fn non_existence_proof(&self, name: &str, encloser: &str) -> Result<Vec<Answer>, ServerError> {
+        if self.nsec3_param.is_none() {
+            return self.covering_proof(name);
+        }
+
+        let encloser_labels = encloser
+            .split('.')
+            .filter(|label| !label.is_empty())
+            .count();
+        let labels: Vec<&str> = name.split('.').collect();
+        let next_closer = labels[labels.len() - encloser_labels - 1..].join(".");
+
+        let mut records = self.matching_proof(encloser)?;
+        records.extend(self.covering_proof(&next_closer)?);
+
+        Ok(records)
+    }
+
+    // NSEC or NSEC3 record whose span covers a name that does not exist
+    fn covering_proof(&self, name: &str) -> Result<Vec<Answer>, ServerError> {
+        let record = match &self.nsec3_param {
+            Some(param) => {
+                let hash = nsec3_hash(name, param);
+
+                // The last record covers the hashes before the first one too
+                self.nsec3
+                    .iter()
+                    .rev()
+                    .find(|(owner_hash, _)| *owner_hash < hash)
+                    .or(self.nsec3.last())
+                    .map(|(_, record)| record.clone())
+            }
+            None => {
+                let mut nsec: Vec<&Answer> = self
+                    .names
+                    .values()
+                    .flatten()
+                    .filter(|record| record.kind == DnsType::NSEC)
+                    .collect();
+
+                nsec.sort_by(|a, b| DomainName::canonical_cmp(&a.name, &b.name));
+
+                nsec.iter()
+                    .rev()
+                    .find(|record| {
+                        DomainName::canonical_cmp(&record.name.to_lowercase(), name).is_lt()
+                    })
+                    .or(nsec.last())
+                    .map(|record| (*record).clone())
+            }
+        };
+
+        self.signed(record.into_iter().collect(), true)
+    }
+}
+
+// The file next to a master file that holds the changes made to the zone by dynamic updates, in
+// IXFR order and master file format. The changes are applied again whenever the zone is loaded.
+fn journal_path(path: &str) -> String {
+    format!("{}.jnl", path)
+}
+
+// The changes in the journal of a master file. A journal that does not start at the serial of
+// the master file is from before the file was edited, and is removed.
+fn read_journal(path: &str, zone: &Zone) -> Result<Vec<ZoneDiff>, ServerError> {
+    let journal_path = journal_path(path);
+    let error =
+        |message: String| ServerError::ParseZoneFile(format!("{}: {}", journal_path, message));
+
+    let input = match fs::read_to_string(&journal_path) {
+        Ok(input) => input,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(error(err.to_string())),
+    };
+
+    let records = ZoneFileParser::new(&input, &zone.origin)
+        .parse()
+        .map_err(|err| match err {
+            ServerError::ParseZoneFile(message) => error(message),
+            err => err,
+        })?
+        .records;
+    let diffs = ZoneDiff::read_all(&records)
+        .ok_or_else(|| error("ends in the middle of a change".to_string()))?;
+
+    match diffs.first() {
+        Some(diff) if record_serial(&diff.old_soa) != soa_serial(zone) => {
+            eprintln!(
+                "Removing {}, it does not start at the serial of {}",
+                journal_path, path
+            );
+
+            fs::remove_file(&journal_path).map_err(|err| error(err.to_string()))?;
+
+            Ok(Vec::new())
+        }
+        _ => Ok(diffs),
+    }
+}
+
+fn append_journal(path: &str, diff: &ZoneDiff) -> Result<(), ServerError> {
+    let journal_path = journal_path(path);
+    let records = Zone {
+        origin: String::new(),
+        records: diff.records().cloned().collect(),
+    };
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&journal_path)
+        .and_then(|mut file| file.write_all(records.to_string().as_bytes()))
+        .map_err(|err| ServerError::UpdateZone(format!("{}: {}", journal_path, err)))
+}
+
+// Applies changes to a zone, oldest first. Each change must start at the serial the zone has by
+// then.
+fn apply_changes(zone: &mut Zone, diffs: &[ZoneDiff]) -> Result<(), ServerError> {
+    for diff in diffs {
+        if record_serial(&diff.old_soa) != soa_serial(zone) {
+            return Err(ServerError::UpdateZone(format!(
+                "change of {} does not start at its serial",
+                zone.origin
+            )));
+        }
+
+        let mut deleted: HashMap<(String, u16), Vec<&RData>> = HashMap::new();
+
+        for record in &diff.deleted {
+            deleted
+                .entry((record.name.to_lowercase(), record.kind.into()))
+                .or_default()
+                .push(&record.data);
+        }
+
+        zone.records.retain(|record| {
+            let is_apex_soa =
+                record.kind == DnsType::SOA && record.name.eq_ignore_ascii_case(&zone.origin);
+            let is_deleted = deleted
+                .get(&(record.name.to_lowercase(), record.kind.into()))
+                .is_some_and(|data| data.contains(&&record.data));
+
+            !is_apex_soa && !is_deleted
+        });
+
+        zone.records.push(diff.new_soa.clone());
+        zone.records.extend(diff.added.iter().cloned());
+    }
+
+    Ok(())
+}
+
+fn soa_serial(zone: &Zone) -> Option<Serial> {
+    zone.records.iter().find_map(|record| match &record.data {
+        RData::Soa(soa) if record.name.eq_ignore_ascii_case(&zone.origin) => Some(soa.serial),
+        _ => None,
+    })
+}
+
+fn record_serial(record: &Answer) -> Option<Serial> {
+    match &record.data {
+        RData::Soa(soa) => Some(soa.serial),
+        _ => None,
+    }
+}
+
+// The deleted or added records of a change, up to the next SOA record
+fn read_section<'a>(records: &mut Peekable<impl Iterator<Item = &'a Answer>>) -> Vec<Answer> {
+    let mut section = Vec::new();
+
+    while let Some(record) = records.next_if(|record| record.kind != DnsType::SOA) {
+        section.push(record.clone());
+    }
+
+    section
+}
+
+// The TTL and data of every record, by lowercase owner name and type
+type Rrsets<'z> = HashMap<(String, u16), Vec<(u32, &'z RData)>>;
+
+fn rrsets(zone: &Zone) -> Rrsets<'_> {
+    let mut rrsets: Rrsets = HashMap::new();
+
+    for record in &zone.records {
+        rrsets
+            .entry((record.name.to_lowercase(), record.kind.into()))
+            .or_default()
+            .push((record.ttl, &record.data));
+    }
+
+    rrsets
+}
+
+// The records `new` has that `old` has not and the other way round, apart from the SOA record.
+// A record whose TTL changed is deleted and added again.
+fn diff(old: &Zone, new: &Zone) -> Option<ZoneDiff> {
+    let apex_soa = |zone: &Zone| {
+        zone.records
+            .iter()
+            .find(|record| {
+                record.kind == DnsType::SOA && record.name.eq_ignore_ascii_case(&zone.origin)
+            })
+            .cloned()
+    };
+
+    let (old_rrsets, new_rrsets) = (rrsets(old), rrsets(new));
+
+    let missing = |zone: &Zone, other: &Rrsets| {
+        zone.records
+            .iter()
+            .filter(|record| {
+                record.kind != DnsType::SOA
+                    && !other
+                        .get(&(record.name.to_lowercase(), record.kind.into()))
+                        .is_some_and(|rrset| rrset.contains(&(record.ttl, &record.data)))
+            })
+            .cloned()
+            .collect()
+    };
+
+    Some(ZoneDiff {
+        old_soa: apex_soa(old)?,
+        deleted: missing(old, &new_rrsets),
+        new_soa: apex_soa(new)?,
+        added: missing(new, &old_rrsets),
+    })
+}
+
+/// The HINFO record answering an ANY question for `name` in place of its RRsets.
+///
+/// https://www.rfc-editor.org/rfc/rfc8482#section-4.2
+pub fn minimal_any(name: &str) -> Answer {
+    Answer {
+        name: name.to_string(),
+        kind: DnsType::HINFO,
+        class: DnsClass::IN,
+        ttl: MINIMAL_ANY_TTL,
+        data: RData::Strings(vec![b"RFC8482".to_vec(), Vec::new()]),
+    }
+}
+
+/// The address a reverse name stands for, e.g. 192.0.2.1 for 1.2.0.192.in-addr.arpa and
+/// the nibbles of an IPv6 address, last first, below ip6.arpa (RFC 3596 section 2.5).
+pub fn reverse_address(name: &str) -> Option<IpAddr> {
+    let name = name.trim_end_matches('.').to_lowercase();
+
+    if let Some(labels) = name.strip_suffix(".in-addr.arpa") {
+        let mut octets: Vec<&str> = labels.split('.').collect();
+        octets.reverse();
+
+        return octets.join(".").parse::<Ipv4Addr>().ok().map(IpAddr::V4);
+    }
+
+    let nibbles: Vec<&str> = name.strip_suffix(".ip6.arpa")?.split('.').collect();
+
+    if nibbles.len() != 32 {
+        return None;
+    }
+
+    let mut addr = 0u128;
+
+    for nibble in nibbles.iter().rev() {
+        let [digit] = nibble.as_bytes() else {
+            return None;
+        };
+
+        addr = (addr << 4) | (*digit as char).to_digit(16)? as u128;
+    }
+
+    Some(IpAddr::V6(Ipv6Addr::from(addr)))
+}
+
+fn cname_target(record: &Answer) -> Option<String> {
+    match &record.data {
+        RData::DomainName(target) => Some(target.trim_end_matches('.').to_lowercase()),
+        _ => None,
+    }
+}
+
+fn ns_target(record: &Answer) -> Option<String> {
+    match (&record.kind, &record.data) {
+        (DnsType::NS, RData::DomainName(target)) => {
+            Some(target.trim_end_matches('.').to_lowercase())
+        }
+        _ => None,
+    }
+}
+
+fn wildcard_name(encloser: &str) -> String {
+    match encloser {
+        "" => "*".to_string(),
+        encloser => format!("*.{}", encloser),
+    }
+}
+
+fn nsec3_hash(name: &str, param: &Nsec3Param) -> String {
+    base32hex(&Nsec3::hash_name(name, param.iterations, &param.salt))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::message::{
+        question::{Question, QuestionClass},
+        update::{UpdateClass, UpdateRecord},
+    };
+
+    fn zone() -> AuthoritativeZone {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     ns1 3600 IN A 192.0.2.1\n\
+                     www 300 IN A 192.0.2.2\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+
+        AuthoritativeZone::new(zone, None).unwrap()
+    }
+
+    // An update replacing the address of www.example.com
+    fn update(addr: Ipv4Addr) -> Update {
+        let a = |class, ttl, data| UpdateRecord {
+            name: "www.example.com".to_string(),
+            kind: QuestionType::DnsType(DnsType::A),
+            class,
+            ttl,
+            data,
+        };
+
+        Update {
+            zone: Question {
+                name: "example.com".to_string(),
+                kind: QuestionType::DnsType(DnsType::SOA),
+                class: QuestionClass::DnsClass(DnsClass::IN),
+            },
+            prerequisites: Vec::new(),
+            updates: vec![
+                a(UpdateClass::ALL, 0, None),
+                a(
+                    UpdateClass::DnsClass(DnsClass::IN),
+                    300,
+                    Some(RData::A(addr)),
+                ),
+            ],
+        }
+    }
+
+    fn serials(records: &[Answer]) -> Vec<Option<u32>> {
+        records
+            .iter()
+            .map(|record| record_serial(record).map(|serial| serial.0))
+            .collect()
+    }
+
+    #[test]
+    fn transfer_starts_and_ends_with_the_soa_record() {
+        let records = zone().transfer().unwrap();
+
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0].kind, DnsType::SOA);
+        assert_eq!(records[4].kind, DnsType::SOA);
+        assert!(records[1..4]
+            .iter()
+            .all(|record| record.kind != DnsType::SOA));
+    }
+
+    #[test]
+    fn updates_are_sent_as_changes() {
+        let mut zone = zone();
+
+        zone.update(&update(Ipv4Addr::new(192, 0, 2, 3))).unwrap();
+        zone.update(&update(Ipv4Addr::new(192, 0, 2, 4))).unwrap();
+
+        // Up to date
+        assert_eq!(serials(&zone.changes_since(Serial(3)).unwrap()), [Some(3)]);
+
+        // Two changes, each deleting the old address and adding the new one
+        let records = zone.changes_since(Serial(1)).unwrap();
+
+        assert_eq!(
+            serials(&records),
+            [
+                Some(3),
+                Some(1),
+                None,
+                Some(2),
+                None,
+                Some(2),
+                None,
+                Some(3),
+                None,
+                Some(3)
+            ]
+        );
+        assert!(matches!(records[2].data, RData::A(addr) if addr == Ipv4Addr::new(192, 0, 2, 2)));
+        assert!(matches!(records[8].data, RData::A(addr) if addr == Ipv4Addr::new(192, 0, 2, 4)));
+
+        // Not a version the zone had
+        assert!(zone.changes_since(Serial(7)).is_none());
+    }
+
+    #[test]
+    fn journal_keeps_the_latest_changes() {
+        let mut zone = zone();
+
+        for host in 0..=MAX_JOURNAL_LENGTH as u8 {
+            zone.update(&update(Ipv4Addr::new(198, 51, 100, host)))
+                .unwrap();
+        }
+
+        // Serial 1 was the version before the first of 65 changes, only the last 64 are kept
+        assert_eq!(zone.serial(), Some(Serial(66)));
+        assert_eq!(zone.journal.len(), MAX_JOURNAL_LENGTH);
+        assert!(zone.changes_since(Serial(1)).is_none());
+        assert!(zone.changes_since(Serial(2)).is_some());
+    }
+
+    #[test]
+    fn incremental_changes_apply_in_serial_order() {
+        let mut primary = zone();
+
+        primary
+            .update(&update(Ipv4Addr::new(192, 0, 2, 3)))
+            .unwrap();
+        primary
+            .update(&update(Ipv4Addr::new(192, 0, 2, 4)))
+            .unwrap();
+
+        let secondary = zone().with_changes(primary.journal.clone()).unwrap();
+
+        assert_eq!(secondary.serial(), Some(Serial(3)));
+        assert_eq!(
+            secondary
+                .changes_since(Serial(1))
+                .map(|records| records.len()),
+            Some(10)
+        );
+        assert!(secondary.names["www.example.com"].iter().all(
+            |record| matches!(record.data, RData::A(addr) if addr == Ipv4Addr::new(192, 0, 2, 4))
+        ));
+
+        // The second change does not start at the zone's serial
+        let broken = primary.journal[1..].to_vec();
+
+        assert!(zone().with_changes(broken).is_err());
+    }
+
+    #[test]
+    fn updates_are_kept_in_a_journal_next_to_the_master_file() {
+        let path = std::env::temp_dir()
+            .join(format!("journal-{}.zone", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let master_file = "$TTL 3600\n\
+                           ; Written by the operator\n\
+                           @ IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                           @ IN NS ns1\n\
+                           ns1 IN A 192.0.2.1\n\
+                           www 300 IN A 192.0.2.2\n";
+        let source = ZoneSource {
+            origin: "example.com".to_string(),
+            path: path.clone(),
+        };
+        let www = |zone: &AuthoritativeZone| {
+            zone.records("www.example.com", DnsType::A.into())
+                .iter()
+                .map(|record| record.data.clone())
+                .collect::<Vec<_>>()
+        };
+
+        fs::write(&path, master_file).unwrap();
+
+        let mut zone = AuthoritativeZone::load(&source, None).unwrap();
+
+        zone.update(&update(Ipv4Addr::new(192, 0, 2, 3))).unwrap();
+        zone.update(&update(Ipv4Addr::new(192, 0, 2, 4))).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), master_file);
+
+        // Loading the zone again applies the changes, which are there for IXFR too
+        let reloaded = AuthoritativeZone::load(&source, None).unwrap();
+
+        assert_eq!(reloaded.serial(), Some(Serial(3)));
+        assert_eq!(www(&reloaded), [RData::A(Ipv4Addr::new(192, 0, 2, 4))]);
+        assert!(reloaded.changes_since(Serial(1)).is_some());
+
+        // Once the operator edits the master file, the journal no longer applies
+        fs::write(&path, master_file.replace(" 1 7200", " 4 7200")).unwrap();
+
+        let edited = AuthoritativeZone::load(&source, None).unwrap();
+
+        assert_eq!(edited.serial(), Some(Serial(4)));
+        assert_eq!(www(&edited), [RData::A(Ipv4Addr::new(192, 0, 2, 2))]);
+        assert!(!Path::new(&journal_path(&path)).exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn srv_targets_in_the_zone_get_their_addresses() {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     _sip._tcp 300 IN SRV 10 60 5060 sip\n\
+                     _sip._tcp 300 IN SRV 20 0 5060 sip.example.net.\n\
+                     sip 300 IN A 192.0.2.5\n\
+                     sip 300 IN AAAA 2001:db8::5\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+        let zone = AuthoritativeZone::new(zone, None).unwrap();
+
+        let lookup = zone
+            .lookup(
+                "_sip._tcp.example.com",
+                DnsType::SRV.into(),
+                false,
+                AnyPolicy::All,
+            )
+            .unwrap();
+
+        assert_eq!(lookup.answers.len(), 2);
+        // Only the target inside the zone has addresses to add
+        assert_eq!(
+            lookup
+                .additionals
+                .iter()
+                .map(|record| (record.name.as_str(), record.kind))
+                .collect::<Vec<_>>(),
+            [
+                ("sip.example.com", DnsType::A),
+                ("sip.example.com", DnsType::AAAA)
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse_names_map_to_addresses() {
+        assert_eq!(
+            reverse_address("2.2.0.192.IN-ADDR.ARPA."),
+            Some(IpAddr::from([192, 0, 2, 2]))
+        );
+        assert_eq!(
+            reverse_address(
+                "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+            ),
+            Some(IpAddr::from_str("2001:db8::1").unwrap())
+        );
+
+        for name in [
+            "2.0.192.in-addr.arpa",
+            "256.2.0.192.in-addr.arpa",
+            "0.0.8.b.d.0.1.0.0.2.ip6.arpa",
+            "www.example.com",
+        ] {
+            assert_eq!(reverse_address(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn ptr_records_name_every_host_with_the_address() {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     www 300 IN A 192.0.2.2\n\
+                     mail 600 IN A 192.0.2.2\n\
+                     * 300 IN A 192.0.2.9\n\
+                     sub 3600 IN NS ns.sub\n\
+                     ns.sub 3600 IN A 192.0.2.2\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+        let name = "2.2.0.192.in-addr.arpa";
+        let addr = IpAddr::from([192, 0, 2, 2]);
+
+        // Synthesis is opt-in
+        let zone = AuthoritativeZone::new(zone, None).unwrap();
+        assert!(zone.reverse_lookup(name, addr).is_empty());
+
+        let zone = zone.with_ptr_synthesis();
+        let ptr = zone.reverse_lookup(name, addr);
+
+        // Glue below the delegation is not a host of this zone
+        assert_eq!(
+            ptr.iter()
+                .map(|record| (record.ttl, &record.data))
+                .collect::<Vec<_>>(),
+            [
+                (600, &RData::DomainName("mail.example.com".to_string())),
+                (300, &RData::DomainName("www.example.com".to_string())),
+            ]
+        );
+        assert!(ptr
+            .iter()
+            .all(|record| record.name == name && record.kind == DnsType::PTR));
+
+        // Nor is the wildcard
+        assert!(zone
+            .reverse_lookup("9.2.0.192.in-addr.arpa", IpAddr::from([192, 0, 2, 9]))
+            .is_empty());
+    }
+
+    #[test]
+    fn names_below_a_dname_are_redirected() {
+        let long_target = format!(
+            "{}.{}.{}.example.net.",
+            "a".repeat(63),
+            "b".repeat(63),
+            "c".repeat(60)
+        );
+        let input = format!(
+            "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+             @ 3600 IN NS ns1\n\
+             old 300 IN DNAME new\n\
+             old 300 IN A 192.0.2.1\n\
+             www.new 300 IN A 192.0.2.3\n\
+             long 300 IN DNAME {}\n",
+            long_target
+        );
+        let zone = ZoneFileParser::new(&input, "example.com").parse().unwrap();
+        let zone = AuthoritativeZone::new(zone, None).unwrap();
+        let lookup = |name: &str| {
+            zone.lookup(name, DnsType::A.into(), false, AnyPolicy::All)
+                .unwrap()
+        };
+
+        // The synthesized CNAME is followed inside the zone
+        let redirected = lookup("www.old.example.com");
+
+        assert!(matches!(redirected.code, ResponseCode::NoErrorCondition));
+        assert_eq!(
+            redirected
+                .answers
+                .iter()
+                .map(|record| (record.name.as_str(), record.kind, &record.data))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "old.example.com",
+                    DnsType::DNAME,
+                    &RData::DomainName("new.example.com".to_string())
+                ),
+                (
+                    "www.old.example.com",
+                    DnsType::CNAME,
+                    &RData::DomainName("www.new.example.com".to_string())
+                ),
+                (
+                    "www.new.example.com",
+                    DnsType::A,
+                    &RData::A(Ipv4Addr::new(192, 0, 2, 3))
+                ),
+            ]
+        );
+
+        // The owner itself is not redirected
+        assert!(matches!(
+            lookup("old.example.com").answers[..],
+            [Answer {
+                kind: DnsType::A,
+                ..
+            }]
+        ));
+
+        // A target name too long to exist (RFC 6672 section 2.2)
+        let too_long = format!("{}.long.example.com", "d".repeat(63));
+
+        assert!(matches!(lookup(&too_long).code, ResponseCode::YXDomain));
+    }
+}
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::message::header::ResponseCode;
+
 #[derive(Debug, Error)]
 pub enum ServerError {
     #[error("DecodeHeader Error: {0}")]
@@ -8,10 +10,94 @@ pub enum ServerError {
     DecodeQuestion(String),
     #[error("DecodeAnswer Error: {0}")]
     DecodeAnswer(String),
+    #[error("DecodeEdns Error: {0}")]
+    DecodeEdns(String),
+    #[error("DecodeTsig Error: {0}")]
+    DecodeTsig(String),
+    #[error("DecodeUpdate Error: {0}")]
+    DecodeUpdate(String),
     #[error("InvalidDnsType Error: {0}")]
     InvalidDnsType(String),
     #[error("InvalidDnsClass Error: {0}")]
     InvalidDnsClass(String),
     #[error("ForwardedServer Error: {0}")]
     ForwardedServer(String),
+    #[error("DecodeUpstream Error: {0}")]
+    DecodeUpstream(String),
+    #[error("ResolverTimeout Error: {0}")]
+    ResolverTimeout(String),
+    #[error("InvalidTsigKey Error: {0}")]
+    InvalidTsigKey(String),
+    #[error("InvalidTrustAnchor Error: {0}")]
+    InvalidTrustAnchor(String),
+    #[error("ParseZoneFile Error: {0}")]
+    ParseZoneFile(String),
+    #[error("InvalidSigningKey Error: {0}")]
+    InvalidSigningKey(String),
+    #[error("InvalidSerialPolicy Error: {0}")]
+    InvalidSerialPolicy(String),
+    #[error("InvalidAnyPolicy Error: {0}")]
+    InvalidAnyPolicy(String),
+    #[error("InvalidQuestionPolicy Error: {0}")]
+    InvalidQuestionPolicy(String),
+    #[error("InvalidAddressPrefix Error: {0}")]
+    InvalidAddressPrefix(String),
+    #[error("InvalidListener Error: {0}")]
+    InvalidListener(String),
+    #[error("InvalidSecondary Error: {0}")]
+    InvalidSecondary(String),
+    #[error("ZoneTransfer Error: {0}")]
+    ZoneTransfer(String),
+    #[error("InvalidNotifyTarget Error: {0}")]
+    InvalidNotifyTarget(String),
+    #[error("Notify Error: {0}")]
+    Notify(String),
+    #[error("UpdateZone Error: {0}")]
+    UpdateZone(String),
+    #[error("SignZone Error: {0}")]
+    SignZone(String),
+    #[error("InvalidArgument Error: {0}")]
+    InvalidArgument(String),
+    #[error("BindSocket Error: {0}")]
+    BindSocket(String),
+}
+
+impl ServerError {
+    /// RCODE of the response to a request that failed with this error. Requests that do not
+    /// decode are the client's fault, the other errors the server's, including responses from
+    /// upstream servers that do not decode.
+    pub fn response_code(&self) -> ResponseCode {
+        match self {
+            ServerError::DecodeHeader(_)
+            | ServerError::DecodeQuestion(_)
+            | ServerError::DecodeAnswer(_)
+            | ServerError::DecodeEdns(_)
+            | ServerError::DecodeTsig(_)
+            | ServerError::DecodeUpdate(_) => ResponseCode::FormatError,
+            // Meta types and classes this server knows nothing about, e.g. in the question
+            ServerError::InvalidDnsType(_) | ServerError::InvalidDnsClass(_) => {
+                ResponseCode::NotImplemented
+            }
+            ServerError::ForwardedServer(_)
+            | ServerError::DecodeUpstream(_)
+            | ServerError::ResolverTimeout(_)
+            | ServerError::InvalidTsigKey(_)
+            | ServerError::InvalidTrustAnchor(_)
+            | ServerError::ParseZoneFile(_)
+            | ServerError::InvalidSigningKey(_)
+            | ServerError::InvalidSerialPolicy(_)
+            | ServerError::InvalidAnyPolicy(_)
+            | ServerError::InvalidQuestionPolicy(_)
+            | ServerError::InvalidAddressPrefix(_)
+            | ServerError::InvalidListener(_)
+            | ServerError::InvalidSecondary(_)
+            | ServerError::ZoneTransfer(_)
+            | ServerError::InvalidNotifyTarget(_)
+            | ServerError::Notify(_)
+            | ServerError::UpdateZone(_)
+            | ServerError::SignZone(_)
+            | ServerError::InvalidArgument(_)
+            | ServerError::BindSocket(_) => ResponseCode::ServerFailure,
+        }
+    }
 }
@@ -0,0 +1,389 @@
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::error::ServerError;
+use crate::message::edns::EDNS_UDP_PAYLOAD_SIZE;
+use crate::update::AddressPrefix;
+
+/// How long a TCP connection may stay idle before it is closed (RFC 7766 section 6.2.3).
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TCP connections a listener keeps open at once, each of them takes a thread. Connections
+/// beyond it are closed right away.
+const MAX_TCP_CONNECTIONS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Udp,
+    /// Messages are prefixed with their length (RFC 1035 section 4.2.2).
+    Tcp,
+}
+
+/// An address the server answers on, in the
+/// `<udp|tcp>:<address>:<port>[,allow=<prefix>]...[,zone=<origin>]...` form, e.g.
+/// `tcp:[::1]:2053,allow=::1,zone=example.com`.
+#[derive(Debug)]
+pub struct Listener {
+    pub transport: Transport,
+    pub addr: SocketAddr,
+    /// Clients allowed to send requests, everyone when empty. Others are refused.
+    pub allow: Vec<AddressPrefix>,
+    /// The local zones visible on this listener, all of them when empty. Questions about the
+    /// others are treated like names outside the local zones.
+    pub zones: Vec<String>,
+}
+
+impl Listener {
+    /// IPv4 clients of a dual-stack IPv6 socket are matched by their IPv4 address.
+    pub fn allows(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+
+        self.allow.is_empty() || self.allow.iter().any(|prefix| prefix.contains(addr))
+    }
+
+    pub fn serves(&self, origin: &str) -> bool {
+        self.zones.is_empty() || self.zones.iter().any(|zone| zone == origin)
+    }
+
+    pub fn bind(self) -> Result<Socket, ServerError> {
+        let bound = match self.transport {
+            Transport::Udp => UdpSocket::bind(self.addr).map(BoundSocket::Udp),
+            Transport::Tcp => TcpListener::bind(self.addr).map(BoundSocket::Tcp),
+        };
+
+        match bound {
+            Ok(bound) => Ok(Socket {
+                listener: Arc::new(self),
+                bound,
+            }),
+            Err(err) => Err(ServerError::BindSocket(format!("{}: {}", self.addr, err))),
+        }
+    }
+}
+
+impl FromStr for Listener {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| ServerError::InvalidListener(format!("{}: {}", value, reason));
+
+        let mut settings = value.split(',');
+        let (transport, addr) = settings
+            .next()
+            .and_then(|addr| addr.split_once(':'))
+            .ok_or_else(|| invalid("not in the <udp|tcp>:<address>:<port> form"))?;
+
+        let transport = match transport.to_lowercase().as_str() {
+            "udp" => Transport::Udp,
+            "tcp" => Transport::Tcp,
+            _ => return Err(invalid("transport is not one of udp or tcp")),
+        };
+        let addr = SocketAddr::from_str(addr).map_err(|err| invalid(&err.to_string()))?;
+
+        let mut listener = Self {
+            transport,
+            addr,
+            allow: Vec::new(),
+            zones: Vec::new(),
+        };
+
+        for setting in settings {
+            match setting.split_once('=') {
+                Some(("allow", prefix)) => listener.allow.push(AddressPrefix::from_str(prefix)?),
+                Some(("zone", origin)) => listener
+                    .zones
+                    .push(origin.trim_end_matches('.').to_lowercase()),
+                _ => return Err(invalid(&format!("unknown setting {}", setting))),
+            }
+        }
+
+        Ok(listener)
+    }
+}
+
+/// A request received by a listener, answered over the connection it came from.
+pub struct Request {
+    pub listener: Arc<Listener>,
+    pub message: Vec<u8>,
+    pub source: SocketAddr,
+    connection: Connection,
+}
+
+enum Connection {
+    Udp(Arc<UdpSocket>),
+    Tcp(Arc<TcpStream>),
+}
+
+impl Request {
+    // A client that gets no response retries until it gives up, so failing to send one is only
+    // logged
+    pub fn reply(&self, response: &[u8]) {
+        let result = match &self.connection {
+            Connection::Udp(socket) => socket.send_to(response, self.source).map(|_| ()),
+            Connection::Tcp(stream) => {
+                let mut message = Vec::with_capacity(2 + response.len());
+
+                message.extend_from_slice(&(response.len() as u16).to_be_bytes());
+                message.extend_from_slice(response);
+
+                stream.as_ref().write_all(&message)
+            }
+        };
+
+        if let Err(err) = result {
+            eprintln!("Failed to send response to {}: {}", self.source, err);
+        }
+    }
+}
+
+#[cfg(test)]
+impl Request {
+    /// A request from `source` as if `listener` had received it over UDP. The response is sent
+    /// from a socket of its own.
+    pub fn received(listener: Listener, message: &[u8], source: SocketAddr) -> Self {
+        Self {
+            listener: Arc::new(listener),
+            message: message.to_vec(),
+            source,
+            connection: Connection::Udp(Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap())),
+        }
+    }
+}
+
+/// A listener with its bound socket.
+pub struct Socket {
+    listener: Arc<Listener>,
+    bound: BoundSocket,
+}
+
+enum BoundSocket {
+    Udp(UdpSocket),
+    Tcp(TcpListener),
+}
+
+impl Socket {
+    /// Receives requests on a thread of their own, and on one per TCP connection. Errors only
+    /// affect the datagram or connection they happen on, so they are logged and the listener
+    /// keeps receiving.
+    pub fn receive(self, requests: Sender<Request>) {
+        let listener = self.listener;
+
+        match self.bound {
+            BoundSocket::Udp(socket) => {
+                thread::spawn(move || receive_datagrams(listener, Arc::new(socket), requests));
+            }
+            BoundSocket::Tcp(socket) => {
+                thread::spawn(move || accept_connections(listener, socket, requests));
+            }
+        }
+    }
+}
+
+// Only this thread opens connections, so the count cannot go over the limit between checking
+// and incrementing it
+fn accept_connections(listener: Arc<Listener>, socket: TcpListener, requests: Sender<Request>) {
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for stream in socket.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Error accepting connection on {}: {}", listener.addr, err);
+                continue;
+            }
+        };
+
+        if connections.load(Ordering::Relaxed) >= MAX_TCP_CONNECTIONS {
+            eprintln!(
+                "Closed connection on {}: {} connections are open already",
+                listener.addr, MAX_TCP_CONNECTIONS
+            );
+            continue;
+        }
+
+        connections.fetch_add(1, Ordering::Relaxed);
+
+        let listener = listener.clone();
+        let requests = requests.clone();
+        let connections = connections.clone();
+
+        thread::spawn(move || {
+            receive_stream(listener, stream, requests);
+            connections.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+fn receive_datagrams(listener: Arc<Listener>, socket: Arc<UdpSocket>, requests: Sender<Request>) {
+    let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, source)) => {
+                let request = Request {
+                    listener: listener.clone(),
+                    message: buf[..len].to_vec(),
+                    source,
+                    connection: Connection::Udp(socket.clone()),
+                };
+
+                if requests.send(request).is_err() {
+                    break;
+                }
+            }
+            // E.g. an ICMP error for an earlier response, which says nothing about the next
+            // datagram
+            Err(err) => eprintln!("Error receiving data on {}: {}", listener.addr, err),
+        }
+    }
+}
+
+// A connection may carry any number of requests, it is closed once the client closes it or
+// stays idle for too long
+fn receive_stream(listener: Arc<Listener>, stream: TcpStream, requests: Sender<Request>) {
+    let Ok(source) = stream.peer_addr() else {
+        return;
+    };
+
+    if let Err(err) = stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT)) {
+        eprintln!("Failed to set a timeout for {}: {}", source, err);
+        return;
+    }
+
+    let stream = Arc::new(stream);
+
+    loop {
+        let mut length = [0; 2];
+
+        if stream.as_ref().read_exact(&mut length).is_err() {
+            break;
+        }
+
+        let mut message = vec![0; u16::from_be_bytes(length) as usize];
+
+        if let Err(err) = stream.as_ref().read_exact(&mut message) {
+            eprintln!("Failed to read request from {}: {}", source, err);
+            break;
+        }
+
+        let request = Request {
+            listener: listener.clone(),
+            message,
+            source,
+            connection: Connection::Tcp(stream.clone()),
+        };
+
+        if requests.send(request).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listeners_are_read_with_their_settings() {
+        let listener =
+            Listener::from_str("TCP:[::1]:2053,allow=::1,allow=192.0.2.0/24,zone=Example.com.")
+                .unwrap();
+
+        assert_eq!(listener.transport, Transport::Tcp);
+        assert_eq!(listener.addr, SocketAddr::from_str("[::1]:2053").unwrap());
+        assert_eq!(listener.allow.len(), 2);
+        assert_eq!(listener.zones, vec!["example.com".to_string()]);
+
+        let listener = Listener::from_str("udp:0.0.0.0:53").unwrap();
+
+        assert_eq!(listener.transport, Transport::Udp);
+        assert!(listener.allow.is_empty() && listener.zones.is_empty());
+    }
+
+    #[test]
+    fn malformed_listeners_are_rejected() {
+        for value in [
+            "127.0.0.1:53",
+            "sctp:127.0.0.1:53",
+            "udp:127.0.0.1",
+            // IPv6 addresses need brackets to tell them from the port
+            "udp:::1:53",
+            "udp:127.0.0.1:53,allow=192.0.2.0/33",
+            "udp:127.0.0.1:53,view=internal",
+        ] {
+            assert!(
+                matches!(
+                    Listener::from_str(value),
+                    Err(ServerError::InvalidListener(_) | ServerError::InvalidAddressPrefix(_))
+                ),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn allow_prefixes_limit_the_clients() {
+        let listener =
+            Listener::from_str("udp:[::]:53,allow=192.0.2.0/24,allow=2001:db8::/32").unwrap();
+
+        assert!(listener.allows(IpAddr::from_str("192.0.2.7").unwrap()));
+        assert!(listener.allows(IpAddr::from_str("2001:db8:1::1").unwrap()));
+        assert!(!listener.allows(IpAddr::from_str("192.0.3.7").unwrap()));
+        assert!(!listener.allows(IpAddr::from_str("2001:db9::1").unwrap()));
+        // What an IPv4 client looks like on a dual-stack socket
+        assert!(listener.allows(IpAddr::from_str("::ffff:192.0.2.7").unwrap()));
+
+        let listener = Listener::from_str("udp:[::]:53").unwrap();
+
+        assert!(listener.allows(IpAddr::from_str("198.51.100.1").unwrap()));
+    }
+
+    #[test]
+    fn zone_settings_limit_the_zones_served() {
+        let listener = Listener::from_str("udp:127.0.0.1:53,zone=example.com").unwrap();
+
+        assert!(listener.serves("example.com"));
+        assert!(!listener.serves("example.org"));
+        assert!(Listener::from_str("udp:127.0.0.1:53")
+            .unwrap()
+            .serves("example.org"));
+    }
+
+    #[test]
+    fn connections_over_the_limit_are_closed() {
+        let socket = Listener::from_str("tcp:127.0.0.1:0")
+            .unwrap()
+            .bind()
+            .unwrap();
+        let BoundSocket::Tcp(tcp_listener) = &socket.bound else {
+            unreachable!();
+        };
+        let addr = tcp_listener.local_addr().unwrap();
+        let (sender, _receiver) = std::sync::mpsc::channel();
+
+        socket.receive(sender);
+
+        let open: Vec<TcpStream> = (0..MAX_TCP_CONNECTIONS)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect();
+        let mut extra = TcpStream::connect(addr).unwrap();
+        extra
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        // The connection is closed without a byte sent
+        assert_eq!(extra.read(&mut [0; 2]).unwrap(), 0);
+        drop(open);
+    }
+}
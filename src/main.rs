@@ -1,27 +1,603 @@
+// Type and class names follow the RFC mnemonics (A, CNAME, AXFR, ...) on purpose
+#![allow(clippy::upper_case_acronyms)]
+
+mod authority;
 mod error;
+mod listener;
 mod message;
+mod notify;
+mod secondary;
 mod server;
+mod signer;
+mod tsig;
+mod update;
+mod validator;
+mod zone;
 
-use std::env;
+use std::{env, fs, process, str::FromStr, time::Duration};
 
-use crate::server::DnsServer;
+use crate::authority::{AnyPolicy, AuthoritativeZone, ZoneSource};
+use crate::error::ServerError;
+use crate::listener::Listener;
+use crate::notify::{Notifier, NotifyTarget};
+use crate::secondary::{SecondarySource, SecondaryZone};
+use crate::server::{DnsServer, QuestionPolicy, ServerIdentity};
+use crate::signer::{SigningKey, ZoneSigner};
+use crate::tsig::{TsigKey, TsigKeyring};
+use crate::update::AddressPrefix;
+use crate::validator::TrustAnchor;
+use crate::zone::{parse_ttl, SerialPolicy, ZoneFileParser};
 
 const RESOLVER_ARG_NAME: &str = "--resolver";
+const TSIG_KEY_ARG_NAME: &str = "--tsig-key";
+const TSIG_KEY_FILE_ARG_NAME: &str = "--tsig-key-file";
+const TRUST_ANCHOR_ARG_NAME: &str = "--trust-anchor";
+const ZONE_ARG_NAME: &str = "--zone";
+const KEY_DIRECTORY_ARG_NAME: &str = "--key-directory";
+const ALLOW_UPDATE_ARG_NAME: &str = "--allow-update";
+const SYNTHESIZE_PTR_ARG_NAME: &str = "--synthesize-ptr";
+const ANY_POLICY_ARG_NAME: &str = "--any-policy";
+const SERVER_VERSION_ARG_NAME: &str = "--server-version";
+const SERVER_ID_ARG_NAME: &str = "--server-id";
+const HIDE_IDENTITY_ARG_NAME: &str = "--hide-identity";
+const QUESTION_POLICY_ARG_NAME: &str = "--question-policy";
+const LISTEN_ARG_NAME: &str = "--listen";
+const SECONDARY_ARG_NAME: &str = "--secondary";
+const NOTIFY_ARG_NAME: &str = "--notify";
+const CONFIG_ARG_NAME: &str = "--config";
+
+const SIGN_ZONE_COMMAND: &str = "sign-zone";
+const ORIGIN_ARG_NAME: &str = "--origin";
+const KEY_ARG_NAME: &str = "--key";
+const NSEC3_ARG_NAME: &str = "--nsec3";
+const VALIDITY_ARG_NAME: &str = "--validity";
+const SERIAL_ARG_NAME: &str = "--serial";
+const OUTPUT_ARG_NAME: &str = "--output";
+
+/// Where queries are answered when no `--listen` option is given.
+const DEFAULT_LISTENERS: [&str; 2] = ["udp:127.0.0.1:2053", "tcp:127.0.0.1:2053"];
+
+/// Signatures made by `sign-zone` are valid for 30 days by default.
+const DEFAULT_SIGNATURE_VALIDITY: &str = "30d";
 
 fn main() {
-    let server = DnsServer::bind("127.0.0.1:2053").unwrap();
-    let mut cli_args = env::args();
-
-    let resolver_addr = cli_args
-        .nth(1)
-        .and_then(|arg_name| {
-            if arg_name == RESOLVER_ARG_NAME {
-                Some(arg_name)
-            } else {
-                None
+    let mut cli_args = env::args().skip(1).peekable();
+
+    if cli_args.next_if_eq(SIGN_ZONE_COMMAND).is_some() {
+        if let Err(err) = sign_zone(cli_args) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    if let Err(err) = serve(cli_args) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+/// Answers queries on every `--listen <udp|tcp>:<address>:<port>[,<setting>]...` address, or
+/// on 127.0.0.1:2053 over UDP and TCP. Options may also come from a file given with
+/// `--config <path>`. TSIG keys are given as `--tsig-key [algorithm:]name:secret`, or read from
+/// a BIND key file with `--tsig-key-file <path>`. Zones named with
+/// `--secondary <origin>:<primary address>[,key=<name>]` are transferred from their primary,
+/// signed with the key of that name. Secondaries given as
+/// `--notify <origin>:<address>[,key=<name>]` are sent a NOTIFY whenever the zone changes.
+/// Dynamic updates from `--allow-update` clients are kept in a `<master file>.jnl` journal that
+/// is applied when the zone is loaded, the master file is never rewritten. Every option is read
+/// before zones are loaded, the key directory may come after them. Unknown options and options
+/// missing their value are errors.
+fn serve(cli_args: impl Iterator<Item = String>) -> Result<(), ServerError> {
+    let mut resolver_addr = None;
+    let mut tsig_keys = Vec::new();
+    let mut trust_anchors = Vec::new();
+    let mut zone_sources = Vec::new();
+    let mut key_directory = None;
+    let mut update_acl = Vec::new();
+    let mut ptr_origins = Vec::new();
+    let mut any_policy = AnyPolicy::All;
+    let mut identity = ServerIdentity::default();
+    let mut question_policy = QuestionPolicy::All;
+    let mut listeners = Vec::new();
+    let mut secondary_sources = Vec::new();
+    let mut notify_targets = Vec::new();
+
+    let mut cli_args = expand_config_files(cli_args)?.into_iter();
+
+    while let Some(arg_name) = cli_args.next() {
+        let mut value = || option_value(&mut cli_args, &arg_name);
+
+        match arg_name.as_str() {
+            RESOLVER_ARG_NAME => resolver_addr = Some(value()?),
+            TSIG_KEY_ARG_NAME => tsig_keys.push(TsigKey::from_str(&value()?)?),
+            TSIG_KEY_FILE_ARG_NAME => tsig_keys.extend(TsigKey::load_file(&value()?)?),
+            TRUST_ANCHOR_ARG_NAME => trust_anchors.push(TrustAnchor::from_str(&value()?)?),
+            ZONE_ARG_NAME => zone_sources.push(value()?),
+            KEY_DIRECTORY_ARG_NAME => key_directory = Some(value()?),
+            ALLOW_UPDATE_ARG_NAME => update_acl.push(AddressPrefix::from_str(&value()?)?),
+            SYNTHESIZE_PTR_ARG_NAME => {
+                ptr_origins.push(value()?.trim_end_matches('.').to_lowercase())
+            }
+            ANY_POLICY_ARG_NAME => any_policy = AnyPolicy::from_str(&value()?)?,
+            SERVER_VERSION_ARG_NAME => identity.version = Some(value()?),
+            SERVER_ID_ARG_NAME => identity.hostname = Some(value()?),
+            HIDE_IDENTITY_ARG_NAME => {
+                identity = ServerIdentity {
+                    version: None,
+                    hostname: None,
+                }
+            }
+            QUESTION_POLICY_ARG_NAME => question_policy = QuestionPolicy::from_str(&value()?)?,
+            LISTEN_ARG_NAME => listeners.push(Listener::from_str(&value()?)?),
+            SECONDARY_ARG_NAME => secondary_sources.push(SecondarySource::from_str(&value()?)?),
+            NOTIFY_ARG_NAME => notify_targets.push(NotifyTarget::from_str(&value()?)?),
+            _ => {
+                return Err(ServerError::InvalidArgument(format!(
+                    "unknown option {}",
+                    arg_name
+                )))
+            }
+        }
+    }
+
+    if listeners.is_empty() {
+        listeners = DEFAULT_LISTENERS
+            .iter()
+            .map(|listener| Listener::from_str(listener))
+            .collect::<Result<_, _>>()?;
+    }
+
+    // Secondaries and notify targets name the key they use
+    let find_key = |name: &Option<String>| match name {
+        Some(name) => tsig_keys
+            .iter()
+            .find(|key| key.name == *name)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| format!("there is no TSIG key {}", name)),
+        None => Ok(None),
+    };
+
+    let secondaries = secondary_sources
+        .into_iter()
+        .map(|source| {
+            let key = find_key(&source.key).map_err(ServerError::InvalidSecondary)?;
+
+            Ok(SecondaryZone::new(source, key))
+        })
+        .collect::<Result<Vec<_>, ServerError>>()?;
+    let notifiers = notify_targets
+        .into_iter()
+        .map(|target| {
+            let key = find_key(&target.key).map_err(ServerError::InvalidNotifyTarget)?;
+
+            Ok(Notifier::new(target, key))
+        })
+        .collect::<Result<Vec<_>, ServerError>>()?;
+
+    let zones = load_zones(&zone_sources, key_directory.as_deref(), &ptr_origins)?;
+
+    // The data of a secondary zone comes from its primary, a master file for it would only be
+    // served until the first transfer replaced it
+    if let Some(zone) = zones.iter().find(|zone| {
+        secondaries
+            .iter()
+            .any(|secondary| secondary.origin() == zone.origin())
+    }) {
+        return Err(ServerError::InvalidSecondary(format!(
+            "{} is given with both {} and {}",
+            zone.origin(),
+            ZONE_ARG_NAME,
+            SECONDARY_ARG_NAME
+        )));
+    }
+
+    let is_loaded = |origin: &str| {
+        zones.iter().any(|zone| zone.origin() == origin)
+            || secondaries
+                .iter()
+                .any(|secondary| secondary.origin() == origin)
+    };
+
+    // A listener limited to zones that are not loaded would silently answer nothing locally
+    if let Some(origin) = listeners
+        .iter()
+        .flat_map(|listener| &listener.zones)
+        .find(|origin| !is_loaded(origin))
+    {
+        return Err(ServerError::InvalidListener(format!(
+            "{} is not a loaded zone",
+            origin
+        )));
+    }
+
+    if let Some(notifier) = notifiers
+        .iter()
+        .find(|notifier| !is_loaded(notifier.origin()))
+    {
+        return Err(ServerError::InvalidNotifyTarget(format!(
+            "{} is not a loaded zone",
+            notifier.origin()
+        )));
+    }
+
+    let server = DnsServer::bind(listeners)?
+        .with_tsig_keys(TsigKeyring::new(tsig_keys))
+        .with_trust_anchors(trust_anchors)
+        .with_zones(zones)
+        .with_update_acl(update_acl)
+        .with_any_policy(any_policy)
+        .with_identity(identity)
+        .with_question_policy(question_policy)
+        .with_secondaries(secondaries)
+        .with_notifiers(notifiers);
+
+    server.listen(resolver_addr.as_deref())
+}
+
+/// Replaces each `--config <path>` with the options in the file, one per line without their
+/// leading dashes, e.g. `listen udp:[::]:53,allow=192.0.2.0/24`. Blank lines and lines starting
+/// with `#` are skipped.
+fn expand_config_files(
+    mut cli_args: impl Iterator<Item = String>,
+) -> Result<Vec<String>, ServerError> {
+    let mut expanded = Vec::new();
+
+    while let Some(arg_name) = cli_args.next() {
+        if arg_name != CONFIG_ARG_NAME {
+            expanded.push(arg_name);
+            continue;
+        }
+
+        let path = option_value(&mut cli_args, CONFIG_ARG_NAME)?;
+        let input = fs::read_to_string(&path)
+            .map_err(|err| ServerError::InvalidArgument(format!("{}: {}", path, err)))?;
+
+        for (index, line) in input.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once(char::is_whitespace)
+                .map_or((line, None), |(name, value)| (name, Some(value.trim())));
+            let name = format!("--{}", name);
+
+            // Config files are not nested, an option that includes one would be easy to loop
+            if name == CONFIG_ARG_NAME {
+                return Err(ServerError::InvalidArgument(format!(
+                    "{}:{}: config files cannot include others",
+                    path,
+                    index + 1
+                )));
             }
+
+            expanded.push(name);
+            expanded.extend(value.map(str::to_string));
+        }
+    }
+
+    Ok(expanded)
+}
+
+// The value following an option, which every option except the flags takes
+fn option_value(
+    cli_args: &mut impl Iterator<Item = String>,
+    arg_name: &str,
+) -> Result<String, ServerError> {
+    cli_args
+        .next()
+        .ok_or_else(|| ServerError::InvalidArgument(format!("{} is missing its value", arg_name)))
+}
+
+/// Loads the zones given as `--zone <origin>:<master file>`. Zones with keys in the key
+/// directory are signed online, and zones named with `--synthesize-ptr <origin>` answer
+/// reverse lookups for their addresses.
+fn load_zones(
+    sources: &[String],
+    key_directory: Option<&str>,
+    ptr_origins: &[String],
+) -> Result<Vec<AuthoritativeZone>, ServerError> {
+    let zones = sources
+        .iter()
+        .map(|source| {
+            let zone = AuthoritativeZone::load(&ZoneSource::from_str(source)?, key_directory)?;
+
+            Ok(
+                if ptr_origins.iter().any(|origin| origin == zone.origin()) {
+                    zone.with_ptr_synthesis()
+                } else {
+                    zone
+                },
+            )
         })
-        .and_then(|_| cli_args.next());
+        .collect::<Result<Vec<_>, ServerError>>()?;
+
+    match ptr_origins
+        .iter()
+        .find(|origin| !zones.iter().any(|zone| zone.origin() == *origin))
+    {
+        Some(origin) => Err(ServerError::ParseZoneFile(format!(
+            "{} {} is not a loaded zone",
+            SYNTHESIZE_PTR_ARG_NAME, origin
+        ))),
+        None => Ok(zones),
+    }
+}
+
+/// Signs a master file offline:
+///
+/// `sign-zone --origin <zone> --key <key path>... [--nsec3] [--validity <ttl>]
+/// [--serial keep|increment|date] [--output <file>] <zone file>`
+///
+/// Key paths point to BIND style key files without their `.key` and `.private` extensions. The
+/// signed zone is written to standard output unless an output file is given.
+fn sign_zone(mut cli_args: impl Iterator<Item = String>) -> Result<(), ServerError> {
+    let mut origin = None;
+    let mut zone_file = None;
+    let mut keys = Vec::new();
+    let mut nsec3 = false;
+    let mut validity = DEFAULT_SIGNATURE_VALIDITY.to_string();
+    let mut serial_policy = SerialPolicy::Keep;
+    let mut output = None;
+
+    while let Some(arg_name) = cli_args.next() {
+        match arg_name.as_str() {
+            ORIGIN_ARG_NAME => origin = Some(option_value(&mut cli_args, &arg_name)?),
+            KEY_ARG_NAME => keys.push(SigningKey::load(&option_value(&mut cli_args, &arg_name)?)?),
+            NSEC3_ARG_NAME => nsec3 = true,
+            VALIDITY_ARG_NAME => validity = option_value(&mut cli_args, &arg_name)?,
+            SERIAL_ARG_NAME => {
+                serial_policy = SerialPolicy::from_str(&option_value(&mut cli_args, &arg_name)?)?
+            }
+            OUTPUT_ARG_NAME => output = Some(option_value(&mut cli_args, &arg_name)?),
+            _ if arg_name.starts_with("--") => {
+                return Err(ServerError::SignZone(format!(
+                    "unknown option {}",
+                    arg_name
+                )))
+            }
+            _ => zone_file = Some(arg_name),
+        }
+    }
+
+    let origin =
+        origin.ok_or_else(|| ServerError::SignZone(format!("{} is missing", ORIGIN_ARG_NAME)))?;
+    let zone_file =
+        zone_file.ok_or_else(|| ServerError::SignZone("zone file is missing".to_string()))?;
+    let validity = parse_ttl(&validity).map_err(ServerError::SignZone)?;
+
+    let input = fs::read_to_string(&zone_file)
+        .map_err(|err| ServerError::ParseZoneFile(format!("{}: {}", zone_file, err)))?;
+    let mut zone = ZoneFileParser::new(&input, &origin).parse()?;
+
+    zone.update_serial(serial_policy)?;
+
+    let mut signer = ZoneSigner::new(keys, Duration::from_secs(validity as u64));
+
+    if nsec3 {
+        signer = signer.with_nsec3(0, Vec::new());
+    }
+
+    let signed_zone = signer.sign(zone)?.to_string();
+
+    match output {
+        Some(path) => fs::write(&path, signed_zone)
+            .map_err(|err| ServerError::SignZone(format!("{}: {}", path, err)))?,
+        None => print!("{}", signed_zone),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+    use crate::message::{
+        dnssec::{rrsets, Rrsig},
+        rdata::{RData, Serial},
+        types::DnsType,
+    };
+    use crate::validator::verify_rrsig;
+
+    // A directory of its own for each test under the system's temporary directory
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("{}-{}", name, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // Writes an Ed25519 key in the format of BIND's dnssec-keygen and returns its path without
+    // the extensions
+    fn write_key(directory: &Path, seed: u8, flags: u16) -> String {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap();
+        let path = directory.join(format!("Kexample.com.+015+{}", seed));
+        let path = path.to_string_lossy().into_owned();
+
+        fs::write(
+            format!("{}.key", path),
+            format!(
+                "example.com. IN DNSKEY {} 3 15 {}\n",
+                flags,
+                STANDARD.encode(key_pair.public_key())
+            ),
+        )
+        .unwrap();
+        fs::write(
+            format!("{}.private", path),
+            format!(
+                "Private-key-format: v1.3\nAlgorithm: 15 (ED25519)\nPrivateKey: {}\n",
+                STANDARD.encode([seed; 32])
+            ),
+        )
+        .unwrap();
+
+        path
+    }
+
+    #[test]
+    fn signed_zone_files_load_and_validate() {
+        let directory = temporary_directory("sign-zone");
+        let zone_file = directory.join("example.com.zone");
+        let output = directory.join("example.com.zone.signed");
+
+        fs::write(
+            &zone_file,
+            "$TTL 3600\n\
+             @ IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+             @ IN NS ns1\n\
+             ns1 IN A 192.0.2.1\n\
+             www 300 IN A 192.0.2.2\n\
+             www 300 IN TXT \"v=spf1 -all\"\n",
+        )
+        .unwrap();
+
+        let key_signing_key = write_key(&directory, 1, 257);
+        let zone_signing_key = write_key(&directory, 2, 256);
+
+        let args = [
+            "--origin",
+            "example.com",
+            "--key",
+            &key_signing_key,
+            "--key",
+            &zone_signing_key,
+            "--nsec3",
+            "--serial",
+            "increment",
+            "--output",
+            &output.to_string_lossy(),
+            &zone_file.to_string_lossy(),
+        ];
+
+        sign_zone(args.iter().map(|arg| arg.to_string())).unwrap();
+
+        // The output is a master file the server loads as it is
+        let source = ZoneSource {
+            origin: "example.com".to_string(),
+            path: output.to_string_lossy().into_owned(),
+        };
+        let zone = AuthoritativeZone::load(&source, None).unwrap();
+
+        assert_eq!(zone.serial(), Some(Serial(2)));
+
+        // Every RRset read back verifies with one of the keys
+        let input = fs::read_to_string(&output).unwrap();
+        let records = ZoneFileParser::new(&input, "example.com")
+            .parse()
+            .unwrap()
+            .records;
+        let dnskeys: Vec<_> = records
+            .iter()
+            .filter_map(|record| match &record.data {
+                RData::Dnskey(dnskey) => Some(dnskey),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(dnskeys.len(), 2);
+        assert!(records.iter().any(|record| record.kind == DnsType::NSEC3));
+
+        for rrset in rrsets(&records) {
+            let signatures: Vec<&Rrsig> = records
+                .iter()
+                .filter(|record| record.name == rrset[0].name)
+                .filter_map(|record| match &record.data {
+                    RData::Rrsig(rrsig) if rrsig.type_covered == rrset[0].kind.into() => {
+                        Some(rrsig)
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            assert!(
+                signatures.iter().any(|rrsig| dnskeys
+                    .iter()
+                    .any(|dnskey| verify_rrsig(&rrset, rrsig, dnskey))),
+                "{} {}",
+                rrset[0].name,
+                rrset[0].kind
+            );
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn unknown_options_and_missing_values_are_errors() {
+        for cli_args in [
+            &["--listne", "udp:127.0.0.1:53"][..],
+            &["--listen"],
+            &["--hide-identity", "--zone"],
+        ] {
+            assert!(
+                matches!(serve(args(cli_args)), Err(ServerError::InvalidArgument(_))),
+                "{:?}",
+                cli_args
+            );
+        }
+
+        assert!(matches!(
+            sign_zone(args(&["--origin", "example.com", "--key"])),
+            Err(ServerError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn config_files_give_options_one_per_line() {
+        let directory = temporary_directory("config-file");
+        let path = directory.join("server.conf");
+        let path = path.to_string_lossy().into_owned();
+
+        fs::write(
+            &path,
+            "# Listeners\n\
+             listen udp:[::]:53,allow=192.0.2.0/24\n\
+             \n\
+             hide-identity\n\
+             any-policy   minimal  \n",
+        )
+        .unwrap();
+
+        let expanded =
+            expand_config_files(args(&["--resolver", "192.0.2.1:53", "--config", &path])).unwrap();
+
+        assert_eq!(
+            expanded,
+            [
+                "--resolver",
+                "192.0.2.1:53",
+                "--listen",
+                "udp:[::]:53,allow=192.0.2.0/24",
+                "--hide-identity",
+                "--any-policy",
+                "minimal",
+            ]
+        );
+
+        fs::write(&path, "config other.conf\n").unwrap();
+
+        assert!(matches!(
+            expand_config_files(args(&["--config", &path])),
+            Err(ServerError::InvalidArgument(_))
+        ));
 
-    server.listen(resolver_addr.as_deref()).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+    }
 }
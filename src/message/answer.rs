@@ -1,30 +1,34 @@
-use std::net::{SocketAddr, UdpSocket};
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 use crate::{
     error::ServerError,
     message::{
-        constants::DNS_MESSAGE_PACKET_SIZE,
-        header::Header,
+        dnssec::is_subdomain,
+        edns::{Edns, EDNS_UDP_PAYLOAD_SIZE},
+        header::{random_id, Header, ResponseCode},
         message::{Message, MessageDecoder, MessageEncoder},
-        types::{DnsClass, DnsType, DomainLabel, DomainName},
+        question::{Question, QuestionClass, QuestionType},
+        rdata::{RData, RDataDecoder, RDataEncoder},
+        types::{DnsClass, DnsType, DomainName},
     },
 };
 
 /// The answer section contains RRs that answer the question
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Answer {
     /// The domain name encoded as a sequence of labels.
     pub name: String,
     pub kind: DnsType,
     pub class: DnsClass,
-    ///	The duration in seconds a record can be cached before requerying.
+    /// The duration in seconds a record can be cached before requerying.
     pub ttl: u32,
-    /// Length of the RDATA field in bytes.
-    pub length: u16,
-    /// Data specific to the record type.
-    pub data: String,
+    /// Data specific to the record type. Its length is worked out when encoding.
+    pub data: RData,
 }
 
 pub struct AnswersEncoder;
@@ -40,21 +44,10 @@ impl AnswersEncoder {
         Bytes::from(buf)
     }
 
-    fn encode_answer(&self, answer: &Answer) -> Bytes {
+    pub fn encode_answer(&self, answer: &Answer) -> Bytes {
         let mut buf = BytesMut::new();
-        let mut encoded_name = BytesMut::new();
-        let answer_name_parts = answer.name.split(".");
-
-        for part in answer_name_parts {
-            let label_length: u8 = part.len() as u8;
-
-            encoded_name.put_u8(label_length);
-            encoded_name.put(part.as_bytes());
-        }
-
-        encoded_name.put_u8(0);
 
-        buf.put(encoded_name);
+        buf.put(DomainName::encode(&answer.name));
 
         buf.put_u16(answer.kind.into());
 
@@ -62,33 +55,30 @@ impl AnswersEncoder {
 
         buf.put_u32(answer.ttl);
 
-        buf.put_u16(answer.length);
+        let encoded_data = RDataEncoder::encode(&answer.data);
 
-        let answer_data_parts = answer.data.split(".");
-
-        // TODO: Check if answer.data contains just 4 parts. Otherwises, throw an error.
-
-        let mut encoded_data = BytesMut::new();
-
-        for part in answer_data_parts {
-            let value = u8::from_str_radix(part, 10).expect("Error when encoding answer data");
-
-            encoded_data.put_u8(value);
-        }
+        buf.put_u16(encoded_data.len() as u16);
 
         buf.put(encoded_data);
 
         Bytes::from(buf)
     }
 }
+/// Answers decoder supports message compression, `message` holds the whole message so
+/// compression pointers can be followed.
 pub struct AnswersDecoder<'a> {
     buf: &'a mut Bytes,
+    message: &'a Bytes,
     answers_count: u16,
 }
 
 impl<'a> AnswersDecoder<'a> {
-    pub fn new(buf: &'a mut Bytes, answers_count: u16) -> Self {
-        Self { buf, answers_count }
+    pub fn new(buf: &'a mut Bytes, message: &'a Bytes, answers_count: u16) -> Self {
+        Self {
+            buf,
+            message,
+            answers_count,
+        }
     }
 
     pub fn decode(mut self) -> Result<Vec<Answer>, ServerError> {
@@ -104,106 +94,347 @@ impl<'a> AnswersDecoder<'a> {
     }
 
     pub fn decode_answer(&mut self) -> Result<Answer, ServerError> {
-        let mut domain_name = DomainName::default();
-
-        loop {
-            let label_length = self.buf.get_u8();
+        let domain_name = DomainName::decode_compressed(self.buf, self.message)
+            .map_err(|err| ServerError::DecodeAnswer(err.to_string()))?;
 
-            if label_length == 0 {
-                break;
-            }
-
-            let bytes = self.buf.copy_to_bytes(label_length as usize);
-            let label = std::str::from_utf8(&bytes[..])
-                .map_err(|err| ServerError::DecodeAnswer(err.to_string()))?;
-
-            domain_name.add_label(DomainLabel {
-                pointer: None,
-                name: label.to_string(),
-            });
+        if self.buf.remaining() < 10 {
+            return Err(ServerError::DecodeAnswer(
+                "record header is truncated".to_string(),
+            ));
         }
 
         let kind = DnsType::try_from(self.buf.get_u16())?;
         let class = DnsClass::try_from(self.buf.get_u16())?;
         let ttl = self.buf.get_u32();
         let length = self.buf.get_u16();
-
-        let mut data: Vec<String> = Vec::new();
-
-        for _ in 0..length {
-            let value = self.buf.get_u8();
-
-            data.push(value.to_string());
-        }
+        let data = RDataDecoder::new(self.buf, self.message, kind, length).decode()?;
 
         Ok(Answer {
             name: domain_name.to_string(),
             kind,
             class,
             ttl,
-            length,
-            data: data.join("."),
+            data,
         })
     }
 }
 
+/// DNAME redirections followed for one question at most, which also ends loops.
+const MAX_DNAME_CHAIN: usize = 8;
+
 pub struct AnswersBuilder;
 
 impl AnswersBuilder {
-    pub fn build_answers(query: &Message) -> Result<Vec<Answer>, ServerError> {
-        Ok(query
+    /// Answers every A question, and ANY questions, with the same address. Questions of other
+    /// types or classes get no answer.
+    pub fn build_answers(query: &Message) -> Vec<Answer> {
+        query
             .questions
             .iter()
+            .filter(|question| {
+                matches!(
+                    question.kind,
+                    QuestionType::DnsType(DnsType::A) | QuestionType::ALL
+                ) && matches!(
+                    question.class,
+                    QuestionClass::DnsClass(DnsClass::IN) | QuestionClass::ALL
+                )
+            })
             .map(|question| Answer {
                 name: question.name.to_string(),
                 kind: DnsType::A,
                 class: DnsClass::IN,
                 ttl: 60,
-                length: 4,
-                data: "8.8.8.8".to_string(),
+                data: RData::A(Ipv4Addr::new(8, 8, 8, 8)),
             })
-            .collect())
+            .collect()
     }
 
-    pub fn build_answers_from_resolver(
+    /// Forwards each question of the query in its own message, as resolvers usually only answer
+    /// the first question of a message. Returns one response per question.
+    pub fn forward_questions(
         query: &Message,
+        edns: Option<&Edns>,
         socket: &UdpSocket,
         addr: &SocketAddr,
-    ) -> Result<Vec<Answer>, ServerError> {
-        let mut answers: Vec<Answer> = Vec::with_capacity(query.header.question_count as usize);
-
-        for question in &query.questions {
-            let message = Message {
-                header: Header {
-                    question_count: 1,
-                    query_indicator: false,
-                    ..query.header
-                },
-                questions: vec![question.clone()],
-                answers: Vec::new(),
+    ) -> Result<Vec<Message>, ServerError> {
+        query
+            .questions
+            .iter()
+            .map(|question| {
+                let response = Self::forward_question(question, &query.header, edns, socket, addr)?;
+
+                Self::follow_dnames(question, response, &query.header, edns, socket, addr)
+            })
+            .collect()
+    }
+
+    // A response may end at a name below a DNAME without answering it, e.g. from a server that
+    // is authoritative for the DNAME but not for its target. The CNAME is synthesized if it is
+    // missing and its target queried, so clients get the whole chain (RFC 6672 section 3.2).
+    fn follow_dnames(
+        question: &Question,
+        mut response: Message,
+        header: &Header,
+        edns: Option<&Edns>,
+        socket: &UdpSocket,
+        addr: &SocketAddr,
+    ) -> Result<Message, ServerError> {
+        // A CNAME question is answered by the synthesized CNAME itself
+        if matches!(
+            question.kind,
+            QuestionType::DnsType(DnsType::CNAME | DnsType::DNAME)
+        ) {
+            return Ok(response);
+        }
+
+        let mut name = normalize(&question.name);
+
+        for _ in 0..MAX_DNAME_CHAIN {
+            let mut is_redirected = false;
+
+            // Walks the CNAME chain already in the answer, bounded in case it loops
+            for _ in 0..response.answers.len() {
+                let target = response
+                    .answers
+                    .iter()
+                    .find_map(|answer| match &answer.data {
+                        RData::DomainName(target)
+                            if answer.kind == DnsType::CNAME && normalize(&answer.name) == name =>
+                        {
+                            Some(normalize(target))
+                        }
+                        _ => None,
+                    });
+
+                let Some(target) = target else {
+                    break;
+                };
+
+                is_redirected = dname_redirect(&response.answers, &name)
+                    .is_some_and(|(_, _, synthesized)| synthesized == target);
+                name = target;
+            }
+
+            let is_answered = response
+                .answers
+                .iter()
+                .any(|answer| normalize(&answer.name) == name);
+
+            if is_answered || !matches!(response.header.code, ResponseCode::NoErrorCondition) {
+                break;
+            }
+
+            if !is_redirected {
+                let Some((class, ttl, target)) = dname_redirect(&response.answers, &name) else {
+                    break;
+                };
+
+                response.answers.push(Answer {
+                    name: name.clone(),
+                    kind: DnsType::CNAME,
+                    class,
+                    ttl,
+                    data: RData::DomainName(target.clone()),
+                });
+
+                name = target;
+            }
+
+            let redirected = Question {
+                name: name.clone(),
+                ..question.clone()
             };
 
-            let encoded_message = MessageEncoder::encode(&message);
+            let next = Self::forward_question(&redirected, header, edns, socket, addr)?;
 
-            // Sent a message to the forwarded server with one question
-            socket
-                .send_to(&encoded_message, addr)
-                .map_err(|err| ServerError::ForwardedServer(err.to_string()))?;
+            response.header.code = next.header.code;
+            response.answers.extend(next.answers);
+            response.authorities = next.authorities;
+            response.additionals.extend(next.additionals);
+        }
 
-            let mut buf = [0; DNS_MESSAGE_PACKET_SIZE];
+        Ok(response)
+    }
 
-            // Receive a message from the forwarded server
-            socket
-                .recv_from(&mut buf)
-                .map_err(|err| ServerError::ForwardedServer(err.to_string()))?;
+    /// Address records of the names the answers point to, such as MX exchanges and SRV
+    /// targets (RFC 1035 section 3.3.9). They are taken from the additional sections of the
+    /// resolver responses, and queried from the resolver when it did not send them.
+    ///
+    /// The additional section is optional, so lookup failures only leave addresses out. After
+    /// the first failure no more lookups are made, so an unreachable resolver delays the
+    /// response by one timeout rather than two per target.
+    pub fn build_additionals(
+        query: &Message,
+        responses: &[Message],
+        socket: &UdpSocket,
+        addr: &SocketAddr,
+    ) -> Vec<Answer> {
+        let answers: Vec<&Answer> = responses
+            .iter()
+            .flat_map(|response| response.answers.iter())
+            .collect();
+
+        let mut targets: Vec<String> = answers
+            .iter()
+            .filter_map(|answer| answer.data.target_name())
+            .map(|name| name.to_lowercase())
+            .collect();
 
-            let forwarded_message = MessageDecoder::decode(&buf)?;
+        targets.sort();
+        targets.dedup();
 
-            for answer in forwarded_message.answers {
-                answers.push(answer);
+        let mut additionals = Vec::new();
+        let mut lookup_failed = false;
+
+        for target in targets {
+            let is_address = |record: &&Answer| {
+                matches!(record.kind, DnsType::A | DnsType::AAAA)
+                    && record.name.eq_ignore_ascii_case(&target)
+            };
+
+            // Addresses already in the answer section are not repeated
+            if answers.iter().any(is_address) {
+                continue;
+            }
+
+            let mut addresses: Vec<Answer> = responses
+                .iter()
+                .flat_map(|response| response.additionals.iter())
+                .filter(is_address)
+                .cloned()
+                .collect();
+
+            if addresses.is_empty() && !lookup_failed {
+                for kind in [DnsType::A, DnsType::AAAA] {
+                    let question = Question {
+                        name: target.clone(),
+                        kind: QuestionType::DnsType(kind),
+                        class: QuestionClass::DnsClass(DnsClass::IN),
+                    };
+
+                    let response = match Self::forward_question(
+                        &question,
+                        &query.header,
+                        None,
+                        socket,
+                        addr,
+                    ) {
+                        Ok(response) => response,
+                        Err(err) => {
+                            eprintln!(
+                                "Failed to look up additional records for {}: {}",
+                                target, err
+                            );
+                            lookup_failed = true;
+                            break;
+                        }
+                    };
+
+                    addresses.extend(
+                        response
+                            .answers
+                            .into_iter()
+                            .filter(|answer| answer.kind == kind && is_address(&answer)),
+                    );
+                }
             }
+
+            additionals.extend(addresses);
         }
 
-        Ok(answers)
+        additionals
+    }
+
+    /// Sends a message with a single question to the resolver, copying the flags from `header`
+    /// but not its ID, and waits for its response. Packets from another address, with another
+    /// ID or that do not decode are ignored; when only undecodable ones came back before the
+    /// timeout, the resolver is taken to be broken rather than unreachable.
+    pub fn forward_question(
+        question: &Question,
+        header: &Header,
+        edns: Option<&Edns>,
+        socket: &UdpSocket,
+        addr: &SocketAddr,
+    ) -> Result<Message, ServerError> {
+        let message = Message {
+            header: Header {
+                question_count: 1,
+                answer_record_count: 0,
+                auth_record_count: 0,
+                additional_record_count: edns.is_some() as u16,
+                query_indicator: false,
+                // A fresh ID per query, so a response cannot be spoofed by whoever sees or
+                // guesses the client's
+                id: random_id(),
+                ..*header
+            },
+            questions: vec![question.clone()],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: edns.cloned(),
+            tsig: None,
+        };
+
+        let encoded_message = MessageEncoder::encode(&message);
+
+        // Sent a message to the forwarded server with one question
+        socket
+            .send_to(&encoded_message, addr)
+            .map_err(|err| ServerError::ForwardedServer(err.to_string()))?;
+
+        let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+        let mut decode_error = None;
+
+        // Receive a message from the forwarded server
+        loop {
+            let (len, source) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                // What a read timeout gives, depending on the platform
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Err(match decode_error {
+                        Some(err) => ServerError::DecodeUpstream(err),
+                        None => ServerError::ResolverTimeout(err.to_string()),
+                    });
+                }
+                Err(err) => return Err(ServerError::ForwardedServer(err.to_string())),
+            };
+
+            // The ID is checked before decoding, so stray packets cannot fail the query
+            if source != *addr || len < 2 || buf[..2] != message.header.id.to_be_bytes() {
+                continue;
+            }
+
+            match MessageDecoder::decode(&buf[..len]) {
+                Ok(forwarded_message) => break Ok(forwarded_message),
+                Err(err) => {
+                    eprintln!(
+                        "Ignored a response from {} that does not decode: {}",
+                        addr, err
+                    );
+                    decode_error = Some(err.to_string());
+                }
+            }
+        }
     }
 }
+
+// The class, TTL and target of the CNAME a DNAME above `name` synthesizes for it
+fn dname_redirect(answers: &[Answer], name: &str) -> Option<(DnsClass, u32, String)> {
+    answers.iter().find_map(|answer| match &answer.data {
+        RData::DomainName(target) if answer.kind == DnsType::DNAME => {
+            let owner = normalize(&answer.name);
+
+            (owner != name && is_subdomain(name, &owner))
+                .then(|| DomainName::substitute(name, &owner, &normalize(target)))
+                .flatten()
+                .map(|target| (answer.class, answer.ttl, target))
+        }
+        _ => None,
+    })
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
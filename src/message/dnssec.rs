@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use ring::digest;
+
+use super::{
+    answer::Answer,
+    rdata::{RData, RDataEncoder},
+    types::{DnsClass, DnsType, DomainName},
+};
+use crate::error::ServerError;
+
+// Signing algorithms the server understands, from the IANA DNSSEC algorithm registry
+//
+// 8               RSA/SHA-256 (RSASHA256, RFC 5702)
+//
+// 10              RSA/SHA-512 (RSASHA512, RFC 5702)
+//
+// 13              ECDSA P-256 with SHA-256 (ECDSAP256SHA256, RFC 6605)
+//
+// 14              ECDSA P-384 with SHA-384 (ECDSAP384SHA384, RFC 6605)
+//
+// 15              Ed25519 (ED25519, RFC 8080)
+pub const ALGORITHM_RSASHA256: u8 = 8;
+pub const ALGORITHM_RSASHA512: u8 = 10;
+pub const ALGORITHM_ECDSAP256SHA256: u8 = 13;
+pub const ALGORITHM_ECDSAP384SHA384: u8 = 14;
+pub const ALGORITHM_ED25519: u8 = 15;
+
+// Digest types of DS records
+//
+// 1               SHA-1 (RFC 4034)
+//
+// 2               SHA-256 (RFC 4509)
+//
+// 4               SHA-384 (RFC 6605)
+pub const DIGEST_SHA1: u8 = 1;
+pub const DIGEST_SHA256: u8 = 2;
+pub const DIGEST_SHA384: u8 = 4;
+
+/// Public key of a zone, used in DNSKEY and CDNSKEY records.
+///
+/// https://www.rfc-editor.org/rfc/rfc4034#section-2.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dnskey {
+    /// Bit 7 marks a zone key, bit 15 a secure entry point (key signing key).
+    pub flags: u16,
+    /// Always 3.
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub public_key: Vec<u8>,
+}
+
+impl Dnskey {
+    /// Flag set on keys that can sign the zone's RRsets.
+    pub const ZONE_KEY_FLAG: u16 = 1 << 8;
+
+    /// Identifies the key in RRSIG and DS records. It is a checksum of the record data, so
+    /// different keys may share a tag.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc4034#appendix-B
+    pub fn key_tag(&self) -> u16 {
+        let mut data = vec![(self.flags >> 8) as u8, self.flags as u8];
+        data.push(self.protocol);
+        data.push(self.algorithm);
+        data.extend_from_slice(&self.public_key);
+
+        let mut accumulator: u32 = 0;
+
+        for (index, byte) in data.iter().enumerate() {
+            if index & 1 == 1 {
+                accumulator += *byte as u32;
+            } else {
+                accumulator += (*byte as u32) << 8;
+            }
+        }
+
+        accumulator += (accumulator >> 16) & 0xFFFF;
+
+        (accumulator & 0xFFFF) as u16
+    }
+
+    /// The key in the uncompressed SEC1 form ring expects for ECDSA, the record only holds the
+    /// bare point coordinates.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc6605#section-4
+    pub fn sec1_public_key(&self) -> Vec<u8> {
+        let mut point = vec![0x04];
+        point.extend_from_slice(&self.public_key);
+        point
+    }
+}
+
+/// Digest of a child zone DNSKEY, used in DS and CDS records.
+///
+/// https://www.rfc-editor.org/rfc/rfc4034#section-5.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ds {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+
+/// Signature over the RRset with the same owner, class and covered type.
+///
+/// https://www.rfc-editor.org/rfc/rfc4034#section-3.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rrsig {
+    /// Type number of the signed RRset, which is not necessarily one the server models.
+    pub type_covered: u16,
+    pub algorithm: u8,
+    /// Number of labels of the original owner name, not counting the root or a wildcard.
+    pub labels: u8,
+    pub original_ttl: u32,
+    /// Seconds since the epoch, in serial number arithmetic.
+    pub expiration: u32,
+    /// Seconds since the epoch, in serial number arithmetic.
+    pub inception: u32,
+    pub key_tag: u16,
+    pub signer_name: String,
+    pub signature: Vec<u8>,
+}
+
+impl Rrsig {
+    /// Data covered by the signature: the RRSIG data without the signature, followed by every
+    /// record of the RRset in canonical form, sorted by record data. `owner` is the wildcard
+    /// name for records synthesized from a wildcard.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc4034#section-3.1.8.1
+    pub fn signed_data(&self, owner: &str, class: DnsClass, records: &[&RData]) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        buf.put(RDataEncoder::encode_canonical(&RData::Rrsig(Rrsig {
+            signature: Vec::new(),
+            ..self.clone()
+        })));
+
+        let mut rdatas: Vec<Bytes> = records
+            .iter()
+            .map(|data| RDataEncoder::encode_canonical(data))
+            .collect();
+
+        // Duplicate records are left out
+        rdatas.sort();
+        rdatas.dedup();
+
+        let owner = DomainName::encode(&owner.to_lowercase());
+
+        for rdata in rdatas {
+            buf.put(owner.clone());
+            buf.put_u16(self.type_covered);
+            buf.put_u16(class.into());
+            buf.put_u32(self.original_ttl);
+            buf.put_u16(rdata.len() as u16);
+            buf.put(rdata);
+        }
+
+        Bytes::from(buf)
+    }
+}
+
+/// Groups records into RRsets by owner name and type, in order of first appearance. Signatures
+/// are left out, they are looked up per RRset.
+pub fn rrsets(records: &[Answer]) -> Vec<Vec<&Answer>> {
+    let mut rrsets: Vec<Vec<&Answer>> = Vec::new();
+    let mut positions: HashMap<(String, u16), usize> = HashMap::new();
+
+    for record in records
+        .iter()
+        .filter(|record| record.kind != DnsType::RRSIG)
+    {
+        let key = (
+            record.name.trim_end_matches('.').to_lowercase(),
+            record.kind.into(),
+        );
+
+        match positions.get(&key) {
+            Some(position) => rrsets[*position].push(record),
+            None => {
+                positions.insert(key, rrsets.len());
+                rrsets.push(vec![record]);
+            }
+        }
+    }
+
+    rrsets
+}
+
+/// Whether `name` is `zone` or below it. Both are lowercase without the trailing dot, the root
+/// is the empty string.
+pub fn is_subdomain(name: &str, zone: &str) -> bool {
+    zone.is_empty() || name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+/// Authenticated denial of existence: the next owner name in the zone and the types present
+/// at this one.
+///
+/// https://www.rfc-editor.org/rfc/rfc4034#section-4.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nsec {
+    pub next_domain_name: String,
+    pub types: TypeBitmap,
+}
+
+/// Hashed authenticated denial of existence.
+///
+/// https://www.rfc-editor.org/rfc/rfc5155#section-3.2
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nsec3 {
+    pub hash_algorithm: u8,
+    /// Bit 0 is the opt-out flag.
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+    /// Hash of the next owner name, without the base32 encoding used for owner names.
+    pub next_hashed_owner_name: Vec<u8>,
+    pub types: TypeBitmap,
+}
+
+impl Nsec3 {
+    /// Hash algorithm 1, the only one defined.
+    pub const SHA1_HASH: u8 = 1;
+
+    /// The span may contain unsigned delegations.
+    pub const OPT_OUT_FLAG: u8 = 1;
+
+    /// Iterated SHA-1 hash of a name, the owner of an NSEC3 record is this hash encoded with
+    /// `base32hex` followed by the zone name.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc5155#section-5
+    pub fn hash_name(name: &str, iterations: u16, salt: &[u8]) -> Vec<u8> {
+        let mut data = DomainName::encode(&name.to_lowercase()).to_vec();
+        data.extend_from_slice(salt);
+
+        let mut hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &data);
+
+        for _ in 0..iterations {
+            let mut data = hash.as_ref().to_vec();
+            data.extend_from_slice(salt);
+
+            hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &data);
+        }
+
+        hash.as_ref().to_vec()
+    }
+}
+
+/// Base 32 with the extended hex alphabet, lowercase and without padding, as used for NSEC3
+/// owner names.
+///
+/// https://www.rfc-editor.org/rfc/rfc4648#section-7
+pub fn base32hex(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 0x1F) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 0x1F) as usize] as char);
+    }
+
+    encoded
+}
+
+/// Decodes base32hex without padding, in either case. None when `data` has other characters
+/// or does not end on a byte.
+pub fn decode_base32hex(data: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in data.chars() {
+        buffer = (buffer << 5) | c.to_digit(32)?;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+
+    // Only the zero bits that fill the last character may be left over
+    (bits < 5 && buffer & ((1 << bits) - 1) == 0).then_some(decoded)
+}
+
+/// Parameters an authoritative server uses to compute NSEC3 hashed owner names.
+///
+/// https://www.rfc-editor.org/rfc/rfc5155#section-4.2
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nsec3Param {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+}
+
+/// Set of type numbers present at an owner name, as carried by NSEC and NSEC3 records.
+///
+/// On the wire the types are split into windows of 256 types. Each window is sent as the window
+/// number, the length of its bitmap and the bitmap itself, where bit 0 of the first octet is the
+/// lowest type of the window. Trailing zero octets are left out and empty windows are not sent.
+///
+/// https://www.rfc-editor.org/rfc/rfc4034#section-4.1.2
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeBitmap {
+    /// Sorted and without duplicates.
+    types: Vec<u16>,
+}
+
+impl TypeBitmap {
+    pub fn new(mut types: Vec<u16>) -> Self {
+        types.sort_unstable();
+        types.dedup();
+
+        Self { types }
+    }
+
+    pub fn types(&self) -> &[u16] {
+        &self.types
+    }
+
+    pub fn contains(&self, kind: u16) -> bool {
+        self.types.binary_search(&kind).is_ok()
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) {
+        let mut types = self.types.iter().peekable();
+
+        while let Some(&first) = types.peek() {
+            let window = (first >> 8) as u8;
+            let mut bitmap = [0u8; 32];
+            let mut length = 0;
+
+            while let Some(&&kind) = types.peek() {
+                if (kind >> 8) as u8 != window {
+                    break;
+                }
+
+                let offset = (kind & 0xFF) as usize;
+                bitmap[offset / 8] |= 0b1000_0000 >> (offset % 8);
+                length = offset / 8 + 1;
+
+                types.next();
+            }
+
+            buf.put_u8(window);
+            buf.put_u8(length as u8);
+            buf.put(&bitmap[..length]);
+        }
+    }
+
+    pub fn decode(buf: &mut Bytes) -> Result<Self, ServerError> {
+        let mut types = Vec::new();
+
+        while buf.has_remaining() {
+            if buf.remaining() < 2 {
+                return Err(ServerError::DecodeAnswer(
+                    "type bitmap window is truncated".to_string(),
+                ));
+            }
+
+            let window = buf.get_u8() as u16;
+            let length = buf.get_u8() as usize;
+
+            if length == 0 || length > 32 || buf.remaining() < length {
+                return Err(ServerError::DecodeAnswer(format!(
+                    "type bitmap window {} has an invalid length of {}",
+                    window, length
+                )));
+            }
+
+            for (index, octet) in buf.split_to(length).iter().enumerate() {
+                for bit in 0..8 {
+                    if octet & (0b1000_0000 >> bit) > 0 {
+                        types.push((window << 8) | (index * 8 + bit) as u16);
+                    }
+                }
+            }
+        }
+
+        Ok(Self::new(types))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+
+    // A, MX, RRSIG, NSEC and TYPE1234, from the NSEC example in RFC 4034 section 4.3
+    fn example_bitmap() -> Vec<u8> {
+        let mut bitmap = vec![0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03, 0x04, 0x1b];
+        bitmap.extend([0x00; 26]);
+        bitmap.push(0x20);
+        bitmap
+    }
+
+    #[test]
+    fn type_bitmap_encodes_one_window_per_high_octet() {
+        let mut buf = BytesMut::new();
+
+        TypeBitmap::new(vec![1234, 47, 1, 46, 15, 1]).encode(&mut buf);
+
+        assert_eq!(buf.to_vec(), example_bitmap());
+    }
+
+    #[test]
+    fn type_bitmap_decodes_what_it_encodes() {
+        let bitmap = TypeBitmap::decode(&mut Bytes::from(example_bitmap())).unwrap();
+
+        assert_eq!(bitmap.types(), [1, 15, 46, 47, 1234]);
+        assert!(bitmap.contains(1234));
+        assert!(!bitmap.contains(2));
+
+        let types = vec![0, 255, 256, 65535];
+        let mut buf = BytesMut::new();
+
+        TypeBitmap::new(types.clone()).encode(&mut buf);
+
+        let decoded = TypeBitmap::decode(&mut buf.freeze()).unwrap();
+
+        assert_eq!(decoded.types(), types);
+    }
+
+    #[test]
+    fn type_bitmap_rejects_invalid_windows() {
+        for bitmap in [
+            vec![0x00],
+            vec![0x00, 0x00],
+            vec![0x00, 0x21],
+            vec![0x00, 0x02, 0x40],
+        ] {
+            assert!(TypeBitmap::decode(&mut Bytes::from(bitmap)).is_err());
+        }
+    }
+
+    // The key of dskey.example.com in RFC 4034 section 5.4
+    #[test]
+    fn key_tag_matches_rfc_example() {
+        let dnskey = Dnskey {
+            flags: 256,
+            protocol: 3,
+            algorithm: 5,
+            public_key: STANDARD
+                .decode(
+                    "AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZDRD9\
+                     9WYwYqUSdjMmmAphXdvxegXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9XzcnOf+EPbt\
+                     G9DMBmADjFDc2w/rljwvFw==",
+                )
+                .unwrap(),
+        };
+
+        assert_eq!(dnskey.key_tag(), 60485);
+    }
+}
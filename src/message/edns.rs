@@ -0,0 +1,248 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::types::DomainName;
+use crate::error::ServerError;
+
+/// Type number of the OPT pseudo-record.
+pub const EDNS_OPT_TYPE: u16 = 41;
+
+/// Option code of an Extended DNS Error.
+pub const EDE_OPTION_CODE: u16 = 15;
+
+/// Largest UDP payload the server sends or accepts when EDNS is in use. It is small enough to
+/// avoid IP fragmentation on most paths.
+pub const EDNS_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// Extension mechanisms for DNS, carried in the OPT pseudo-record of the additional section. The
+/// record reuses the CLASS field for the UDP payload size and the TTL field for the extended
+/// RCODE, the version and the flags.
+///
+/// https://www.rfc-editor.org/rfc/rfc6891#section-6.1
+#[derive(Debug, Clone)]
+pub struct Edns {
+    /// Largest UDP payload the sender can reassemble.
+    pub udp_payload_size: u16,
+    /// Upper 8 bits of the 12 bit extended RCODE.
+    pub extended_rcode: u8,
+    pub version: u8,
+    /// DO bit, the sender can handle DNSSEC records (RFC 3225).
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+impl Edns {
+    pub fn new(dnssec_ok: bool) -> Self {
+        Self {
+            udp_payload_size: EDNS_UDP_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok,
+            options: Vec::new(),
+        }
+    }
+}
+
+/// Why a request failed, sent as an EDNS option of SERVFAIL and REFUSED responses. The extra
+/// text is meant for people debugging the failure.
+///
+/// https://www.rfc-editor.org/rfc/rfc8914#section-2
+#[derive(Debug, Clone)]
+pub struct ExtendedError {
+    pub info_code: InfoCode,
+    pub extra_text: String,
+}
+
+impl ExtendedError {
+    pub fn new(info_code: InfoCode, extra_text: &str) -> Self {
+        Self {
+            info_code,
+            extra_text: extra_text.to_string(),
+        }
+    }
+
+    pub fn to_option(&self) -> EdnsOption {
+        let mut data = Vec::with_capacity(2 + self.extra_text.len());
+
+        data.extend_from_slice(&u16::from(self.info_code).to_be_bytes());
+        data.extend_from_slice(self.extra_text.as_bytes());
+
+        EdnsOption {
+            code: EDE_OPTION_CODE,
+            data,
+        }
+    }
+}
+
+/// INFO-CODE values of the Extended DNS Errors this server sends.
+///
+/// https://www.rfc-editor.org/rfc/rfc8914#section-4
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InfoCode {
+    /// 6 the answer failed DNSSEC validation
+    DnssecBogus,
+    /// 18 the client is not allowed to make the request
+    Prohibited,
+    /// 20 the server is not authoritative for the name and does not resolve it
+    NotAuthoritative,
+    /// 21 the request is understood but not supported
+    NotSupported,
+    /// 22 the resolver did not answer in time
+    NoReachableAuthority,
+    /// 23 the resolver could not be reached or sent an unusable response
+    NetworkError,
+}
+
+impl From<InfoCode> for u16 {
+    fn from(value: InfoCode) -> Self {
+        match value {
+            InfoCode::DnssecBogus => 6,
+            InfoCode::Prohibited => 18,
+            InfoCode::NotAuthoritative => 20,
+            InfoCode::NotSupported => 21,
+            InfoCode::NoReachableAuthority => 22,
+            InfoCode::NetworkError => 23,
+        }
+    }
+}
+
+pub struct EdnsEncoder;
+
+impl EdnsEncoder {
+    pub fn encode(edns: &Edns) -> Bytes {
+        let mut buf = BytesMut::new();
+        let mut data = BytesMut::new();
+
+        for option in &edns.options {
+            data.put_u16(option.code);
+            data.put_u16(option.data.len() as u16);
+            data.put(&option.data[..]);
+        }
+
+        // The owner is always the root domain
+        buf.put(DomainName::encode(""));
+        buf.put_u16(EDNS_OPT_TYPE);
+        buf.put_u16(edns.udp_payload_size);
+        buf.put_u8(edns.extended_rcode);
+        buf.put_u8(edns.version);
+        buf.put_u16(if edns.dnssec_ok { 1 << 15 } else { 0 });
+        buf.put_u16(data.len() as u16);
+        buf.put(data);
+
+        Bytes::from(buf)
+    }
+}
+
+pub struct EdnsDecoder<'a> {
+    buf: &'a mut Bytes,
+}
+
+impl<'a> EdnsDecoder<'a> {
+    pub fn new(buf: &'a mut Bytes) -> Self {
+        Self { buf }
+    }
+
+    pub fn decode(self) -> Result<Edns, ServerError> {
+        DomainName::decode(self.buf).map_err(ServerError::DecodeEdns)?;
+
+        // The type has already been checked by the caller
+        self.buf.advance(2);
+
+        let udp_payload_size = self.buf.get_u16();
+        let extended_rcode = self.buf.get_u8();
+        let version = self.buf.get_u8();
+        let flags = self.buf.get_u16();
+        let length = self.buf.get_u16() as usize;
+
+        if self.buf.remaining() < length {
+            return Err(ServerError::DecodeEdns(format!(
+                "record data is {} bytes long but only {} bytes are left",
+                length,
+                self.buf.remaining()
+            )));
+        }
+
+        let mut data = self.buf.split_to(length);
+        let mut options = Vec::new();
+
+        while data.has_remaining() {
+            if data.remaining() < 4 {
+                return Err(ServerError::DecodeEdns("option is truncated".to_string()));
+            }
+
+            let code = data.get_u16();
+            let option_length = data.get_u16() as usize;
+
+            if data.remaining() < option_length {
+                return Err(ServerError::DecodeEdns(format!(
+                    "option {} is {} bytes long but only {} bytes are left",
+                    code,
+                    option_length,
+                    data.remaining()
+                )));
+            }
+
+            options.push(EdnsOption {
+                code,
+                data: data.split_to(option_length).to_vec(),
+            });
+        }
+
+        Ok(Edns {
+            udp_payload_size,
+            extended_rcode,
+            version,
+            dnssec_ok: flags & (1 << 15) > 0,
+            options,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::decode_hex;
+
+    #[test]
+    fn extended_errors_are_sent_as_an_opt_option() {
+        let mut edns = Edns::new(false);
+        edns.options
+            .push(ExtendedError::new(InfoCode::NoReachableAuthority, "timed out").to_option());
+
+        // Root owner, type 41, payload size 1232, no flags, then option 15 with INFO-CODE 22
+        let encoded = EdnsEncoder::encode(&edns);
+        let expected = "00 0029 04D0 00 00 0000 000F 000F 000B 0016 74696D6564206F7574";
+
+        assert_eq!(
+            encoded.to_vec(),
+            decode_hex(&expected.replace(' ', "")).unwrap()
+        );
+
+        let decoded = EdnsDecoder::new(&mut encoded.clone()).decode().unwrap();
+
+        assert_eq!(decoded.options.len(), 1);
+        assert_eq!(decoded.options[0].code, EDE_OPTION_CODE);
+        assert_eq!(decoded.options[0].data, b"\x00\x16timed out");
+    }
+
+    #[test]
+    fn info_codes_are_the_registered_values() {
+        assert_eq!(
+            [
+                InfoCode::DnssecBogus,
+                InfoCode::Prohibited,
+                InfoCode::NotAuthoritative,
+                InfoCode::NotSupported,
+                InfoCode::NoReachableAuthority,
+                InfoCode::NetworkError,
+            ]
+            .map(u16::from),
+            [6, 18, 20, 21, 22, 23]
+        );
+    }
+}
@@ -1,6 +1,7 @@
-use std::u16;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use ring::rand::{SecureRandom, SystemRandom};
 
 use crate::error::ServerError;
 
@@ -34,9 +35,15 @@ pub struct Header {
     /// Server sets this to 1 to indicate that recursion is available.
     pub recursion_available: bool,
 
-    ///	Used by DNSSEC queries. At inception, it was reserved for future use.
+    /// The Z bit, reserved for future use. Must be 0.
     pub reserve: u8,
 
+    /// Server sets this to 1 when every record in the response was validated with DNSSEC.
+    pub authentic_data: bool,
+
+    /// Sender sets this to 1 to ask the server not to validate the response with DNSSEC.
+    pub checking_disabled: bool,
+
     /// Response code indicating the status of the response.
     pub code: ResponseCode,
 
@@ -53,6 +60,21 @@ pub struct Header {
     pub additional_record_count: u16,
 }
 
+/// An ID for a query this server sends. An unpredictable one makes spoofing the response
+/// harder.
+pub fn random_id() -> u16 {
+    let mut id = [0; 2];
+
+    if SystemRandom::new().fill(&mut id).is_err() {
+        id = (SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.subsec_nanos()) as u16)
+            .to_be_bytes();
+    }
+
+    u16::from_be_bytes(id)
+}
+
 // A four bit field that specifies kind of query in this
 // message.  This value is set by the originator of a query
 // and copied into the response.  The values are:
@@ -63,21 +85,29 @@ pub struct Header {
 //
 // 2               a server status request (STATUS)
 //
-// 3-15            reserved for future use
+// 4               a zone change notification (NOTIFY, RFC 1996)
+//
+// 5               a dynamic update (UPDATE, RFC 2136)
+//
+// 3, 6-15         reserved for future use
 #[derive(Debug, Clone, Copy)]
 pub enum OperationCode {
     StandardQuery,
     InverseQuery,
     ServerStatusRequest,
+    Notify,
+    Update,
     Reserve(u8),
 }
 
-impl Into<u8> for OperationCode {
-    fn into(self) -> u8 {
-        match self {
+impl From<OperationCode> for u8 {
+    fn from(value: OperationCode) -> Self {
+        match value {
             OperationCode::StandardQuery => 0,
             OperationCode::InverseQuery => 1,
             OperationCode::ServerStatusRequest => 2,
+            OperationCode::Notify => 4,
+            OperationCode::Update => 5,
             OperationCode::Reserve(num) => num,
         }
     }
@@ -91,7 +121,9 @@ impl TryFrom<u8> for OperationCode {
             0 => Ok(OperationCode::StandardQuery),
             1 => Ok(OperationCode::InverseQuery),
             2 => Ok(OperationCode::ServerStatusRequest),
-            3..=15 => Ok(OperationCode::Reserve(value)),
+            4 => Ok(OperationCode::Notify),
+            5 => Ok(OperationCode::Update),
+            3 | 6..=15 => Ok(OperationCode::Reserve(value)),
             num => Err(ServerError::DecodeHeader(format!(
                 "{} is not a valid operation code",
                 num
@@ -128,7 +160,25 @@ impl TryFrom<u8> for OperationCode {
 //                                 a particular operation (e.g., zone
 //                                 transfer) for particular data.
 //
-//                 6-15            Reserved for future use.
+//                 6               YXDomain - A name that should not
+//                                 exist does exist (RFC 2136).
+//
+//                 7               YXRRSet - An RRset that should not
+//                                 exist does exist (RFC 2136).
+//
+//                 8               NXRRSet - An RRset that should exist
+//                                 does not exist (RFC 2136).
+//
+//                 9               Not Authorized - The server is not
+//                                 authoritative for the zone, or the
+//                                 request failed TSIG verification
+//                                 (RFC 2136, RFC 8945).
+//
+//                 10              NotZone - A name used in the
+//                                 prerequisite or update section is not
+//                                 within the zone (RFC 2136).
+//
+//                 11-15           Reserved for future use.
 #[derive(Debug, Clone, Copy)]
 pub enum ResponseCode {
     NoErrorCondition,
@@ -137,18 +187,28 @@ pub enum ResponseCode {
     NameError,
     NotImplemented,
     Refused,
+    YXDomain,
+    YXRRSet,
+    NXRRSet,
+    NotAuth,
+    NotZone,
     Reserved(u8),
 }
 
-impl Into<u8> for ResponseCode {
-    fn into(self) -> u8 {
-        match self {
+impl From<ResponseCode> for u8 {
+    fn from(value: ResponseCode) -> Self {
+        match value {
             ResponseCode::NoErrorCondition => 0,
             ResponseCode::FormatError => 1,
             ResponseCode::ServerFailure => 2,
             ResponseCode::NameError => 3,
             ResponseCode::NotImplemented => 4,
             ResponseCode::Refused => 5,
+            ResponseCode::YXDomain => 6,
+            ResponseCode::YXRRSet => 7,
+            ResponseCode::NXRRSet => 8,
+            ResponseCode::NotAuth => 9,
+            ResponseCode::NotZone => 10,
             ResponseCode::Reserved(num) => num,
         }
     }
@@ -165,7 +225,12 @@ impl TryFrom<u8> for ResponseCode {
             3 => Ok(ResponseCode::NameError),
             4 => Ok(ResponseCode::NotImplemented),
             5 => Ok(ResponseCode::Refused),
-            6..=15 => Ok(ResponseCode::Reserved(value)),
+            6 => Ok(ResponseCode::YXDomain),
+            7 => Ok(ResponseCode::YXRRSet),
+            8 => Ok(ResponseCode::NXRRSet),
+            9 => Ok(ResponseCode::NotAuth),
+            10 => Ok(ResponseCode::NotZone),
+            11..=15 => Ok(ResponseCode::Reserved(value)),
             num => Err(ServerError::DecodeHeader(format!(
                 "{} is not a valid response code",
                 num
@@ -185,7 +250,7 @@ impl HeaderEncoder {
         let mut third_byte: u8 = 0;
 
         if header.query_indicator {
-            third_byte = third_byte | 1 << 7;
+            third_byte |= 1 << 7;
         }
 
         // It shifts the bits from operation code u8 3 positions because we want to keep the first
@@ -198,18 +263,18 @@ impl HeaderEncoder {
         // assert!(third_byte_with_query_indicator | operation_code_mask, b"1111_1000");
         let operation_code_num: u8 = header.operation_code.into();
         let operation_code_mask = operation_code_num << 3;
-        third_byte = third_byte | operation_code_mask;
+        third_byte |= operation_code_mask;
 
         if header.auth_answer {
-            third_byte = third_byte | 1 << 2;
+            third_byte |= 1 << 2;
         }
 
         if header.truncation {
-            third_byte = third_byte | 1 << 1;
+            third_byte |= 1 << 1;
         }
 
         if header.recursion_desired {
-            third_byte = third_byte | 1 << 0;
+            third_byte |= 1 << 0;
         }
 
         buf.put_u8(third_byte);
@@ -217,14 +282,27 @@ impl HeaderEncoder {
         let mut fourth_byte: u8 = 0;
 
         if header.recursion_available {
-            fourth_byte = fourth_byte | 1 << 7;
+            fourth_byte |= 1 << 7;
+        }
+
+        // The reserved bit sits right after the recursion available bit, followed by the DNSSEC
+        // authentic data and checking disabled bits (RFC 4035)
+        fourth_byte |= (header.reserve & 0b0000_0001) << 6;
+
+        if header.authentic_data {
+            fourth_byte |= 1 << 5;
+        }
+
+        if header.checking_disabled {
+            fourth_byte |= 1 << 4;
         }
 
         // Here we do not need to shift any bit as we did with the operation code because the first
-        // four bits are already taken by the recursion available (1 bit) and the reserved (3 bits)
+        // four bits are already taken by the recursion available, reserved, authentic data and
+        // checking disabled bits
         let response_code_mask: u8 = header.code.into();
 
-        fourth_byte = fourth_byte | response_code_mask;
+        fourth_byte |= response_code_mask;
 
         buf.put_u8(fourth_byte);
 
@@ -241,13 +319,21 @@ pub struct HeaderDecoder;
 
 impl HeaderDecoder {
     pub fn decode(buf: &mut Bytes) -> Result<Header, ServerError> {
+        if buf.remaining() < DNS_HEADER_LEN {
+            return Err(ServerError::DecodeHeader(format!(
+                "header is {} bytes long, got {}",
+                DNS_HEADER_LEN,
+                buf.remaining()
+            )));
+        }
+
         let mut buf = buf.copy_to_bytes(DNS_HEADER_LEN);
 
         let id = buf.get_u16();
 
         let third_byte = buf.get_u8();
 
-        let query_indicator = third_byte & 0b0000_0001 > 0;
+        let query_indicator = third_byte & 0b1000_0000 > 0;
 
         let operation_code_mask = (third_byte & 0b0111_1000) >> 3;
         let operation_code = OperationCode::try_from(operation_code_mask)?;
@@ -259,8 +345,11 @@ impl HeaderDecoder {
         let fourth_byte = buf.get_u8();
 
         let recursion_available = fourth_byte & 0b1000_0000 > 0;
+        let reserve = (fourth_byte & 0b0100_0000) >> 6;
+        let authentic_data = fourth_byte & 0b0010_0000 > 0;
+        let checking_disabled = fourth_byte & 0b0001_0000 > 0;
 
-        let code_mask = fourth_byte >> 4;
+        let code_mask = fourth_byte & 0b0000_1111;
         let code = ResponseCode::try_from(code_mask)?;
 
         let question_count = buf.get_u16();
@@ -276,7 +365,9 @@ impl HeaderDecoder {
             truncation,
             recursion_desired,
             recursion_available,
-            reserve: 0,
+            reserve,
+            authentic_data,
+            checking_disabled,
             code,
             question_count,
             answer_record_count,
@@ -3,8 +3,11 @@ use bytes::{BufMut, Bytes, BytesMut};
 use super::{
     answer::{Answer, AnswersDecoder, AnswersEncoder},
     constants::DNS_MESSAGE_PACKET_SIZE,
-    header::{Header, HeaderDecoder, HeaderEncoder},
+    edns::{Edns, EdnsEncoder},
+    header::{Header, HeaderDecoder, HeaderEncoder, OperationCode},
     question::{Question, QuestionsDecoder, QuestionsEncoder},
+    section::SectionDecoder,
+    tsig::{Tsig, TsigEncoder},
 };
 use crate::error::ServerError;
 
@@ -14,6 +17,12 @@ pub struct Message {
     pub header: Header,
     pub questions: Vec<Question>,
     pub answers: Vec<Answer>,
+    pub authorities: Vec<Answer>,
+    pub additionals: Vec<Answer>,
+    /// EDNS OPT pseudo-record, sent in the additional section.
+    pub edns: Option<Edns>,
+    /// Transaction signature, sent as the last record of the additional section.
+    pub tsig: Option<Tsig>,
 }
 
 pub struct MessageEncoder;
@@ -39,6 +48,28 @@ impl MessageEncoder {
             buf.put(answers);
         }
 
+        if !&message.authorities.is_empty() {
+            let answers_encoder = AnswersEncoder;
+            let authorities = answers_encoder.encode(&message.authorities);
+
+            buf.put(authorities);
+        }
+
+        if !&message.additionals.is_empty() {
+            let answers_encoder = AnswersEncoder;
+            let additionals = answers_encoder.encode(&message.additionals);
+
+            buf.put(additionals);
+        }
+
+        if let Some(edns) = &message.edns {
+            buf.put(EdnsEncoder::encode(edns));
+        }
+
+        if let Some(tsig) = &message.tsig {
+            buf.put(TsigEncoder::encode(tsig));
+        }
+
         Bytes::from(buf)
     }
 }
@@ -46,30 +77,79 @@ impl MessageEncoder {
 pub struct MessageDecoder;
 
 impl MessageDecoder {
-    pub fn decode(buf: &[u8; DNS_MESSAGE_PACKET_SIZE]) -> Result<Message, ServerError> {
-        let mut buf = Bytes::copy_from_slice(buf);
+    pub fn decode(buf: &[u8]) -> Result<Message, ServerError> {
+        let message = Bytes::copy_from_slice(buf);
+        let mut buf = message.clone();
 
         let header = HeaderDecoder::decode(&mut buf)?;
         let mut questions = Vec::with_capacity(header.question_count as usize);
         let mut answers = Vec::with_capacity(header.answer_record_count as usize);
+        let mut authorities = Vec::new();
+        let mut additionals = Vec::new();
+        let mut edns = None;
+        let mut tsig = None;
 
         if header.question_count > 0 {
-            let questions_decoder = QuestionsDecoder::new(&mut buf, header.question_count);
+            let questions_decoder =
+                QuestionsDecoder::new(&mut buf, header.question_count, message.len());
             let decoded_questions = questions_decoder.decode()?;
 
             questions = decoded_questions;
         }
 
+        // UPDATE messages reuse the answer and authority sections for prerequisites and updates,
+        // which carry the ANY and NONE classes that are not valid record classes. Those records
+        // are skipped the same way unsupported additional records are, UpdateDecoder reads
+        // these sections in full.
+        let is_update = matches!(header.operation_code, OperationCode::Update);
+
         if header.answer_record_count > 0 {
-            let answers_decoder = AnswersDecoder::new(&mut buf, header.answer_record_count);
-            let decoded_answers = answers_decoder.decode()?;
+            if is_update {
+                let section_decoder =
+                    SectionDecoder::new(&mut buf, &message, header.answer_record_count);
+
+                answers = section_decoder.decode()?.records;
+            } else {
+                let answers_decoder =
+                    AnswersDecoder::new(&mut buf, &message, header.answer_record_count);
+                let decoded_answers = answers_decoder.decode()?;
+
+                answers = decoded_answers;
+            }
+        }
+
+        if header.auth_record_count > 0 {
+            let section_decoder = SectionDecoder::new(&mut buf, &message, header.auth_record_count);
+
+            let section = section_decoder.decode()?;
 
-            answers = decoded_answers;
+            if section.tsig.is_some() {
+                return Err(ServerError::DecodeTsig(
+                    "TSIG record is not in the additional section".to_string(),
+                ));
+            }
+
+            authorities = section.records;
         }
+
+        if header.additional_record_count > 0 {
+            let section_decoder =
+                SectionDecoder::new(&mut buf, &message, header.additional_record_count);
+            let section = section_decoder.decode()?;
+
+            additionals = section.records;
+            edns = section.edns;
+            tsig = section.tsig;
+        }
+
         Ok(Message {
             header,
             questions,
             answers,
+            authorities,
+            additionals,
+            edns,
+            tsig,
         })
     }
 }
@@ -1,6 +1,14 @@
 pub mod answer;
 pub mod constants;
+pub mod dnssec;
+pub mod edns;
 pub mod header;
+#[allow(clippy::module_inception)]
 pub mod message;
 pub mod question;
+pub mod rdata;
+pub mod section;
+pub mod svcb;
+pub mod tsig;
 pub mod types;
+pub mod update;
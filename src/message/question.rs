@@ -1,10 +1,7 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::collections::HashMap;
 
-use super::{
-    constants::DNS_MESSAGE_PACKET_SIZE,
-    types::{DnsClass, DnsType, DomainLabel, DomainName},
-};
+use super::types::{DnsClass, DnsType, DomainLabel, DomainName};
 use crate::error::ServerError;
 
 #[derive(Debug)]
@@ -60,6 +57,9 @@ pub struct Question {
 #[derive(Debug, Clone, Copy)]
 pub enum QuestionType {
     DnsType(DnsType),
+    /// 251 A request for an incremental transfer of a zone (RFC 1995)
+    IXFR,
+    /// 252 A request for a transfer of an entire zone
     AXFR,
     /// 253 A request for mailbox-related records (MB, MG or MR)
     MAILB,
@@ -69,10 +69,11 @@ pub enum QuestionType {
     ALL,
 }
 
-impl Into<u16> for QuestionType {
-    fn into(self) -> u16 {
-        match self {
+impl From<QuestionType> for u16 {
+    fn from(value: QuestionType) -> Self {
+        match value {
             QuestionType::DnsType(dns_type) => dns_type.into(),
+            QuestionType::IXFR => 251,
             QuestionType::AXFR => 252,
             QuestionType::MAILB => 253,
             QuestionType::MAILA => 254,
@@ -86,6 +87,7 @@ impl TryFrom<u16> for QuestionType {
 
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
+            251 => Ok(QuestionType::IXFR),
             252 => Ok(QuestionType::AXFR),
             253 => Ok(QuestionType::MAILB),
             254 => Ok(QuestionType::MAILA),
@@ -99,6 +101,14 @@ impl TryFrom<u16> for QuestionType {
     }
 }
 
+impl QuestionType {
+    /// Zone transfers (AXFR and IXFR) are answered with the records of a whole zone or its
+    /// changes, instead of a lookup.
+    pub fn is_zone_transfer(&self) -> bool {
+        matches!(self, QuestionType::IXFR | QuestionType::AXFR)
+    }
+}
+
 /// QCLASS fields appear in the question section of a query.  QCLASS values
 /// are a superset of CLASS values; every CLASS is a valid QCLASS.  In
 /// addition to CLASS values, the following QCLASSes are defined:
@@ -108,9 +118,9 @@ pub enum QuestionClass {
     ALL,
 }
 
-impl Into<u16> for QuestionClass {
-    fn into(self) -> u16 {
-        match self {
+impl From<QuestionClass> for u16 {
+    fn from(value: QuestionClass) -> Self {
+        match value {
             QuestionClass::DnsClass(dns_class) => dns_class.into(),
             QuestionClass::ALL => 255,
         }
@@ -148,19 +158,8 @@ impl QuestionsEncoder {
 
     fn encode_question(&self, question: &Question) -> Bytes {
         let mut buf = BytesMut::new();
-        let mut encoded_name = BytesMut::new();
-        let question_parts = question.name.split(".");
 
-        for part in question_parts {
-            let label_length: u8 = part.len() as u8;
-
-            encoded_name.put_u8(label_length);
-            encoded_name.put(part.as_bytes());
-        }
-
-        encoded_name.put_u8(0);
-
-        buf.put(encoded_name);
+        buf.put(DomainName::encode(&question.name));
 
         buf.put_u16(question.kind.into());
 
@@ -178,14 +177,18 @@ impl QuestionsEncoder {
 pub struct QuestionsDecoder<'a> {
     buf: &'a mut Bytes,
     questions_count: u16,
+    message_len: usize,
     domain_names: QuestionDomainNames,
 }
 
 impl<'a> QuestionsDecoder<'a> {
-    pub fn new(buf: &'a mut Bytes, questions_count: u16) -> Self {
+    /// `message_len` is the length of the whole message, used to work out the position of each
+    /// label.
+    pub fn new(buf: &'a mut Bytes, questions_count: u16, message_len: usize) -> Self {
         Self {
             buf,
             questions_count,
+            message_len,
             domain_names: QuestionDomainNames::default(),
         }
     }
@@ -206,6 +209,7 @@ impl<'a> QuestionsDecoder<'a> {
         let mut domain_name = DomainName::default();
 
         loop {
+            self.check_remaining(1, "domain name")?;
             let label_length = self.buf.get_u8();
 
             if label_length == 0 {
@@ -213,6 +217,7 @@ impl<'a> QuestionsDecoder<'a> {
             }
 
             if self.is_pointer(label_length) {
+                self.check_remaining(1, "compression pointer")?;
                 let pointer = self.buf.get_u8();
 
                 match self.domain_names.get_labels_by_pointer(pointer as usize) {
@@ -229,6 +234,8 @@ impl<'a> QuestionsDecoder<'a> {
                 };
             }
 
+            self.check_remaining(label_length as usize, "label")?;
+
             let pointer_position = self.get_cursor_position();
             let bytes = self.buf.copy_to_bytes(label_length as usize);
             let label = std::str::from_utf8(&bytes[..])
@@ -241,6 +248,8 @@ impl<'a> QuestionsDecoder<'a> {
             domain_name.add_label(domain_label);
         }
 
+        self.check_remaining(4, "type and class")?;
+
         let name = domain_name.to_string();
         let kind = QuestionType::try_from(self.buf.get_u16())?;
         let class = QuestionClass::try_from(self.buf.get_u16())?;
@@ -250,16 +259,45 @@ impl<'a> QuestionsDecoder<'a> {
         Ok(Question { name, kind, class })
     }
 
+    // A question cut short is malformed, reading past the end of the message would panic
+    fn check_remaining(&self, length: usize, field: &str) -> Result<(), ServerError> {
+        if self.buf.remaining() < length {
+            return Err(ServerError::DecodeQuestion(format!(
+                "{} is truncated",
+                field
+            )));
+        }
+
+        Ok(())
+    }
+
     // When the first two bits are ones, we know that it is a pointer.
     // This allows a pointer to be distinguished from a label, since the
     // label must begin with two zero bits because labels are restricted to 63 octets or less.
     fn is_pointer(&self, byte: u8) -> bool {
-        byte & 0b1100_000 > 0
+        byte & 0b1100_0000 > 0
     }
 
     // Gets the buffer cursor positions, which it's used when compressing domain names. The
     // cursor position is used as a pointer to a specific domain label.
     fn get_cursor_position(&self) -> usize {
-        (DNS_MESSAGE_PACKET_SIZE - self.buf.remaining()) - 1
+        (self.message_len - self.buf.remaining()) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_questions_are_errors() {
+        for input in [&b"\x03www\x07exa"[..], b"\x03www\x00\x00", b"\xc0", b""] {
+            let mut buf = Bytes::copy_from_slice(input);
+
+            assert!(matches!(
+                QuestionsDecoder::new(&mut buf, 1, input.len()).decode(),
+                Err(ServerError::DecodeQuestion(_))
+            ));
+        }
     }
 }
@@ -0,0 +1,803 @@
+use std::{
+    cmp::Ordering,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::{
+    dnssec::{Dnskey, Ds, Nsec, Nsec3, Nsec3Param, Rrsig, TypeBitmap},
+    svcb::{SvcParamsDecoder, SvcParamsEncoder, Svcb},
+    types::{DnsType, DomainName},
+};
+use crate::error::ServerError;
+
+/// Data specific to the record type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    /// NS, CNAME, DNAME, PTR and the obsolete MD, MF, MB, MG and MR records, whose data is a single
+    /// domain name.
+    DomainName(String),
+    Soa(Soa),
+    Mx(Mx),
+    Srv(Srv),
+    Naptr(Naptr),
+    Uri(Uri),
+    Loc(Loc),
+    /// TXT and HINFO, whose data is a sequence of character-strings of up to 255 bytes each.
+    /// They are arbitrary bytes, not necessarily text.
+    Strings(Vec<Vec<u8>>),
+    Sshfp(Sshfp),
+    Tlsa(Tlsa),
+    Caa(Caa),
+    /// SVCB and HTTPS
+    Svcb(Svcb),
+    /// DNSKEY and CDNSKEY
+    Dnskey(Dnskey),
+    /// DS and CDS
+    Ds(Ds),
+    Rrsig(Rrsig),
+    Nsec(Nsec),
+    Nsec3(Nsec3),
+    Nsec3Param(Nsec3Param),
+    /// Data of the types that are not modelled yet, kept as received so it can be relayed.
+    Raw(Vec<u8>),
+}
+
+/// Start of a zone of authority.
+///
+/// https://www.rfc-editor.org/rfc/rfc1035#section-3.3.13
+#[derive(Debug, Clone, PartialEq)]
+pub struct Soa {
+    /// Primary name server of the zone.
+    pub mname: String,
+    /// Mailbox of the person responsible for the zone, the first label being the local part.
+    pub rname: String,
+    pub serial: Serial,
+    /// Seconds before secondaries check the serial again.
+    pub refresh: u32,
+    /// Seconds before secondaries retry a failed refresh.
+    pub retry: u32,
+    /// Seconds after which secondaries stop answering when they cannot refresh.
+    pub expire: u32,
+    /// TTL of negative responses (RFC 2308).
+    pub minimum: u32,
+}
+
+/// Version number of a zone. Serials wrap around 2^32, so they are compared and incremented
+/// with serial number arithmetic, in which some pairs of values are not comparable.
+///
+/// https://www.rfc-editor.org/rfc/rfc1982
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Serial(pub u32);
+
+impl Serial {
+    /// The next serial, wrapping from 2^32 - 1 to 0.
+    pub fn increment(self) -> Serial {
+        Serial(self.0.wrapping_add(1))
+    }
+}
+
+impl PartialOrd for Serial {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match other.0.wrapping_sub(self.0) {
+            0 => Some(Ordering::Equal),
+            // Exactly half the number space apart, neither is greater
+            distance if distance == 1 << 31 => None,
+            distance if distance < 1 << 31 => Some(Ordering::Less),
+            _ => Some(Ordering::Greater),
+        }
+    }
+}
+
+/// Host willing to act as a mail exchange for the owner name.
+///
+/// https://www.rfc-editor.org/rfc/rfc1035#section-3.3.9
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mx {
+    /// Lower values are preferred.
+    pub preference: u16,
+    pub exchange: String,
+}
+
+/// Location of a service, owned by a `_service._proto.name` domain name.
+///
+/// https://www.rfc-editor.org/rfc/rfc2782
+#[derive(Debug, Clone, PartialEq)]
+pub struct Srv {
+    /// Clients try the targets with the lowest priority first.
+    pub priority: u16,
+    /// Relative share of the load among targets of the same priority.
+    pub weight: u16,
+    pub port: u16,
+    /// The root domain means the service is not available at this domain.
+    pub target: String,
+}
+
+/// Rewrite rule of a Dynamic Delegation Discovery System application, e.g. mapping a phone
+/// number to a SIP URI.
+///
+/// https://www.rfc-editor.org/rfc/rfc3403#section-4.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Naptr {
+    /// Rules are applied in increasing order.
+    pub order: u16,
+    /// Among rules of the same order, lower values are preferred.
+    pub preference: u16,
+    /// Character-string of flags controlling the rewriting, e.g. `U` for a terminal URI.
+    pub flags: Vec<u8>,
+    /// Character-string naming the service and protocol, e.g. `E2U+sip`.
+    pub services: Vec<u8>,
+    /// Character-string holding a substitution expression, applied to the client's string.
+    pub regexp: Vec<u8>,
+    /// Domain name to query next when there is no regular expression, the root domain otherwise.
+    pub replacement: String,
+}
+
+/// URI of a service, owned by a `_service._proto.name` domain name.
+///
+/// https://www.rfc-editor.org/rfc/rfc7553#section-4.5
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uri {
+    /// Clients try the targets with the lowest priority first.
+    pub priority: u16,
+    /// Relative share of the load among targets of the same priority.
+    pub weight: u16,
+    /// The URI itself, filling the rest of the record data.
+    pub target: Vec<u8>,
+}
+
+/// Geographical location of the owner, with the size of the located object and the precision
+/// of the position. The fields are kept in their wire encoding.
+///
+/// https://www.rfc-editor.org/rfc/rfc1876#section-2
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loc {
+    /// Diameter of a sphere enclosing the object, in centimeters as a mantissa in the high
+    /// nibble and a power of ten in the low nibble.
+    pub size: u8,
+    /// Horizontal precision, encoded like the size.
+    pub horizontal_precision: u8,
+    /// Vertical precision, encoded like the size.
+    pub vertical_precision: u8,
+    /// Thousandths of an arc second, with 2^31 at the equator.
+    pub latitude: u32,
+    /// Thousandths of an arc second, with 2^31 at the prime meridian.
+    pub longitude: u32,
+    /// Centimeters from a base 100000 meters below the WGS 84 reference spheroid.
+    pub altitude: u32,
+}
+
+/// Fingerprint of the SSH public key of the owner host.
+///
+/// https://www.rfc-editor.org/rfc/rfc4255#section-3.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sshfp {
+    /// Public key algorithm, e.g. 1 for RSA or 4 for Ed25519.
+    pub algorithm: u8,
+    /// Digest used for the fingerprint, 1 for SHA-1 and 2 for SHA-256 (RFC 6594).
+    pub fingerprint_type: u8,
+    pub fingerprint: Vec<u8>,
+}
+
+/// Certificate or public key a TLS server must present, owned by a `_port._proto.name` domain
+/// name (DANE).
+///
+/// https://www.rfc-editor.org/rfc/rfc6698#section-2.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tlsa {
+    /// Whether the data matches a trust anchor (0 and 2) or the server certificate (1 and 3).
+    pub usage: u8,
+    /// 0 for the full certificate, 1 for its public key.
+    pub selector: u8,
+    /// 0 for the data itself, 1 for its SHA-256 digest and 2 for its SHA-512 digest.
+    pub matching_type: u8,
+    pub data: Vec<u8>,
+}
+
+/// Property restricting which certification authorities may issue certificates for the owner
+/// name.
+///
+/// https://www.rfc-editor.org/rfc/rfc8659#section-4.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caa {
+    /// The high bit marks the property as critical: authorities that do not understand it
+    /// must not issue.
+    pub flags: u8,
+    /// Property name such as `issue`, `issuewild` or `iodef`, up to 15 ASCII letters and digits.
+    pub tag: String,
+    pub value: Vec<u8>,
+}
+
+impl Caa {
+    pub fn is_valid_tag(tag: &str) -> bool {
+        (1..=15).contains(&tag.len()) && tag.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+}
+
+impl RData {
+    /// Name whose address records belong in the additional section when the record is sent.
+    pub fn target_name(&self) -> Option<&str> {
+        match self {
+            RData::Mx(mx) if !mx.exchange.is_empty() => Some(&mx.exchange),
+            RData::Srv(srv) if !srv.target.is_empty() => Some(&srv.target),
+            RData::Svcb(svcb) if !svcb.target.is_empty() => Some(&svcb.target),
+            _ => None,
+        }
+    }
+}
+
+pub struct RDataEncoder;
+
+impl RDataEncoder {
+    pub fn encode(data: &RData) -> Bytes {
+        Self::encode_data(data, false)
+    }
+
+    /// Canonical form used when signing and validating RRsets. Domain names inside the data of
+    /// the types listed in RFC 4034 section 6.2, as amended by RFC 6840 section 5.1, are
+    /// lowercased.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc4034#section-6.2
+    pub fn encode_canonical(data: &RData) -> Bytes {
+        Self::encode_data(data, true)
+    }
+
+    fn encode_data(data: &RData, canonical: bool) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        match data {
+            RData::A(addr) => buf.put(&addr.octets()[..]),
+            RData::Aaaa(addr) => buf.put(&addr.octets()[..]),
+            RData::DomainName(name) => {
+                if canonical {
+                    buf.put(DomainName::encode(&name.to_lowercase()));
+                } else {
+                    buf.put(DomainName::encode(name));
+                }
+            }
+            RData::Soa(soa) => {
+                if canonical {
+                    buf.put(DomainName::encode(&soa.mname.to_lowercase()));
+                    buf.put(DomainName::encode(&soa.rname.to_lowercase()));
+                } else {
+                    buf.put(DomainName::encode(&soa.mname));
+                    buf.put(DomainName::encode(&soa.rname));
+                }
+
+                buf.put_u32(soa.serial.0);
+                buf.put_u32(soa.refresh);
+                buf.put_u32(soa.retry);
+                buf.put_u32(soa.expire);
+                buf.put_u32(soa.minimum);
+            }
+            RData::Mx(mx) => {
+                buf.put_u16(mx.preference);
+
+                if canonical {
+                    buf.put(DomainName::encode(&mx.exchange.to_lowercase()));
+                } else {
+                    buf.put(DomainName::encode(&mx.exchange));
+                }
+            }
+            RData::Srv(srv) => {
+                buf.put_u16(srv.priority);
+                buf.put_u16(srv.weight);
+                buf.put_u16(srv.port);
+
+                if canonical {
+                    buf.put(DomainName::encode(&srv.target.to_lowercase()));
+                } else {
+                    buf.put(DomainName::encode(&srv.target));
+                }
+            }
+            RData::Naptr(naptr) => {
+                buf.put_u16(naptr.order);
+                buf.put_u16(naptr.preference);
+
+                for string in [&naptr.flags, &naptr.services, &naptr.regexp] {
+                    buf.put_u8(string.len() as u8);
+                    buf.put(&string[..]);
+                }
+
+                if canonical {
+                    buf.put(DomainName::encode(&naptr.replacement.to_lowercase()));
+                } else {
+                    buf.put(DomainName::encode(&naptr.replacement));
+                }
+            }
+            RData::Uri(uri) => {
+                buf.put_u16(uri.priority);
+                buf.put_u16(uri.weight);
+                buf.put(&uri.target[..]);
+            }
+            RData::Loc(loc) => {
+                // Version 0 is the only one defined
+                buf.put_u8(0);
+                buf.put_u8(loc.size);
+                buf.put_u8(loc.horizontal_precision);
+                buf.put_u8(loc.vertical_precision);
+                buf.put_u32(loc.latitude);
+                buf.put_u32(loc.longitude);
+                buf.put_u32(loc.altitude);
+            }
+            RData::Strings(strings) => {
+                for string in strings {
+                    buf.put_u8(string.len() as u8);
+                    buf.put(&string[..]);
+                }
+            }
+            RData::Sshfp(sshfp) => {
+                buf.put_u8(sshfp.algorithm);
+                buf.put_u8(sshfp.fingerprint_type);
+                buf.put(&sshfp.fingerprint[..]);
+            }
+            RData::Tlsa(tlsa) => {
+                buf.put_u8(tlsa.usage);
+                buf.put_u8(tlsa.selector);
+                buf.put_u8(tlsa.matching_type);
+                buf.put(&tlsa.data[..]);
+            }
+            RData::Caa(caa) => {
+                buf.put_u8(caa.flags);
+                buf.put_u8(caa.tag.len() as u8);
+                buf.put(caa.tag.as_bytes());
+                buf.put(&caa.value[..]);
+            }
+            // The target keeps its case, SVCB is not one of the types listed for canonical form
+            RData::Svcb(svcb) => {
+                buf.put_u16(svcb.priority);
+                buf.put(DomainName::encode(&svcb.target));
+                SvcParamsEncoder::encode(&svcb.params, &mut buf);
+            }
+            RData::Dnskey(dnskey) => {
+                buf.put_u16(dnskey.flags);
+                buf.put_u8(dnskey.protocol);
+                buf.put_u8(dnskey.algorithm);
+                buf.put(&dnskey.public_key[..]);
+            }
+            RData::Ds(ds) => {
+                buf.put_u16(ds.key_tag);
+                buf.put_u8(ds.algorithm);
+                buf.put_u8(ds.digest_type);
+                buf.put(&ds.digest[..]);
+            }
+            RData::Rrsig(rrsig) => {
+                buf.put_u16(rrsig.type_covered);
+                buf.put_u8(rrsig.algorithm);
+                buf.put_u8(rrsig.labels);
+                buf.put_u32(rrsig.original_ttl);
+                buf.put_u32(rrsig.expiration);
+                buf.put_u32(rrsig.inception);
+                buf.put_u16(rrsig.key_tag);
+
+                if canonical {
+                    buf.put(DomainName::encode(&rrsig.signer_name.to_lowercase()));
+                } else {
+                    buf.put(DomainName::encode(&rrsig.signer_name));
+                }
+
+                buf.put(&rrsig.signature[..]);
+            }
+            // The next domain name keeps its case, RFC 6840 removed NSEC from the list of types
+            // whose names are lowercased
+            RData::Nsec(nsec) => {
+                buf.put(DomainName::encode(&nsec.next_domain_name));
+                nsec.types.encode(&mut buf);
+            }
+            RData::Nsec3(nsec3) => {
+                buf.put_u8(nsec3.hash_algorithm);
+                buf.put_u8(nsec3.flags);
+                buf.put_u16(nsec3.iterations);
+                buf.put_u8(nsec3.salt.len() as u8);
+                buf.put(&nsec3.salt[..]);
+                buf.put_u8(nsec3.next_hashed_owner_name.len() as u8);
+                buf.put(&nsec3.next_hashed_owner_name[..]);
+                nsec3.types.encode(&mut buf);
+            }
+            RData::Nsec3Param(param) => {
+                buf.put_u8(param.hash_algorithm);
+                buf.put_u8(param.flags);
+                buf.put_u16(param.iterations);
+                buf.put_u8(param.salt.len() as u8);
+                buf.put(&param.salt[..]);
+            }
+            RData::Raw(data) => buf.put(&data[..]),
+        }
+
+        Bytes::from(buf)
+    }
+}
+
+/// Names inside the data of the types defined in RFC 1035 may be compressed, `message` holds the
+/// whole message so compression pointers can be followed. Names of newer types never are.
+pub struct RDataDecoder<'a> {
+    buf: &'a mut Bytes,
+    message: &'a Bytes,
+    kind: DnsType,
+    length: u16,
+}
+
+impl<'a> RDataDecoder<'a> {
+    pub fn new(buf: &'a mut Bytes, message: &'a Bytes, kind: DnsType, length: u16) -> Self {
+        Self {
+            buf,
+            message,
+            kind,
+            length,
+        }
+    }
+
+    pub fn decode(self) -> Result<RData, ServerError> {
+        if self.buf.remaining() < self.length as usize {
+            return Err(ServerError::DecodeAnswer(format!(
+                "record data is {} bytes long but only {} bytes are left",
+                self.length,
+                self.buf.remaining()
+            )));
+        }
+
+        // Decoding works on a copy of the record data, so a malformed record cannot read past it
+        let mut data = self.buf.split_to(self.length as usize);
+
+        let rdata = match self.kind {
+            DnsType::A => {
+                let octets: [u8; 4] = data[..].try_into().map_err(|_| {
+                    ServerError::DecodeAnswer(format!(
+                        "A record data must be 4 bytes long, got {}",
+                        data.len()
+                    ))
+                })?;
+
+                RData::A(Ipv4Addr::from(octets))
+            }
+            DnsType::AAAA => {
+                let octets: [u8; 16] = data[..].try_into().map_err(|_| {
+                    ServerError::DecodeAnswer(format!(
+                        "AAAA record data must be 16 bytes long, got {}",
+                        data.len()
+                    ))
+                })?;
+
+                RData::Aaaa(Ipv6Addr::from(octets))
+            }
+            DnsType::NS
+            | DnsType::CNAME
+            | DnsType::PTR
+            | DnsType::DNAME
+            | DnsType::MD
+            | DnsType::MF
+            | DnsType::MB
+            | DnsType::MG
+            | DnsType::MR => {
+                let name = DomainName::decode_compressed(&mut data, self.message)
+                    .map_err(ServerError::DecodeAnswer)?;
+
+                RData::DomainName(name.to_string())
+            }
+            DnsType::SOA => {
+                Self::check_length(&data, 22)?;
+
+                let mname = DomainName::decode_compressed(&mut data, self.message)
+                    .map_err(ServerError::DecodeAnswer)?;
+                let rname = DomainName::decode_compressed(&mut data, self.message)
+                    .map_err(ServerError::DecodeAnswer)?;
+
+                Self::check_length(&data, 20)?;
+
+                RData::Soa(Soa {
+                    mname: mname.to_string(),
+                    rname: rname.to_string(),
+                    serial: Serial(data.get_u32()),
+                    refresh: data.get_u32(),
+                    retry: data.get_u32(),
+                    expire: data.get_u32(),
+                    minimum: data.get_u32(),
+                })
+            }
+            DnsType::MX => {
+                Self::check_length(&data, 3)?;
+
+                let preference = data.get_u16();
+                let exchange = DomainName::decode_compressed(&mut data, self.message)
+                    .map_err(ServerError::DecodeAnswer)?;
+
+                RData::Mx(Mx {
+                    preference,
+                    exchange: exchange.to_string(),
+                })
+            }
+            DnsType::SRV => {
+                Self::check_length(&data, 7)?;
+
+                let priority = data.get_u16();
+                let weight = data.get_u16();
+                let port = data.get_u16();
+
+                // The target must not be compressed, but older servers do it anyway
+                let target = DomainName::decode_compressed(&mut data, self.message)
+                    .map_err(ServerError::DecodeAnswer)?;
+
+                RData::Srv(Srv {
+                    priority,
+                    weight,
+                    port,
+                    target: target.to_string(),
+                })
+            }
+            // The replacement is never compressed (RFC 3403 section 4.1)
+            DnsType::NAPTR => {
+                Self::check_length(&data, 7)?;
+
+                RData::Naptr(Naptr {
+                    order: data.get_u16(),
+                    preference: data.get_u16(),
+                    flags: Self::decode_length_prefixed(&mut data)?,
+                    services: Self::decode_length_prefixed(&mut data)?,
+                    regexp: Self::decode_length_prefixed(&mut data)?,
+                    replacement: Self::decode_name(&mut data)?,
+                })
+            }
+            DnsType::URI => {
+                Self::check_length(&data, 4)?;
+
+                RData::Uri(Uri {
+                    priority: data.get_u16(),
+                    weight: data.get_u16(),
+                    target: data.to_vec(),
+                })
+            }
+            // Other versions have an unknown format and are relayed as they are
+            DnsType::LOC if data.first() == Some(&0) => {
+                Self::check_length(&data, 16)?;
+
+                data.advance(1);
+
+                RData::Loc(Loc {
+                    size: data.get_u8(),
+                    horizontal_precision: data.get_u8(),
+                    vertical_precision: data.get_u8(),
+                    latitude: data.get_u32(),
+                    longitude: data.get_u32(),
+                    altitude: data.get_u32(),
+                })
+            }
+            DnsType::DNSKEY | DnsType::CDNSKEY => {
+                Self::check_length(&data, 4)?;
+
+                RData::Dnskey(Dnskey {
+                    flags: data.get_u16(),
+                    protocol: data.get_u8(),
+                    algorithm: data.get_u8(),
+                    public_key: data.to_vec(),
+                })
+            }
+            DnsType::TXT | DnsType::HINFO => {
+                let mut strings = Vec::new();
+
+                while data.has_remaining() {
+                    strings.push(Self::decode_length_prefixed(&mut data)?);
+                }
+
+                RData::Strings(strings)
+            }
+            DnsType::SSHFP => {
+                Self::check_length(&data, 2)?;
+
+                RData::Sshfp(Sshfp {
+                    algorithm: data.get_u8(),
+                    fingerprint_type: data.get_u8(),
+                    fingerprint: data.to_vec(),
+                })
+            }
+            DnsType::TLSA => {
+                Self::check_length(&data, 3)?;
+
+                RData::Tlsa(Tlsa {
+                    usage: data.get_u8(),
+                    selector: data.get_u8(),
+                    matching_type: data.get_u8(),
+                    data: data.to_vec(),
+                })
+            }
+            DnsType::CAA => {
+                Self::check_length(&data, 2)?;
+
+                let flags = data.get_u8();
+                let tag = Self::decode_length_prefixed(&mut data)?;
+                let tag = String::from_utf8(tag)
+                    .ok()
+                    .filter(|tag| Caa::is_valid_tag(tag))
+                    .ok_or_else(|| {
+                        ServerError::DecodeAnswer("CAA property tag is not valid".to_string())
+                    })?;
+
+                RData::Caa(Caa {
+                    flags,
+                    tag,
+                    value: data.to_vec(),
+                })
+            }
+            // The target name is never compressed (RFC 9460 section 2.2). Records with parameters
+            // that do not validate are kept as opaque data (RFC 3597), so they are still served
+            // and transferred as published, clients ignore the ones they cannot use.
+            DnsType::SVCB | DnsType::HTTPS => {
+                Self::check_length(&data, 3)?;
+
+                let rdata = data.clone();
+                let priority = data.get_u16();
+                let target = Self::decode_name(&mut data)?;
+
+                match SvcParamsDecoder::decode(&mut data) {
+                    Ok(params) => RData::Svcb(Svcb {
+                        priority,
+                        target,
+                        params,
+                    }),
+                    Err(_) => RData::Raw(rdata.to_vec()),
+                }
+            }
+            DnsType::DS | DnsType::CDS => {
+                Self::check_length(&data, 4)?;
+
+                RData::Ds(Ds {
+                    key_tag: data.get_u16(),
+                    algorithm: data.get_u8(),
+                    digest_type: data.get_u8(),
+                    digest: data.to_vec(),
+                })
+            }
+            DnsType::RRSIG => {
+                Self::check_length(&data, 18)?;
+
+                let type_covered = data.get_u16();
+                let algorithm = data.get_u8();
+                let labels = data.get_u8();
+                let original_ttl = data.get_u32();
+                let expiration = data.get_u32();
+                let inception = data.get_u32();
+                let key_tag = data.get_u16();
+                let signer_name = Self::decode_name(&mut data)?;
+
+                RData::Rrsig(Rrsig {
+                    type_covered,
+                    algorithm,
+                    labels,
+                    original_ttl,
+                    expiration,
+                    inception,
+                    key_tag,
+                    signer_name,
+                    signature: data.to_vec(),
+                })
+            }
+            DnsType::NSEC => {
+                let next_domain_name = Self::decode_name(&mut data)?;
+                let types = TypeBitmap::decode(&mut data)?;
+
+                RData::Nsec(Nsec {
+                    next_domain_name,
+                    types,
+                })
+            }
+            DnsType::NSEC3 => {
+                Self::check_length(&data, 5)?;
+
+                let hash_algorithm = data.get_u8();
+                let flags = data.get_u8();
+                let iterations = data.get_u16();
+                let salt = Self::decode_length_prefixed(&mut data)?;
+                let next_hashed_owner_name = Self::decode_length_prefixed(&mut data)?;
+                let types = TypeBitmap::decode(&mut data)?;
+
+                RData::Nsec3(Nsec3 {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed_owner_name,
+                    types,
+                })
+            }
+            DnsType::NSEC3PARAM => {
+                Self::check_length(&data, 5)?;
+
+                let hash_algorithm = data.get_u8();
+                let flags = data.get_u8();
+                let iterations = data.get_u16();
+                let salt = Self::decode_length_prefixed(&mut data)?;
+
+                RData::Nsec3Param(Nsec3Param {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                })
+            }
+            _ => RData::Raw(data.to_vec()),
+        };
+
+        Ok(rdata)
+    }
+
+    fn check_length(data: &Bytes, min_length: usize) -> Result<(), ServerError> {
+        if data.len() < min_length {
+            return Err(ServerError::DecodeAnswer(format!(
+                "record data must be at least {} bytes long, got {}",
+                min_length,
+                data.len()
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn decode_name(data: &mut Bytes) -> Result<String, ServerError> {
+        if !data.has_remaining() {
+            return Err(ServerError::DecodeAnswer(
+                "domain name is missing from the record data".to_string(),
+            ));
+        }
+
+        let domain_name =
+            DomainName::decode(data).map_err(|err| ServerError::DecodeAnswer(err.to_string()))?;
+
+        Ok(domain_name.to_string())
+    }
+
+    // Salt and hash fields are prefixed by a single length octet
+    fn decode_length_prefixed(data: &mut Bytes) -> Result<Vec<u8>, ServerError> {
+        if !data.has_remaining() {
+            return Err(ServerError::DecodeAnswer(
+                "length prefixed field is missing".to_string(),
+            ));
+        }
+
+        let length = data.get_u8() as usize;
+
+        if data.remaining() < length {
+            return Err(ServerError::DecodeAnswer(format!(
+                "field is {} bytes long but only {} bytes are left",
+                length,
+                data.remaining()
+            )));
+        }
+
+        Ok(data.split_to(length).to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serials_compare_across_wraparound() {
+        assert!(Serial(1) < Serial(2));
+        assert!(Serial(u32::MAX) < Serial(0));
+        assert!(Serial(0) > Serial(u32::MAX));
+        assert!(Serial(u32::MAX - 10) < Serial(10));
+        assert_eq!(Serial(7).partial_cmp(&Serial(7)), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn serials_half_the_number_space_apart_are_not_comparable() {
+        let serial = Serial(5);
+        let opposite = Serial(5 + (1 << 31));
+
+        assert_eq!(serial.partial_cmp(&opposite), None);
+        assert_eq!(opposite.partial_cmp(&serial), None);
+
+        // One less than half the number space is still comparable
+        assert!(serial < Serial(5 + (1 << 31) - 1));
+    }
+
+    #[test]
+    fn serial_increment_wraps_to_zero() {
+        assert_eq!(Serial(41).increment(), Serial(42));
+        assert_eq!(Serial(u32::MAX).increment(), Serial(0));
+        assert!(Serial(u32::MAX) < Serial(u32::MAX).increment());
+    }
+}
@@ -0,0 +1,96 @@
+use bytes::{Buf, Bytes};
+
+use super::{
+    answer::{Answer, AnswersDecoder},
+    edns::{Edns, EdnsDecoder, EDNS_OPT_TYPE},
+    tsig::{Tsig, TsigDecoder},
+    types::{DnsClass, DnsType, DomainName},
+};
+use crate::error::ServerError;
+
+/// Records found in the authority or additional section of a message.
+#[derive(Debug, Default)]
+pub struct SectionRecords {
+    pub records: Vec<Answer>,
+    /// Only present in the additional section.
+    pub edns: Option<Edns>,
+    /// Only present in the additional section, where it must be the last record.
+    pub tsig: Option<Tsig>,
+}
+
+/// Decodes the authority and additional sections. Unlike the answer section, records of a type
+/// or class the server does not model are skipped instead of failing the whole message, and the
+/// EDNS OPT pseudo-record and TSIG record are split out from the rest.
+pub struct SectionDecoder<'a> {
+    buf: &'a mut Bytes,
+    message: &'a Bytes,
+    records_count: u16,
+}
+
+impl<'a> SectionDecoder<'a> {
+    /// `message` holds the whole message, used to follow compression pointers and to work out
+    /// the offset of each record.
+    pub fn new(buf: &'a mut Bytes, message: &'a Bytes, records_count: u16) -> Self {
+        Self {
+            buf,
+            message,
+            records_count,
+        }
+    }
+
+    pub fn decode(self) -> Result<SectionRecords, ServerError> {
+        let mut section = SectionRecords::default();
+
+        for _ in 0..self.records_count {
+            let offset = self.message.len() - self.buf.remaining();
+
+            if section.tsig.is_some() {
+                return Err(ServerError::DecodeTsig(
+                    "TSIG record is not the last record of the message".to_string(),
+                ));
+            }
+
+            // Peek the record type and class, which come right after the owner name
+            let mut record = self.buf.clone();
+            DomainName::decode(&mut record)
+                .map_err(|err| ServerError::DecodeAnswer(err.to_string()))?;
+
+            if record.remaining() < 10 {
+                return Err(ServerError::DecodeAnswer(
+                    "record header is truncated".to_string(),
+                ));
+            }
+
+            let kind = record.get_u16();
+            let class = record.get_u16();
+
+            if kind == EDNS_OPT_TYPE {
+                section.edns = Some(EdnsDecoder::new(self.buf).decode()?);
+            } else if kind == DnsType::TSIG.into() {
+                section.tsig = Some(TsigDecoder::new(self.buf, offset).decode()?);
+            } else if DnsType::try_from(kind).is_ok() && DnsClass::try_from(class).is_ok() {
+                let answer = AnswersDecoder::new(self.buf, self.message, 1).decode_answer()?;
+
+                section.records.push(answer);
+            } else {
+                // TTL followed by the record data
+                record.advance(4);
+                let length = record.get_u16() as usize;
+
+                if record.remaining() < length {
+                    return Err(ServerError::DecodeAnswer(format!(
+                        "record data is {} bytes long but only {} bytes are left",
+                        length,
+                        record.remaining()
+                    )));
+                }
+
+                record.advance(length);
+
+                *self.buf = record;
+            }
+        }
+
+        Ok(section)
+    }
+}
@@ -0,0 +1,407 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::error::ServerError;
+
+// SvcParamKey values and their presentation names
+//
+// 0 mandatory
+// 1 alpn
+// 2 no-default-alpn
+// 3 port
+// 4 ipv4hint
+// 5 ech
+// 6 ipv6hint
+//
+// https://www.rfc-editor.org/rfc/rfc9460#section-14.3.2
+const KEY_NAMES: [&str; 7] = [
+    "mandatory",
+    "alpn",
+    "no-default-alpn",
+    "port",
+    "ipv4hint",
+    "ech",
+    "ipv6hint",
+];
+
+/// Service binding, the data of SVCB and HTTPS records.
+///
+/// https://www.rfc-editor.org/rfc/rfc9460#section-2.2
+#[derive(Debug, Clone, PartialEq)]
+pub struct Svcb {
+    /// 0 for AliasMode, where the target is an alias of the owner and there are no parameters.
+    /// In ServiceMode, clients try the records with the lowest priority first.
+    pub priority: u16,
+    /// In ServiceMode, the root domain means the owner name itself.
+    pub target: String,
+    /// Sorted by key, without duplicates.
+    pub params: Vec<SvcParam>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SvcParam {
+    /// Keys the client must understand to use the record.
+    Mandatory(Vec<u16>),
+    /// Application protocols supported by the service, e.g. `h2` or `h3`.
+    Alpn(Vec<Vec<u8>>),
+    /// The service does not support the default protocol of the scheme, e.g. `http/1.1`.
+    NoDefaultAlpn,
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    /// Encrypted ClientHello configuration list.
+    Ech(Vec<u8>),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    /// Keys without a model, kept as received.
+    Unknown(u16, Vec<u8>),
+}
+
+impl SvcParam {
+    pub fn key(&self) -> u16 {
+        match self {
+            SvcParam::Mandatory(_) => 0,
+            SvcParam::Alpn(_) => 1,
+            SvcParam::NoDefaultAlpn => 2,
+            SvcParam::Port(_) => 3,
+            SvcParam::Ipv4Hint(_) => 4,
+            SvcParam::Ech(_) => 5,
+            SvcParam::Ipv6Hint(_) => 6,
+            SvcParam::Unknown(key, _) => *key,
+        }
+    }
+
+    /// Presentation name of a key, `key<number>` for keys without one.
+    pub fn key_name(key: u16) -> String {
+        KEY_NAMES
+            .get(key as usize)
+            .map_or_else(|| format!("key{}", key), |name| name.to_string())
+    }
+
+    pub fn parse_key(name: &str) -> Option<u16> {
+        match KEY_NAMES.iter().position(|key_name| *key_name == name) {
+            Some(key) => Some(key as u16),
+            None => name.strip_prefix("key")?.parse().ok(),
+        }
+    }
+
+    fn encode_value(&self, buf: &mut BytesMut) {
+        match self {
+            SvcParam::Mandatory(keys) => keys.iter().for_each(|key| buf.put_u16(*key)),
+            SvcParam::Alpn(ids) => {
+                for id in ids {
+                    buf.put_u8(id.len() as u8);
+                    buf.put(&id[..]);
+                }
+            }
+            SvcParam::NoDefaultAlpn => {}
+            SvcParam::Port(port) => buf.put_u16(*port),
+            SvcParam::Ipv4Hint(addrs) => addrs.iter().for_each(|addr| buf.put(&addr.octets()[..])),
+            SvcParam::Ech(config) => buf.put(&config[..]),
+            SvcParam::Ipv6Hint(addrs) => addrs.iter().for_each(|addr| buf.put(&addr.octets()[..])),
+            SvcParam::Unknown(_, value) => buf.put(&value[..]),
+        }
+    }
+
+    fn decode_value(key: u16, mut value: Bytes) -> Result<Self, ServerError> {
+        let invalid =
+            || ServerError::DecodeAnswer(format!("{} value is not valid", Self::key_name(key)));
+
+        let param = match key {
+            0 if !value.is_empty() && value.len() % 2 == 0 => SvcParam::Mandatory(
+                value
+                    .chunks(2)
+                    .map(|key| u16::from_be_bytes([key[0], key[1]]))
+                    .collect(),
+            ),
+            1 => {
+                let mut ids = Vec::new();
+
+                while value.has_remaining() {
+                    let length = value.get_u8() as usize;
+
+                    if length == 0 || value.remaining() < length {
+                        return Err(invalid());
+                    }
+
+                    ids.push(value.split_to(length).to_vec());
+                }
+
+                if ids.is_empty() {
+                    return Err(invalid());
+                }
+
+                SvcParam::Alpn(ids)
+            }
+            2 if value.is_empty() => SvcParam::NoDefaultAlpn,
+            3 if value.len() == 2 => SvcParam::Port(value.get_u16()),
+            4 if !value.is_empty() && value.len() % 4 == 0 => SvcParam::Ipv4Hint(
+                value
+                    .chunks(4)
+                    .map(|octets| Ipv4Addr::from(<[u8; 4]>::try_from(octets).unwrap()))
+                    .collect(),
+            ),
+            5 => SvcParam::Ech(value.to_vec()),
+            6 if !value.is_empty() && value.len() % 16 == 0 => SvcParam::Ipv6Hint(
+                value
+                    .chunks(16)
+                    .map(|octets| Ipv6Addr::from(<[u8; 16]>::try_from(octets).unwrap()))
+                    .collect(),
+            ),
+            0..=6 => return Err(invalid()),
+            key => SvcParam::Unknown(key, value.to_vec()),
+        };
+
+        Ok(param)
+    }
+}
+
+/// The keys listed in `mandatory` must be in increasing order without duplicates, must not
+/// include `mandatory` itself and must all be present in the record (RFC 9460 section 8).
+pub fn check_mandatory(params: &[SvcParam]) -> Result<(), String> {
+    let Some(keys) = params.iter().find_map(|param| match param {
+        SvcParam::Mandatory(keys) => Some(keys),
+        _ => None,
+    }) else {
+        return Ok(());
+    };
+
+    if let Some(pair) = keys.windows(2).find(|pair| pair[0] >= pair[1]) {
+        return Err(format!(
+            "mandatory lists {} more than once or out of order",
+            SvcParam::key_name(pair[1])
+        ));
+    }
+
+    for key in keys {
+        if *key == 0 {
+            return Err("mandatory cannot list itself".to_string());
+        }
+
+        if !params.iter().any(|param| param.key() == *key) {
+            return Err(format!(
+                "mandatory lists {}, which is missing from the record",
+                SvcParam::key_name(*key)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Parameters are written as key, value length and value, in increasing key order.
+pub struct SvcParamsEncoder;
+
+impl SvcParamsEncoder {
+    pub fn encode(params: &[SvcParam], buf: &mut BytesMut) {
+        for param in params {
+            let mut value = BytesMut::new();
+
+            param.encode_value(&mut value);
+
+            buf.put_u16(param.key());
+            buf.put_u16(value.len() as u16);
+            buf.put(value);
+        }
+    }
+}
+
+pub struct SvcParamsDecoder;
+
+impl SvcParamsDecoder {
+    pub fn decode(buf: &mut Bytes) -> Result<Vec<SvcParam>, ServerError> {
+        let mut params: Vec<SvcParam> = Vec::new();
+
+        while buf.has_remaining() {
+            if buf.remaining() < 4 {
+                return Err(ServerError::DecodeAnswer(
+                    "service parameter is truncated".to_string(),
+                ));
+            }
+
+            let key = buf.get_u16();
+            let length = buf.get_u16() as usize;
+
+            if params.last().is_some_and(|last| last.key() >= key) {
+                return Err(ServerError::DecodeAnswer(format!(
+                    "service parameter {} is out of order",
+                    SvcParam::key_name(key)
+                )));
+            }
+
+            if buf.remaining() < length {
+                return Err(ServerError::DecodeAnswer(format!(
+                    "service parameter {} is truncated",
+                    SvcParam::key_name(key)
+                )));
+            }
+
+            params.push(SvcParam::decode_value(key, buf.split_to(length))?);
+        }
+
+        check_mandatory(&params).map_err(ServerError::DecodeAnswer)?;
+
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{
+        rdata::{RData, RDataDecoder, RDataEncoder},
+        types::DnsType,
+    };
+    use crate::zone::{decode_hex, ZoneFileParser};
+
+    // Spaces only group the bytes
+    fn hex(data: &str) -> Vec<u8> {
+        decode_hex(&data.split_whitespace().collect::<String>()).unwrap()
+    }
+
+    fn decode(kind: DnsType, data: &str) -> RData {
+        let data = Bytes::from(hex(data));
+
+        RDataDecoder::new(&mut data.clone(), &data, kind, data.len() as u16)
+            .decode()
+            .unwrap()
+    }
+
+    // A record of the test vectors of RFC 9460 appendix D.2 in presentation and wire format
+    fn check_vector(record: &str, wire: &str) {
+        let zone = ZoneFileParser::new(record, "example.com").parse().unwrap();
+        let data = &zone.records[0].data;
+
+        assert_eq!(RDataEncoder::encode(data), hex(wire), "{}", record);
+        assert_eq!(&decode(DnsType::SVCB, wire), data, "{}", record);
+    }
+
+    #[test]
+    fn rfc_9460_test_vectors() {
+        check_vector(
+            r#"@ SVCB 1 foo.example.com. key667=hello"#,
+            "0001 03666F6F076578616D706C6503636F6D00 029B 0005 68656C6C6F",
+        );
+        check_vector(
+            r#"@ SVCB 1 foo.example.com. key667="hello\210qoo""#,
+            "0001 03666F6F076578616D706C6503636F6D00 029B 0009 68656C6C6FD2716F6F",
+        );
+        check_vector(
+            r#"@ SVCB 16 foo.example.org. alpn=h2,h3-19 mandatory=ipv4hint,alpn ipv4hint=192.0.2.1"#,
+            "0010 03666F6F076578616D706C65036F726700 0000 0004 00010004 \
+             0001 0009 026832 0568332D3139 0004 0004 C0000201",
+        );
+        check_vector(
+            r#"@ SVCB 16 foo.example.org. alpn="f\\\\oo\\,bar,h2""#,
+            "0010 03666F6F076578616D706C65036F726700 0001 000C 08665C6F6F2C626172 026832",
+        );
+        check_vector(
+            r#"@ SVCB 16 foo.example.org. alpn=f\\\092oo\092,bar,h2"#,
+            "0010 03666F6F076578616D706C65036F726700 0001 000C 08665C6F6F2C626172 026832",
+        );
+    }
+
+    #[test]
+    fn alpn_ids_are_escaped_twice_when_written() {
+        let input = r#"@ HTTPS 1 . alpn="f\\\\oo\\,bar,h2""#;
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+
+        assert_eq!(
+            zone.records[0].data,
+            RData::Svcb(Svcb {
+                priority: 1,
+                target: String::new(),
+                params: vec![SvcParam::Alpn(vec![b"f\\oo,bar".to_vec(), b"h2".to_vec()])],
+            })
+        );
+        assert_eq!(
+            zone.to_string(),
+            "example.com. 3600 IN HTTPS 1 . alpn=\"f\\\\\\\\oo\\\\,bar,h2\"\n"
+        );
+    }
+
+    #[test]
+    fn keys_are_written_in_increasing_order() {
+        let input = "@ HTTPS 1 . port=8443 key65000=x alpn=h2 mandatory=port";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+        let RData::Svcb(svcb) = &zone.records[0].data else {
+            panic!("HTTPS record was not parsed");
+        };
+
+        assert_eq!(
+            svcb.params.iter().map(SvcParam::key).collect::<Vec<_>>(),
+            [0, 1, 3, 65000]
+        );
+
+        // A key given twice is an error rather than one of them winning
+        let input = "@ HTTPS 1 . port=8443 port=443";
+        assert!(ZoneFileParser::new(input, "example.com").parse().is_err());
+    }
+
+    #[test]
+    fn keys_out_of_order_on_the_wire_are_rejected() {
+        for params in [
+            // port before alpn
+            "0003 0002 01BB 0001 0003 026832",
+            // port twice
+            "0003 0002 01BB 0003 0002 20FB",
+        ] {
+            let mut data = Bytes::from(hex(params));
+
+            assert!(SvcParamsDecoder::decode(&mut data).is_err(), "{}", params);
+        }
+    }
+
+    #[test]
+    fn mandatory_keys_must_be_present_and_in_order() {
+        let port = SvcParam::Port(443);
+        let alpn = SvcParam::Alpn(vec![b"h2".to_vec()]);
+
+        assert!(
+            check_mandatory(&[SvcParam::Mandatory(vec![1, 3]), alpn.clone(), port.clone()]).is_ok()
+        );
+
+        for params in [
+            vec![SvcParam::Mandatory(vec![3, 1]), alpn.clone(), port.clone()],
+            vec![SvcParam::Mandatory(vec![3, 3]), port.clone()],
+            vec![SvcParam::Mandatory(vec![0, 3]), port.clone()],
+            vec![SvcParam::Mandatory(vec![1, 3]), port.clone()],
+        ] {
+            assert!(check_mandatory(&params).is_err(), "{:?}", params);
+        }
+    }
+
+    #[test]
+    fn unknown_keys_use_the_key_number_form() {
+        assert_eq!(SvcParam::key_name(6), "ipv6hint");
+        assert_eq!(SvcParam::key_name(65000), "key65000");
+        assert_eq!(SvcParam::parse_key("alpn"), Some(1));
+        assert_eq!(SvcParam::parse_key("key1"), Some(1));
+        assert_eq!(SvcParam::parse_key("key65535"), Some(65535));
+        assert_eq!(SvcParam::parse_key("key65536"), None);
+        assert_eq!(SvcParam::parse_key("keyx"), None);
+
+        let mut params = BytesMut::new();
+        SvcParamsEncoder::encode(&[SvcParam::Unknown(65000, b"x".to_vec())], &mut params);
+
+        assert_eq!(
+            SvcParamsDecoder::decode(&mut params.freeze()).unwrap(),
+            [SvcParam::Unknown(65000, b"x".to_vec())]
+        );
+    }
+
+    #[test]
+    fn records_with_invalid_parameters_are_kept_opaque() {
+        // A port value of 3 bytes, and mandatory listing a key the record lacks
+        for data in [
+            "0001 00 0003 0003 01BB00",
+            "0001 00 0000 0002 0003 0001 0003 026832",
+        ] {
+            assert_eq!(
+                decode(DnsType::HTTPS, data),
+                RData::Raw(hex(data)),
+                "{}",
+                data
+            );
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::{
+    question::QuestionClass,
+    types::{DnsType, DomainName},
+};
+use crate::error::ServerError;
+
+/// The TSIG record carries a message authentication code for the whole message it is attached
+/// to. It is always the last record of the additional section.
+///
+/// https://www.rfc-editor.org/rfc/rfc8945#section-4.2
+#[derive(Debug, Clone)]
+pub struct Tsig {
+    /// Name of the key used to compute the MAC.
+    pub key_name: String,
+    /// Name of the MAC algorithm, e.g. `hmac-sha256`.
+    pub algorithm: String,
+    /// Seconds since the epoch when the message was signed. Only the lower 48 bits are sent.
+    pub time_signed: u64,
+    /// Seconds of error permitted in `time_signed`.
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    /// ID of the message before any forwarder rewrote it.
+    pub original_id: u16,
+    /// Extended RCODE covering TSIG processing.
+    pub error: TsigErrorCode,
+    pub other_data: Vec<u8>,
+    /// Offset of the record in the message it was decoded from, as the MAC covers every byte
+    /// before it. It is 0 for records built locally.
+    pub offset: usize,
+}
+
+// TSIG specific error codes, sent in the error field of the record. The values are:
+//
+// 0               No error
+//
+// 16              BADSIG - TSIG signature failure
+//
+// 17              BADKEY - Key not recognized
+//
+// 18              BADTIME - Signature out of time window
+//
+// 22              BADTRUNC - Bad truncation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TsigErrorCode {
+    NoError,
+    BadSignature,
+    BadKey,
+    BadTime,
+    BadTruncation,
+    Other(u16),
+}
+
+impl From<TsigErrorCode> for u16 {
+    fn from(value: TsigErrorCode) -> Self {
+        match value {
+            TsigErrorCode::NoError => 0,
+            TsigErrorCode::BadSignature => 16,
+            TsigErrorCode::BadKey => 17,
+            TsigErrorCode::BadTime => 18,
+            TsigErrorCode::BadTruncation => 22,
+            TsigErrorCode::Other(num) => num,
+        }
+    }
+}
+
+impl From<u16> for TsigErrorCode {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => TsigErrorCode::NoError,
+            16 => TsigErrorCode::BadSignature,
+            17 => TsigErrorCode::BadKey,
+            18 => TsigErrorCode::BadTime,
+            22 => TsigErrorCode::BadTruncation,
+            num => TsigErrorCode::Other(num),
+        }
+    }
+}
+
+pub struct TsigEncoder;
+
+impl TsigEncoder {
+    pub fn encode(tsig: &Tsig) -> Bytes {
+        let mut buf = BytesMut::new();
+        let mut data = BytesMut::new();
+
+        data.put(DomainName::encode(&tsig.algorithm));
+        Self::put_time_signed(&mut data, tsig.time_signed);
+        data.put_u16(tsig.fudge);
+        data.put_u16(tsig.mac.len() as u16);
+        data.put(&tsig.mac[..]);
+        data.put_u16(tsig.original_id);
+        data.put_u16(tsig.error.into());
+        data.put_u16(tsig.other_data.len() as u16);
+        data.put(&tsig.other_data[..]);
+
+        buf.put(DomainName::encode(&tsig.key_name));
+        buf.put_u16(DnsType::TSIG.into());
+        buf.put_u16(QuestionClass::ALL.into());
+        buf.put_u32(0);
+        buf.put_u16(data.len() as u16);
+        buf.put(data);
+
+        Bytes::from(buf)
+    }
+
+    /// Encodes the TSIG variables that are appended to the message when computing the MAC.
+    /// Names are in canonical (lowercase, uncompressed) form.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc8945#section-4.3.3
+    pub fn encode_variables(tsig: &Tsig) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        buf.put(DomainName::encode(&tsig.key_name.to_lowercase()));
+        buf.put_u16(QuestionClass::ALL.into());
+        buf.put_u32(0);
+        buf.put(DomainName::encode(&tsig.algorithm.to_lowercase()));
+        Self::put_time_signed(&mut buf, tsig.time_signed);
+        buf.put_u16(tsig.fudge);
+        buf.put_u16(tsig.error.into());
+        buf.put_u16(tsig.other_data.len() as u16);
+        buf.put(&tsig.other_data[..]);
+
+        Bytes::from(buf)
+    }
+
+    /// Encodes the timers, the only TSIG variables covered by the MAC of the later messages of
+    /// a multi-message response.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc8945#section-5.3.1
+    pub fn encode_timers(tsig: &Tsig) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        Self::put_time_signed(&mut buf, tsig.time_signed);
+        buf.put_u16(tsig.fudge);
+
+        Bytes::from(buf)
+    }
+
+    // Time signed is a 48 bit unsigned integer
+    fn put_time_signed(buf: &mut BytesMut, time_signed: u64) {
+        buf.put_u16((time_signed >> 32) as u16);
+        buf.put_u32(time_signed as u32);
+    }
+}
+
+pub struct TsigDecoder<'a> {
+    buf: &'a mut Bytes,
+    offset: usize,
+}
+
+impl<'a> TsigDecoder<'a> {
+    pub fn new(buf: &'a mut Bytes, offset: usize) -> Self {
+        Self { buf, offset }
+    }
+
+    pub fn decode(self) -> Result<Tsig, ServerError> {
+        let key_name = DomainName::decode(self.buf)
+            .map_err(|err| ServerError::DecodeTsig(err.to_string()))?
+            .to_string();
+
+        // Type, class and TTL are fixed for TSIG records
+        Self::check_remaining(self.buf, 10, "record header")?;
+        self.buf.advance(8);
+
+        let length = self.buf.get_u16() as usize;
+
+        Self::check_remaining(self.buf, length, "record data")?;
+
+        let mut data = self.buf.split_to(length);
+
+        let algorithm = DomainName::decode(&mut data)
+            .map_err(|err| ServerError::DecodeTsig(err.to_string()))?
+            .to_string();
+
+        Self::check_remaining(&data, 10, "time signed, fudge and MAC size")?;
+        let time_signed = ((data.get_u16() as u64) << 32) | data.get_u32() as u64;
+        let fudge = data.get_u16();
+        let mac_size = data.get_u16() as usize;
+
+        Self::check_remaining(&data, mac_size, "MAC")?;
+        let mac = data.split_to(mac_size).to_vec();
+
+        Self::check_remaining(&data, 6, "original ID, error and other length")?;
+        let original_id = data.get_u16();
+        let error = TsigErrorCode::from(data.get_u16());
+        let other_len = data.get_u16() as usize;
+
+        Self::check_remaining(&data, other_len, "other data")?;
+        let other_data = data.split_to(other_len).to_vec();
+
+        Ok(Tsig {
+            key_name,
+            algorithm,
+            time_signed,
+            fudge,
+            mac,
+            original_id,
+            error,
+            other_data,
+            offset: self.offset,
+        })
+    }
+
+    fn check_remaining(buf: &Bytes, length: usize, field: &str) -> Result<(), ServerError> {
+        if buf.remaining() < length {
+            return Err(ServerError::DecodeTsig(format!(
+                "{} needs {} bytes but only {} are left",
+                field,
+                length,
+                buf.remaining()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tsig() -> Tsig {
+        Tsig {
+            key_name: "test-key".to_string(),
+            algorithm: "hmac-sha256".to_string(),
+            // Above 2^32, so both parts of the 48 bit time are used
+            time_signed: 0x0001_2345_6789,
+            fudge: 300,
+            mac: vec![0xab; 32],
+            original_id: 0x1234,
+            error: TsigErrorCode::BadTime,
+            other_data: vec![0, 0, 0x65, 0x53, 0xf1, 0],
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn records_are_decoded_as_encoded() {
+        let mut buf = TsigEncoder::encode(&tsig());
+        let decoded = TsigDecoder::new(&mut buf, 40).decode().unwrap();
+
+        assert_eq!(decoded.key_name, "test-key");
+        assert_eq!(decoded.algorithm, "hmac-sha256");
+        assert_eq!(decoded.time_signed, 0x0001_2345_6789);
+        assert_eq!(decoded.fudge, 300);
+        assert_eq!(decoded.mac, vec![0xab; 32]);
+        assert_eq!(decoded.original_id, 0x1234);
+        assert_eq!(decoded.error, TsigErrorCode::BadTime);
+        assert_eq!(decoded.other_data, tsig().other_data);
+        assert_eq!(decoded.offset, 40);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn error_codes_keep_their_values() {
+        for value in [0, 16, 17, 18, 22, 19] {
+            assert_eq!(u16::from(TsigErrorCode::from(value)), value);
+        }
+
+        assert_eq!(TsigErrorCode::from(16), TsigErrorCode::BadSignature);
+        assert_eq!(TsigErrorCode::from(19), TsigErrorCode::Other(19));
+    }
+
+    #[test]
+    fn timers_are_the_time_signed_and_fudge() {
+        assert_eq!(
+            &TsigEncoder::encode_timers(&tsig())[..],
+            [0x00, 0x01, 0x23, 0x45, 0x67, 0x89, 0x01, 0x2c]
+        );
+    }
+
+    #[test]
+    fn truncated_records_are_errors() {
+        let encoded = TsigEncoder::encode(&tsig());
+
+        for len in [5, 20, 40, encoded.len() - 1] {
+            let mut buf = encoded.slice(..len);
+
+            assert!(matches!(
+                TsigDecoder::new(&mut buf, 0).decode(),
+                Err(ServerError::DecodeTsig(_))
+            ));
+        }
+    }
+}
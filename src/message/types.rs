@@ -1,7 +1,11 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
 use crate::error::ServerError;
 
 /// TYPE fields are used in resource records
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DnsType {
     /// 1 a host address
     A,
@@ -35,11 +39,53 @@ pub enum DnsType {
     MX,
     /// 16 text strings
     TXT,
+    /// 28 an IPv6 host address (RFC 3596)
+    AAAA,
+    /// 29 geographical location (RFC 1876)
+    LOC,
+    /// 33 location of a service (RFC 2782)
+    SRV,
+    /// 35 naming authority pointer, a rewrite rule (RFC 3403)
+    NAPTR,
+    /// 39 redirection of a whole subtree to another name (RFC 6672)
+    DNAME,
+    /// 43 delegation signer (RFC 4034)
+    DS,
+    /// 44 SSH key fingerprint (RFC 4255)
+    SSHFP,
+    /// 46 signature over an RRset (RFC 4034)
+    RRSIG,
+    /// 47 next secure record, authenticated denial of existence (RFC 4034)
+    NSEC,
+    /// 48 public key of a zone (RFC 4034)
+    DNSKEY,
+    /// 50 hashed next secure record (RFC 5155)
+    NSEC3,
+    /// 51 parameters used to build NSEC3 records (RFC 5155)
+    NSEC3PARAM,
+    /// 52 TLS certificate association for DANE (RFC 6698)
+    TLSA,
+    /// 59 child copy of a DS record (RFC 7344)
+    CDS,
+    /// 60 child copy of a DNSKEY record (RFC 7344)
+    CDNSKEY,
+    /// 64 general purpose service binding (RFC 9460)
+    SVCB,
+    /// 65 service binding for HTTPS origins (RFC 9460)
+    HTTPS,
+    /// 250 transaction signature (RFC 8945)
+    TSIG,
+    /// 256 URI of a service (RFC 7553)
+    URI,
+    /// 257 certification authorities allowed to issue certificates (RFC 8659)
+    CAA,
+    /// Any other data type, whose records are passed on with opaque data (RFC 3597)
+    Unknown(u16),
 }
 
-impl Into<u16> for DnsType {
-    fn into(self) -> u16 {
-        match self {
+impl From<DnsType> for u16 {
+    fn from(value: DnsType) -> Self {
+        match value {
             DnsType::A => 1,
             DnsType::NS => 2,
             DnsType::MD => 3,
@@ -56,6 +102,27 @@ impl Into<u16> for DnsType {
             DnsType::MINFO => 14,
             DnsType::MX => 15,
             DnsType::TXT => 16,
+            DnsType::AAAA => 28,
+            DnsType::LOC => 29,
+            DnsType::SRV => 33,
+            DnsType::NAPTR => 35,
+            DnsType::DNAME => 39,
+            DnsType::DS => 43,
+            DnsType::SSHFP => 44,
+            DnsType::RRSIG => 46,
+            DnsType::NSEC => 47,
+            DnsType::DNSKEY => 48,
+            DnsType::NSEC3 => 50,
+            DnsType::NSEC3PARAM => 51,
+            DnsType::TLSA => 52,
+            DnsType::CDS => 59,
+            DnsType::CDNSKEY => 60,
+            DnsType::SVCB => 64,
+            DnsType::HTTPS => 65,
+            DnsType::TSIG => 250,
+            DnsType::URI => 256,
+            DnsType::CAA => 257,
+            DnsType::Unknown(num) => num,
         }
     }
 }
@@ -81,10 +148,97 @@ impl TryFrom<u16> for DnsType {
             14 => Ok(DnsType::MINFO),
             15 => Ok(DnsType::MX),
             16 => Ok(DnsType::TXT),
-            num => Err(ServerError::InvalidDnsType(format!(
+            28 => Ok(DnsType::AAAA),
+            29 => Ok(DnsType::LOC),
+            33 => Ok(DnsType::SRV),
+            35 => Ok(DnsType::NAPTR),
+            39 => Ok(DnsType::DNAME),
+            43 => Ok(DnsType::DS),
+            44 => Ok(DnsType::SSHFP),
+            46 => Ok(DnsType::RRSIG),
+            47 => Ok(DnsType::NSEC),
+            48 => Ok(DnsType::DNSKEY),
+            50 => Ok(DnsType::NSEC3),
+            51 => Ok(DnsType::NSEC3PARAM),
+            52 => Ok(DnsType::TLSA),
+            59 => Ok(DnsType::CDS),
+            60 => Ok(DnsType::CDNSKEY),
+            64 => Ok(DnsType::SVCB),
+            65 => Ok(DnsType::HTTPS),
+            250 => Ok(DnsType::TSIG),
+            256 => Ok(DnsType::URI),
+            257 => Ok(DnsType::CAA),
+            // OPT and the question types only exist on the wire, they never type a record of
+            // data (RFC 6895 section 3.1)
+            num @ (0 | 41 | 128..=249 | 251..=255) => Err(ServerError::InvalidDnsType(format!(
                 "{} is not a valid DNS type",
                 num
             ))),
+            num => Ok(DnsType::Unknown(num)),
+        }
+    }
+}
+
+/// Parses the type mnemonic used in master files.
+impl FromStr for DnsType {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_uppercase().as_str() {
+            "A" => Ok(DnsType::A),
+            "NS" => Ok(DnsType::NS),
+            "MD" => Ok(DnsType::MD),
+            "MF" => Ok(DnsType::MF),
+            "CNAME" => Ok(DnsType::CNAME),
+            "SOA" => Ok(DnsType::SOA),
+            "MB" => Ok(DnsType::MB),
+            "MG" => Ok(DnsType::MG),
+            "MR" => Ok(DnsType::MR),
+            "NULL" => Ok(DnsType::NULL),
+            "WKS" => Ok(DnsType::WKS),
+            "PTR" => Ok(DnsType::PTR),
+            "HINFO" => Ok(DnsType::HINFO),
+            "MINFO" => Ok(DnsType::MINFO),
+            "MX" => Ok(DnsType::MX),
+            "TXT" => Ok(DnsType::TXT),
+            "AAAA" => Ok(DnsType::AAAA),
+            "LOC" => Ok(DnsType::LOC),
+            "SRV" => Ok(DnsType::SRV),
+            "NAPTR" => Ok(DnsType::NAPTR),
+            "DNAME" => Ok(DnsType::DNAME),
+            "DS" => Ok(DnsType::DS),
+            "SSHFP" => Ok(DnsType::SSHFP),
+            "RRSIG" => Ok(DnsType::RRSIG),
+            "NSEC" => Ok(DnsType::NSEC),
+            "DNSKEY" => Ok(DnsType::DNSKEY),
+            "NSEC3" => Ok(DnsType::NSEC3),
+            "NSEC3PARAM" => Ok(DnsType::NSEC3PARAM),
+            "TLSA" => Ok(DnsType::TLSA),
+            "CDS" => Ok(DnsType::CDS),
+            "CDNSKEY" => Ok(DnsType::CDNSKEY),
+            "SVCB" => Ok(DnsType::SVCB),
+            "HTTPS" => Ok(DnsType::HTTPS),
+            "TSIG" => Ok(DnsType::TSIG),
+            "URI" => Ok(DnsType::URI),
+            "CAA" => Ok(DnsType::CAA),
+            // Any type may be written by number (RFC 3597 section 5)
+            name => name
+                .strip_prefix("TYPE")
+                .and_then(|num| num.parse::<u16>().ok())
+                .ok_or_else(|| {
+                    ServerError::InvalidDnsType(format!("{} is not a valid DNS type", name))
+                })
+                .and_then(DnsType::try_from),
+        }
+    }
+}
+
+/// The variant names are the type mnemonics, types without one are written as TYPE<number>.
+impl std::fmt::Display for DnsType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsType::Unknown(num) => write!(f, "TYPE{}", num),
+            _ => write!(f, "{:?}", self),
         }
     }
 }
@@ -100,15 +254,18 @@ pub enum DnsClass {
     CH,
     /// 4 Hesiod [Dyer 87]
     HS,
+    /// Any other class, e.g. a private one (RFC 3597)
+    Unknown(u16),
 }
 
-impl Into<u16> for DnsClass {
-    fn into(self) -> u16 {
-        match self {
+impl From<DnsClass> for u16 {
+    fn from(value: DnsClass) -> Self {
+        match value {
             DnsClass::IN => 1,
             DnsClass::CS => 2,
             DnsClass::CH => 3,
             DnsClass::HS => 4,
+            DnsClass::Unknown(num) => num,
         }
     }
 }
@@ -122,10 +279,44 @@ impl TryFrom<u16> for DnsClass {
             2 => Ok(DnsClass::CS),
             3 => Ok(DnsClass::CH),
             4 => Ok(DnsClass::HS),
-            num => Err(ServerError::InvalidDnsClass(format!(
+            // NONE and ANY only exist in questions and updates
+            num @ (0 | 254 | 255) => Err(ServerError::InvalidDnsClass(format!(
                 "{} is not a valid DNS class",
                 num
             ))),
+            num => Ok(DnsClass::Unknown(num)),
+        }
+    }
+}
+
+/// Parses the class mnemonic used in master files.
+impl FromStr for DnsClass {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_uppercase().as_str() {
+            "IN" => Ok(DnsClass::IN),
+            "CS" => Ok(DnsClass::CS),
+            "CH" => Ok(DnsClass::CH),
+            "HS" => Ok(DnsClass::HS),
+            name => name
+                .strip_prefix("CLASS")
+                .and_then(|num| num.parse::<u16>().ok())
+                .ok_or_else(|| {
+                    ServerError::InvalidDnsClass(format!("{} is not a valid DNS class", name))
+                })
+                .and_then(DnsClass::try_from),
+        }
+    }
+}
+
+/// The variant names are the class mnemonics, classes without one are written as
+/// CLASS<number>.
+impl std::fmt::Display for DnsClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsClass::Unknown(num) => write!(f, "CLASS{}", num),
+            _ => write!(f, "{:?}", self),
         }
     }
 }
@@ -159,6 +350,133 @@ impl DomainName {
     pub fn as_slice(&self, start_index: usize) -> &[DomainLabel] {
         &self.labels[start_index..]
     }
+
+    /// Encodes a dotted domain name as a sequence of length-prefixed labels terminated by the
+    /// root label, without compression.
+    pub fn encode(name: &str) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        for part in name.split('.').filter(|part| !part.is_empty()) {
+            buf.put_u8(part.len() as u8);
+            buf.put(part.as_bytes());
+        }
+
+        buf.put_u8(0);
+
+        Bytes::from(buf)
+    }
+
+    /// Canonical order of dotted domain names, comparing labels case-insensitively from the
+    /// root down. A name sorts before its subdomains.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc4034#section-6.1
+    pub fn canonical_cmp(a: &str, b: &str) -> Ordering {
+        let labels = |name: &str| -> Vec<String> {
+            name.split('.')
+                .filter(|label| !label.is_empty())
+                .map(|label| label.to_lowercase())
+                .rev()
+                .collect()
+        };
+
+        labels(a).cmp(&labels(b))
+    }
+
+    /// Replaces the `owner` suffix of `name` with `target`, the substitution a DNAME record
+    /// makes. Names are dotted and without the trailing dot, `name` must be below `owner`.
+    /// Returns `None` when the result is longer than the 255 octets a name may take.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc6672#section-2.2
+    pub fn substitute(name: &str, owner: &str, target: &str) -> Option<String> {
+        let prefix = match owner {
+            "" => name,
+            _ => name.strip_suffix(owner)?.strip_suffix('.')?,
+        };
+        let target = target.trim_end_matches('.');
+
+        let substituted = if target.is_empty() {
+            prefix.to_string()
+        } else {
+            format!("{}.{}", prefix, target)
+        };
+
+        (DomainName::encode(&substituted).len() <= 255).then_some(substituted)
+    }
+
+    /// Decodes a sequence of labels terminated by the root label. Compression pointers are not
+    /// followed, as the rest of the message is not available here; the name ends at the pointer.
+    pub fn decode(buf: &mut Bytes) -> Result<DomainName, String> {
+        let mut domain_name = DomainName::default();
+
+        domain_name.decode_labels(buf)?;
+
+        Ok(domain_name)
+    }
+
+    /// Decodes a domain name that may end in a compression pointer, following pointers into
+    /// `message`, which holds the whole message. Only the bytes up to the first pointer are
+    /// consumed from `buf`.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc1035#section-4.1.4
+    pub fn decode_compressed(buf: &mut Bytes, message: &Bytes) -> Result<DomainName, String> {
+        let mut domain_name = DomainName::default();
+        let mut pointer = domain_name.decode_labels(buf)?;
+        let mut jumps = 0;
+
+        while let Some(offset) = pointer {
+            jumps += 1;
+
+            // A name has at most 127 labels, more jumps than that means a pointer loop
+            if jumps > 127 || offset >= message.len() {
+                return Err(format!("compression pointer {} is not valid", offset));
+            }
+
+            pointer = domain_name.decode_labels(&mut message.slice(offset..))?;
+        }
+
+        Ok(domain_name)
+    }
+
+    // Reads labels until the root label or a compression pointer, returning the pointer offset
+    fn decode_labels(&mut self, buf: &mut Bytes) -> Result<Option<usize>, String> {
+        loop {
+            if !buf.has_remaining() {
+                return Err("domain name is not terminated".to_string());
+            }
+
+            let label_length = buf.get_u8();
+
+            if label_length == 0 {
+                return Ok(None);
+            }
+
+            if label_length & 0b1100_0000 == 0b1100_0000 {
+                if !buf.has_remaining() {
+                    return Err("compression pointer is truncated".to_string());
+                }
+
+                let offset = ((label_length & 0b0011_1111) as usize) << 8 | buf.get_u8() as usize;
+
+                return Ok(Some(offset));
+            }
+
+            if buf.remaining() < label_length as usize {
+                return Err(format!(
+                    "label is {} bytes long but only {} bytes are left",
+                    label_length,
+                    buf.remaining()
+                ));
+            }
+
+            let bytes = buf.copy_to_bytes(label_length as usize);
+            let label = std::str::from_utf8(&bytes[..]).map_err(|err| err.to_string())?;
+
+            self.add_label(DomainLabel {
+                pointer: None,
+                name: label.to_string(),
+            });
+        }
+    }
 }
 
 impl std::fmt::Display for DomainName {
@@ -168,3 +486,39 @@ impl std::fmt::Display for DomainName {
         write!(f, "{}", name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dname_substitution_replaces_the_owner_suffix() {
+        assert_eq!(
+            DomainName::substitute("www.old.example.com", "old.example.com", "example.net."),
+            Some("www.example.net".to_string())
+        );
+        assert_eq!(
+            DomainName::substitute("a.b.example.com", "example.com", ""),
+            Some("a.b".to_string())
+        );
+        assert_eq!(
+            DomainName::substitute("www.example.org", "example.com", "example.net"),
+            None
+        );
+    }
+
+    #[test]
+    fn dname_substitution_is_limited_to_255_octets() {
+        let prefix = [
+            "a".repeat(63),
+            "a".repeat(63),
+            "a".repeat(63),
+            "a".repeat(50),
+        ]
+        .join(".");
+        let name = format!("{}.old.example", prefix);
+
+        assert!(DomainName::substitute(&name, "old.example", "example").is_some());
+        assert!(DomainName::substitute(&name, "old.example", "new.example").is_none());
+    }
+}
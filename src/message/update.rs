@@ -0,0 +1,135 @@
+use bytes::{Buf, Bytes};
+
+use super::{
+    header::HeaderDecoder,
+    question::{Question, QuestionType, QuestionsDecoder},
+    rdata::{RData, RDataDecoder},
+    types::{DnsClass, DnsType, DomainName},
+};
+use crate::error::ServerError;
+
+/// CLASS of a prerequisite or update record. Besides the class of the zone, ANY and NONE give
+/// the record its meaning, e.g. "RRset exists" or "delete an RR from an RRset".
+///
+/// https://www.rfc-editor.org/rfc/rfc2136#section-2.4
+#[derive(Debug, Clone, Copy)]
+pub enum UpdateClass {
+    DnsClass(DnsClass),
+    /// 254
+    NONE,
+    /// 255
+    ALL,
+}
+
+impl TryFrom<u16> for UpdateClass {
+    type Error = ServerError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            254 => Ok(UpdateClass::NONE),
+            255 => Ok(UpdateClass::ALL),
+            num => Ok(UpdateClass::DnsClass(DnsClass::try_from(num)?)),
+        }
+    }
+}
+
+/// A record of the prerequisite or update section. The type may be ANY, and records that only
+/// name an RRset have no data.
+#[derive(Debug, Clone)]
+pub struct UpdateRecord {
+    pub name: String,
+    pub kind: QuestionType,
+    pub class: UpdateClass,
+    pub ttl: u32,
+    pub data: Option<RData>,
+}
+
+/// An UPDATE request. The question, answer and authority sections of the message are read as
+/// the zone, prerequisite and update sections.
+///
+/// https://www.rfc-editor.org/rfc/rfc2136#section-2
+#[derive(Debug)]
+pub struct Update {
+    pub zone: Question,
+    pub prerequisites: Vec<UpdateRecord>,
+    pub updates: Vec<UpdateRecord>,
+}
+
+pub struct UpdateDecoder;
+
+impl UpdateDecoder {
+    pub fn decode(buf: &[u8]) -> Result<Update, ServerError> {
+        let message = Bytes::copy_from_slice(buf);
+        let mut buf = message.clone();
+
+        let header = HeaderDecoder::decode(&mut buf)?;
+
+        // The zone section holds exactly one zone (RFC 2136 section 3.1.1)
+        if header.question_count != 1 {
+            return Err(ServerError::DecodeUpdate(format!(
+                "zone section has {} records",
+                header.question_count
+            )));
+        }
+
+        let zone = QuestionsDecoder::new(&mut buf, 1, message.len())
+            .decode()?
+            .remove(0);
+
+        if !matches!(zone.kind, QuestionType::DnsType(DnsType::SOA)) {
+            return Err(ServerError::DecodeUpdate(
+                "zone section is not of type SOA".to_string(),
+            ));
+        }
+
+        let prerequisites = (0..header.answer_record_count)
+            .map(|_| Self::decode_record(&mut buf, &message))
+            .collect::<Result<_, _>>()?;
+        let updates = (0..header.auth_record_count)
+            .map(|_| Self::decode_record(&mut buf, &message))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Update {
+            zone,
+            prerequisites,
+            updates,
+        })
+    }
+
+    fn decode_record(buf: &mut Bytes, message: &Bytes) -> Result<UpdateRecord, ServerError> {
+        let name = DomainName::decode_compressed(buf, message)
+            .map_err(|err| ServerError::DecodeUpdate(err.to_string()))?;
+
+        if buf.remaining() < 10 {
+            return Err(ServerError::DecodeUpdate(
+                "record header is truncated".to_string(),
+            ));
+        }
+
+        let kind = QuestionType::try_from(buf.get_u16())?;
+        let class = UpdateClass::try_from(buf.get_u16())?;
+        let ttl = buf.get_u32();
+        let length = buf.get_u16();
+
+        let data = match kind {
+            _ if length == 0 => None,
+            QuestionType::DnsType(kind) => {
+                Some(RDataDecoder::new(buf, message, kind, length).decode()?)
+            }
+            _ => {
+                return Err(ServerError::DecodeUpdate(format!(
+                    "{} records cannot have data",
+                    u16::from(kind)
+                )))
+            }
+        };
+
+        Ok(UpdateRecord {
+            name: name.to_string(),
+            kind,
+            class,
+            ttl,
+            data,
+        })
+    }
+}
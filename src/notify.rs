@@ -0,0 +1,232 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::error::ServerError;
+use crate::message::{
+    answer::Answer,
+    edns::EDNS_UDP_PAYLOAD_SIZE,
+    header::{random_id, Header, OperationCode, ResponseCode},
+    message::{Message, MessageDecoder, MessageEncoder},
+    question::{Question, QuestionClass, QuestionType},
+    tsig::TsigErrorCode,
+    types::{DnsClass, DnsType},
+};
+use crate::tsig::TsigKey;
+
+/// How long to wait for a secondary to acknowledge a NOTIFY before sending it again.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// NOTIFY messages sent to a secondary before giving up on it.
+const MAX_NOTIFY_ATTEMPTS: usize = 5;
+
+/// A secondary told when a local zone changes, in the `<origin>:<address>[,key=<name>]` form,
+/// e.g. `example.com:192.0.2.2:53,key=transfer`.
+pub struct NotifyTarget {
+    pub origin: String,
+    pub addr: SocketAddr,
+    /// Name of the TSIG key the NOTIFY messages are signed with.
+    pub key: Option<String>,
+}
+
+impl FromStr for NotifyTarget {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ServerError::InvalidNotifyTarget(format!(
+                "{} is not in the origin:address:port[,key=<name>] form",
+                value
+            ))
+        };
+
+        let mut settings = value.split(',');
+        let (origin, addr) = settings
+            .next()
+            .and_then(|target| target.split_once(':'))
+            .ok_or_else(invalid)?;
+
+        if origin.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut key = None;
+
+        for setting in settings {
+            match setting.split_once('=') {
+                Some(("key", name)) => key = Some(name.trim_end_matches('.').to_lowercase()),
+                _ => {
+                    return Err(ServerError::InvalidNotifyTarget(format!(
+                        "{}: unknown setting {}",
+                        value, setting
+                    )))
+                }
+            }
+        }
+
+        Ok(Self {
+            origin: origin.trim_end_matches('.').to_lowercase(),
+            addr: SocketAddr::from_str(addr).map_err(|_| invalid())?,
+            key,
+        })
+    }
+}
+
+/// Sends NOTIFY messages for a local zone to one of its secondaries, so it refreshes the zone
+/// right away instead of when its refresh timer fires.
+///
+/// https://www.rfc-editor.org/rfc/rfc1996#section-3
+#[derive(Clone)]
+pub struct Notifier {
+    origin: String,
+    addr: SocketAddr,
+    key: Option<TsigKey>,
+}
+
+impl Notifier {
+    /// `key` is the key the target names.
+    pub fn new(target: NotifyTarget, key: Option<TsigKey>) -> Self {
+        Self {
+            origin: target.origin,
+            addr: target.addr,
+            key,
+        }
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Tells the secondary about the version of the zone `soa` is the SOA record of. The
+    /// NOTIFY is sent again over UDP until the secondary acknowledges it, on a thread of its
+    /// own.
+    pub fn notify(&self, soa: Answer) {
+        let notifier = self.clone();
+
+        thread::spawn(move || {
+            if let Err(err) = notifier.send(soa) {
+                eprintln!(
+                    "Failed to notify {} of changes to {}: {}",
+                    notifier.addr, notifier.origin, err
+                );
+            }
+        });
+    }
+
+    fn send(&self, soa: Answer) -> Result<(), ServerError> {
+        let io_error = |err: std::io::Error| ServerError::Notify(err.to_string());
+        let local_addr = if self.addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        // The SOA record in the answer section is a hint the secondary may use (RFC 1996
+        // section 3.7)
+        let message = Message {
+            header: Header {
+                id: random_id(),
+                query_indicator: false,
+                operation_code: OperationCode::Notify,
+                auth_answer: true,
+                truncation: false,
+                recursion_desired: false,
+                recursion_available: false,
+                reserve: 0,
+                authentic_data: false,
+                checking_disabled: false,
+                code: ResponseCode::NoErrorCondition,
+                question_count: 1,
+                answer_record_count: 1,
+                auth_record_count: 0,
+                additional_record_count: 0,
+            },
+            questions: vec![Question {
+                name: self.origin.clone(),
+                kind: QuestionType::DnsType(DnsType::SOA),
+                class: QuestionClass::DnsClass(DnsClass::IN),
+            }],
+            answers: vec![soa],
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            tsig: None,
+        };
+
+        let (encoded_message, request_mac) = match &self.key {
+            Some(key) => key.encode_signed(&message),
+            None => (MessageEncoder::encode(&message), Vec::new()),
+        };
+
+        let socket = UdpSocket::bind(local_addr).map_err(io_error)?;
+        let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+
+        for _ in 0..MAX_NOTIFY_ATTEMPTS {
+            socket
+                .send_to(&encoded_message, self.addr)
+                .map_err(io_error)?;
+
+            let deadline = Instant::now() + NOTIFY_TIMEOUT;
+
+            // Messages that are not the acknowledgement are ignored until the timeout
+            while let Some(timeout) = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|timeout| !timeout.is_zero())
+            {
+                socket.set_read_timeout(Some(timeout)).map_err(io_error)?;
+
+                let Ok((len, source)) = socket.recv_from(&mut buf) else {
+                    break;
+                };
+                let Ok(response) = MessageDecoder::decode(&buf[..len]) else {
+                    continue;
+                };
+
+                if source != self.addr
+                    || response.header.id != message.header.id
+                    || !response.header.query_indicator
+                {
+                    continue;
+                }
+
+                return self.check_acknowledgement(&buf[..len], &response, &request_mac);
+            }
+        }
+
+        Err(ServerError::Notify(format!(
+            "no acknowledgement after {} attempts",
+            MAX_NOTIFY_ATTEMPTS
+        )))
+    }
+
+    fn check_acknowledgement(
+        &self,
+        buf: &[u8],
+        response: &Message,
+        request_mac: &[u8],
+    ) -> Result<(), ServerError> {
+        if let Some(key) = &self.key {
+            let error = response
+                .tsig
+                .as_ref()
+                .map_or(TsigErrorCode::BadKey, |tsig| {
+                    key.verify_response(request_mac, buf, tsig)
+                });
+
+            if error != TsigErrorCode::NoError {
+                return Err(ServerError::Notify(format!(
+                    "acknowledgement failed TSIG verification: {:?}",
+                    error
+                )));
+            }
+        }
+
+        match response.header.code {
+            ResponseCode::NoErrorCondition => Ok(()),
+            code => Err(ServerError::Notify(format!("answered {:?}", code))),
+        }
+    }
+}
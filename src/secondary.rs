@@ -0,0 +1,737 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, UdpSocket},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+
+use crate::authority::{AuthoritativeZone, ZoneDiff};
+use crate::error::ServerError;
+use crate::message::{
+    answer::Answer,
+    edns::EDNS_UDP_PAYLOAD_SIZE,
+    header::{random_id, Header, OperationCode, ResponseCode},
+    message::{Message, MessageDecoder, MessageEncoder},
+    question::{Question, QuestionClass, QuestionType},
+    rdata::{RData, Serial, Soa},
+    tsig::TsigErrorCode,
+    types::{DnsClass, DnsType},
+};
+use crate::tsig::TsigKey;
+
+/// How long to wait for the primary before a refresh counts as failed.
+const PRIMARY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the result of a refresh under way is looked for.
+const REFRESH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Wait before retrying the first transfer of a zone, which has no SOA record to take the
+/// retry interval from yet.
+const INITIAL_RETRY: Duration = Duration::from_secs(60);
+
+/// Where a secondary zone is transferred from, in the `<origin>:<primary address>[,key=<name>]`
+/// form, e.g. `example.com:192.0.2.1:53,key=transfer`.
+pub struct SecondarySource {
+    pub origin: String,
+    pub primary: SocketAddr,
+    /// Name of the TSIG key queries to the primary are signed with, and its responses must be
+    /// signed with.
+    pub key: Option<String>,
+}
+
+impl FromStr for SecondarySource {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ServerError::InvalidSecondary(format!(
+                "{} is not in the origin:address:port[,key=<name>] form",
+                value
+            ))
+        };
+
+        let mut settings = value.split(',');
+        let (origin, primary) = settings
+            .next()
+            .and_then(|source| source.split_once(':'))
+            .ok_or_else(invalid)?;
+
+        if origin.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut key = None;
+
+        for setting in settings {
+            match setting.split_once('=') {
+                Some(("key", name)) => key = Some(name.trim_end_matches('.').to_lowercase()),
+                _ => {
+                    return Err(ServerError::InvalidSecondary(format!(
+                        "{}: unknown setting {}",
+                        value, setting
+                    )))
+                }
+            }
+        }
+
+        Ok(Self {
+            origin: origin.trim_end_matches('.').to_lowercase(),
+            primary: SocketAddr::from_str(primary).map_err(|_| invalid())?,
+            key,
+        })
+    }
+}
+
+/// What a refresh did to the data of a secondary zone.
+pub enum Refresh {
+    Unchanged,
+    Transferred(Box<AuthoritativeZone>),
+    /// The primary could not be reached for longer than the SOA expire interval, the data must
+    /// no longer be served.
+    Expired,
+}
+
+/// A zone this server is a secondary for. Its data is served with the other local zones once
+/// transferred, this keeps track of when to check the primary for a newer version and when
+/// the data expires without one. Refreshes run on a thread of their own, so requests are
+/// answered in the meantime.
+///
+/// https://www.rfc-editor.org/rfc/rfc1034#section-4.3.5
+pub struct SecondaryZone {
+    primary: Primary,
+    refresh_at: Instant,
+    /// Unset while there is no data to expire.
+    expire_at: Option<Instant>,
+    /// Result of the refresh under way.
+    pending: Option<Receiver<Result<Option<Transfer>, ServerError>>>,
+}
+
+impl SecondaryZone {
+    /// The zone is transferred on the first refresh. `key` is the key the source names.
+    pub fn new(source: SecondarySource, key: Option<TsigKey>) -> Self {
+        Self {
+            primary: Primary {
+                origin: source.origin,
+                addr: source.primary,
+                key,
+            },
+            refresh_at: Instant::now(),
+            expire_at: None,
+            pending: None,
+        }
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.primary.origin
+    }
+
+    pub fn primary(&self) -> SocketAddr {
+        self.primary.addr
+    }
+
+    /// When the zone needs looking after again: its next refresh, its data expiring, or soon
+    /// while a refresh is under way.
+    pub fn deadline(&self) -> Instant {
+        if self.pending.is_some() {
+            return Instant::now() + REFRESH_POLL_INTERVAL;
+        }
+
+        self.expire_at
+            .map_or(self.refresh_at, |expire_at| expire_at.min(self.refresh_at))
+    }
+
+    /// Has the next refresh happen right away, e.g. after a NOTIFY from the primary.
+    pub fn refresh_now(&mut self) {
+        self.refresh_at = Instant::now();
+    }
+
+    /// Starts checking the serial on the primary once the refresh interval has passed, and
+    /// picks up the result of that check on a later call. The zone is transferred when the
+    /// primary's version is newer than `zone`, the data served so far, with IXFR first when
+    /// there is data to update. Failed refreshes are retried after the retry interval.
+    pub fn refresh(&mut self, zone: Option<&AuthoritativeZone>, now: Instant) -> Refresh {
+        if self.expire_at.is_some_and(|expire_at| expire_at <= now) {
+            self.expire_at = None;
+            return Refresh::Expired;
+        }
+
+        let Some(pending) = &self.pending else {
+            if now >= self.refresh_at {
+                let (sender, receiver) = mpsc::channel();
+                let primary = self.primary.clone();
+                let soa = zone.and_then(AuthoritativeZone::soa_record).cloned();
+
+                thread::spawn(move || sender.send(primary.fetch(soa.as_ref())));
+                self.pending = Some(receiver);
+            }
+
+            return Refresh::Unchanged;
+        };
+
+        let result = match pending.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return Refresh::Unchanged,
+            Err(TryRecvError::Disconnected) => Err(ServerError::ZoneTransfer(
+                "refresh ended without a result".to_string(),
+            )),
+        };
+
+        self.pending = None;
+
+        let transferred = result.and_then(|transfer| match (transfer, zone) {
+            (None | Some(Transfer::UpToDate), _) => Ok(None),
+            (Some(Transfer::Full(records)), _) => {
+                AuthoritativeZone::transferred(self.origin(), records).map(Some)
+            }
+            (Some(Transfer::Incremental(diffs)), Some(zone)) => zone.with_changes(diffs).map(Some),
+            (Some(Transfer::Incremental(_)), None) => Err(ServerError::ZoneTransfer(format!(
+                "changes of {} came without its data",
+                self.origin()
+            ))),
+        });
+
+        match transferred {
+            Ok(transferred) => {
+                if let Some(soa) = transferred.as_ref().or(zone).and_then(zone_soa) {
+                    self.refresh_at = now + Duration::from_secs(soa.refresh as u64);
+                    self.expire_at = Some(now + Duration::from_secs(soa.expire as u64));
+                }
+
+                transferred.map_or(Refresh::Unchanged, |zone| {
+                    Refresh::Transferred(Box::new(zone))
+                })
+            }
+            Err(err) => {
+                eprintln!(
+                    "Failed to refresh {} from {}: {}",
+                    self.origin(),
+                    self.primary(),
+                    err
+                );
+
+                self.refresh_at = now
+                    + zone
+                        .and_then(zone_soa)
+                        .map_or(INITIAL_RETRY, |soa| Duration::from_secs(soa.retry as u64));
+
+                Refresh::Unchanged
+            }
+        }
+    }
+}
+
+// The primary server of a secondary zone
+#[derive(Clone)]
+struct Primary {
+    origin: String,
+    addr: SocketAddr,
+    key: Option<TsigKey>,
+}
+
+impl Primary {
+    // What changed since the version `soa` is the SOA record of, nothing when it is up to date.
+    // Without a version there is only the whole zone to ask for.
+    fn fetch(&self, soa: Option<&Answer>) -> Result<Option<Transfer>, ServerError> {
+        let Some(soa) = soa else {
+            return match self.transfer(QuestionType::AXFR, None)? {
+                Transfer::Full(records) => Ok(Some(Transfer::Full(records))),
+                _ => Err(ServerError::ZoneTransfer(format!(
+                    "AXFR of {} is not a full zone",
+                    self.origin
+                ))),
+            };
+        };
+
+        let serial = self.serial()?;
+
+        if soa_data(soa).is_some_and(|current| serial <= current.serial) {
+            return Ok(None);
+        }
+
+        // Primaries without IXFR answer with an error, or with the whole zone
+        match self.transfer(QuestionType::IXFR, Some(soa)) {
+            Ok(transfer) => Ok(Some(transfer)),
+            Err(err) => {
+                eprintln!("IXFR of {} failed, trying AXFR: {}", self.origin, err);
+                self.transfer(QuestionType::AXFR, None).map(Some)
+            }
+        }
+    }
+
+    // The serial of the zone on the primary, asked for over UDP
+    fn serial(&self) -> Result<Serial, ServerError> {
+        let local_addr = if self.addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let query = self.query(QuestionType::DnsType(DnsType::SOA), None);
+        let (encoded_query, request_mac) = self.sign(&query);
+        let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+
+        let socket = UdpSocket::bind(local_addr)
+            .and_then(|socket| {
+                socket.set_read_timeout(Some(PRIMARY_TIMEOUT))?;
+                socket.send_to(&encoded_query, self.addr)?;
+                Ok(socket)
+            })
+            .map_err(|err| ServerError::ZoneTransfer(err.to_string()))?;
+
+        // Responses with another ID or from another address are ignored
+        let (response, len) = loop {
+            let (len, source) = socket
+                .recv_from(&mut buf)
+                .map_err(|err| ServerError::ZoneTransfer(err.to_string()))?;
+            let response = MessageDecoder::decode(&buf[..len])?;
+
+            if source == self.addr && response.header.id == query.header.id {
+                break (response, len);
+            }
+        };
+
+        self.verify(&buf[..len], &response, &request_mac, false)?;
+        self.check_response(&response)?;
+
+        response
+            .answers
+            .iter()
+            .filter(|record| self.is_apex(record))
+            .find_map(soa_data)
+            .map(|soa| soa.serial)
+            .ok_or_else(|| ServerError::ZoneTransfer(format!("{} has no SOA record", self.origin)))
+    }
+
+    // Transfers the zone over TCP. IXFR queries carry the SOA record of the version the
+    // secondary has (RFC 1995 section 3).
+    fn transfer(&self, kind: QuestionType, soa: Option<&Answer>) -> Result<Transfer, ServerError> {
+        let io_error = |err: std::io::Error| ServerError::ZoneTransfer(err.to_string());
+
+        let query = self.query(kind, soa.cloned());
+        let (encoded_query, mut previous_mac) = self.sign(&query);
+
+        let mut stream =
+            TcpStream::connect_timeout(&self.addr, PRIMARY_TIMEOUT).map_err(io_error)?;
+        stream
+            .set_read_timeout(Some(PRIMARY_TIMEOUT))
+            .map_err(io_error)?;
+
+        let mut message = (encoded_query.len() as u16).to_be_bytes().to_vec();
+        message.extend_from_slice(&encoded_query);
+        stream.write_all(&message).map_err(io_error)?;
+
+        let current = soa.and_then(soa_data).map(|soa| soa.serial);
+        let mut records = Vec::new();
+        let mut is_continuation = false;
+
+        // The response may span any number of messages
+        loop {
+            let mut length = [0; 2];
+            stream.read_exact(&mut length).map_err(io_error)?;
+
+            let mut buf = vec![0; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut buf).map_err(io_error)?;
+
+            let response = MessageDecoder::decode(&buf)?;
+
+            if response.header.id != query.header.id {
+                return Err(ServerError::ZoneTransfer(format!(
+                    "response ID {} does not match query ID {}",
+                    response.header.id, query.header.id
+                )));
+            }
+
+            previous_mac = self.verify(&buf, &response, &previous_mac, is_continuation)?;
+            is_continuation = true;
+
+            self.check_response(&response)?;
+            records.extend(response.answers);
+
+            if let Some(transfer) = self.read_transfer(&records, current)? {
+                break Ok(transfer);
+            }
+        }
+    }
+
+    // The transfer the records received so far make up, if they are complete. An incremental
+    // response has an SOA record as its second record, a full one the zone's other records
+    // between its SOA records.
+    //
+    // https://www.rfc-editor.org/rfc/rfc1995#section-4
+    fn read_transfer(
+        &self,
+        records: &[Answer],
+        current: Option<Serial>,
+    ) -> Result<Option<Transfer>, ServerError> {
+        let Some(serial) = records
+            .first()
+            .filter(|record| self.is_apex(record))
+            .and_then(soa_data)
+            .map(|soa| soa.serial)
+        else {
+            return Err(ServerError::ZoneTransfer(format!(
+                "transfer of {} does not start with its SOA record",
+                self.origin
+            )));
+        };
+
+        let is_soa = |record: &Answer| record.kind == DnsType::SOA;
+
+        match records.get(1) {
+            // A lone SOA record answers an IXFR query from a secondary that is up to date
+            None if current.is_some_and(|current| serial <= current) => {
+                Ok(Some(Transfer::UpToDate))
+            }
+            None => Ok(None),
+            Some(second)
+                if current.is_some()
+                    && is_soa(second)
+                    && soa_data(second).is_some_and(|soa| soa.serial != serial) =>
+            {
+                Ok(read_diffs(&records[1..], serial).map(Transfer::Incremental))
+            }
+            Some(_) if is_soa(&records[records.len() - 1]) => {
+                Ok(Some(Transfer::Full(records[..records.len() - 1].to_vec())))
+            }
+            Some(_) => Ok(None),
+        }
+    }
+
+    // The encoded query, signed when the primary has a key, and the MAC the first message of
+    // the response follows on from
+    fn sign(&self, query: &Message) -> (Bytes, Vec<u8>) {
+        match &self.key {
+            Some(key) => key.encode_signed(query),
+            None => (MessageEncoder::encode(query), Vec::new()),
+        }
+    }
+
+    // Responses from a primary with a key must be signed with it. The first message of a
+    // response follows on from the query's MAC and later ones from the message before them
+    // (RFC 8945 section 5.3.1). Returns the MAC the next message follows on from.
+    fn verify(
+        &self,
+        buf: &[u8],
+        response: &Message,
+        previous_mac: &[u8],
+        is_continuation: bool,
+    ) -> Result<Vec<u8>, ServerError> {
+        let Some(key) = &self.key else {
+            return Ok(Vec::new());
+        };
+
+        let Some(tsig) = &response.tsig else {
+            return Err(ServerError::ZoneTransfer(format!(
+                "{} sent an unsigned response for {}",
+                self.addr, self.origin
+            )));
+        };
+
+        let error = if is_continuation {
+            key.verify_continuation(previous_mac, buf, tsig)
+        } else {
+            key.verify_response(previous_mac, buf, tsig)
+        };
+
+        if error != TsigErrorCode::NoError {
+            return Err(ServerError::ZoneTransfer(format!(
+                "response from {} for {} failed TSIG verification: {:?}",
+                self.addr, self.origin, error
+            )));
+        }
+
+        Ok(tsig.mac.clone())
+    }
+
+    fn check_response(&self, response: &Message) -> Result<(), ServerError> {
+        if matches!(response.header.code, ResponseCode::NoErrorCondition) {
+            return Ok(());
+        }
+
+        Err(ServerError::ZoneTransfer(format!(
+            "{} answered {:?} for {}",
+            self.addr, response.header.code, self.origin
+        )))
+    }
+
+    fn query(&self, kind: QuestionType, soa: Option<Answer>) -> Message {
+        Message {
+            header: Header {
+                id: random_id(),
+                query_indicator: false,
+                operation_code: OperationCode::StandardQuery,
+                auth_answer: false,
+                truncation: false,
+                recursion_desired: false,
+                recursion_available: false,
+                reserve: 0,
+                authentic_data: false,
+                checking_disabled: false,
+                code: ResponseCode::NoErrorCondition,
+                question_count: 1,
+                answer_record_count: 0,
+                auth_record_count: soa.is_some() as u16,
+                additional_record_count: 0,
+            },
+            questions: vec![Question {
+                name: self.origin.clone(),
+                kind,
+                class: QuestionClass::DnsClass(DnsClass::IN),
+            }],
+            answers: Vec::new(),
+            authorities: soa.into_iter().collect(),
+            additionals: Vec::new(),
+            edns: None,
+            tsig: None,
+        }
+    }
+
+    fn is_apex(&self, record: &Answer) -> bool {
+        record
+            .name
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(&self.origin)
+    }
+}
+
+enum Transfer {
+    UpToDate,
+    /// Every record of the zone but the closing SOA record.
+    Full(Vec<Answer>),
+    Incremental(Vec<ZoneDiff>),
+}
+
+// The changes of an incremental transfer, once the records end with the SOA record of version
+// `serial` where the next change would start
+fn read_diffs(records: &[Answer], serial: Serial) -> Option<Vec<ZoneDiff>> {
+    let (last, changes) = records.split_last()?;
+
+    if !soa_data(last).is_some_and(|soa| soa.serial == serial) {
+        return None;
+    }
+
+    ZoneDiff::read_all(changes)
+}
+
+fn soa_data(record: &Answer) -> Option<&Soa> {
+    match &record.data {
+        RData::Soa(soa) => Some(soa),
+        _ => None,
+    }
+}
+
+fn zone_soa(zone: &AuthoritativeZone) -> Option<&Soa> {
+    zone.soa_record().and_then(soa_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener};
+
+    use super::*;
+
+    fn soa(serial: u32) -> Answer {
+        Answer {
+            name: "example.com".to_string(),
+            kind: DnsType::SOA,
+            class: DnsClass::IN,
+            ttl: 3600,
+            data: RData::Soa(Soa {
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: Serial(serial),
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+            }),
+        }
+    }
+
+    fn a(name: &str, host: u8) -> Answer {
+        Answer {
+            name: format!("{}.example.com", name),
+            kind: DnsType::A,
+            class: DnsClass::IN,
+            ttl: 300,
+            data: RData::A(Ipv4Addr::new(192, 0, 2, host)),
+        }
+    }
+
+    fn primary(addr: SocketAddr) -> Primary {
+        Primary {
+            origin: "example.com".to_string(),
+            addr,
+            key: None,
+        }
+    }
+
+    // A primary that answers one transfer query with the answer sections of `messages`
+    fn serve(messages: Vec<Vec<Answer>>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut length = [0; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut buf = vec![0; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut buf).unwrap();
+
+            let mut query = MessageDecoder::decode(&buf).unwrap();
+            query.header.query_indicator = true;
+            query.header.auth_record_count = 0;
+            query.authorities.clear();
+
+            for answers in messages {
+                query.header.answer_record_count = answers.len() as u16;
+                query.answers = answers;
+
+                let response = MessageEncoder::encode(&query);
+                stream
+                    .write_all(&(response.len() as u16).to_be_bytes())
+                    .unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn full_transfer_spans_messages_up_to_the_closing_soa_record() {
+        let addr = serve(vec![vec![soa(3), a("www", 1)], vec![a("mail", 2), soa(3)]]);
+
+        let transfer = primary(addr).transfer(QuestionType::AXFR, None).unwrap();
+
+        assert!(matches!(transfer, Transfer::Full(records) if records.len() == 3));
+    }
+
+    #[test]
+    fn full_transfer_without_closing_soa_record_fails() {
+        let addr = serve(vec![vec![soa(3), a("www", 1)]]);
+
+        assert!(primary(addr).transfer(QuestionType::AXFR, None).is_err());
+
+        let records = [soa(3), a("www", 1), a("mail", 2)];
+        let primary = primary(addr);
+
+        assert!(matches!(primary.read_transfer(&records, None), Ok(None)));
+        assert!(primary.read_transfer(&records[1..], None).is_err());
+    }
+
+    #[test]
+    fn incremental_transfer_is_read_as_changes() {
+        let records = [
+            soa(3),
+            soa(1),
+            a("www", 1),
+            soa(2),
+            a("www", 2),
+            soa(2),
+            soa(3),
+            a("mail", 3),
+            soa(3),
+        ];
+        let primary = primary("127.0.0.1:9".parse().unwrap());
+
+        let Ok(Some(Transfer::Incremental(diffs))) =
+            primary.read_transfer(&records, Some(Serial(1)))
+        else {
+            panic!("not an incremental transfer");
+        };
+
+        assert_eq!(diffs.len(), 2);
+        assert_eq!((diffs[0].deleted.len(), diffs[0].added.len()), (1, 1));
+        assert_eq!((diffs[1].deleted.len(), diffs[1].added.len()), (0, 1));
+        assert_eq!(
+            soa_data(&diffs[1].new_soa).map(|soa| soa.serial),
+            Some(Serial(3))
+        );
+
+        // Not complete before the closing SOA record
+        assert!(matches!(
+            primary.read_transfer(&records[..8], Some(Serial(1))),
+            Ok(None)
+        ));
+
+        // A lone SOA record tells an up to date secondary nothing changed
+        assert!(matches!(
+            primary.read_transfer(&[soa(1)], Some(Serial(1))),
+            Ok(Some(Transfer::UpToDate))
+        ));
+    }
+
+    #[test]
+    fn incremental_transfer_answered_with_the_whole_zone_is_full() {
+        let records = [soa(3), a("www", 1), a("mail", 2), soa(3)];
+        let primary = primary("127.0.0.1:9".parse().unwrap());
+
+        assert!(matches!(
+            primary.read_transfer(&records, Some(Serial(1))),
+            Ok(Some(Transfer::Full(records))) if records.len() == 3
+        ));
+    }
+
+    #[test]
+    fn refresh_timers_follow_the_soa_record() {
+        let source = SecondarySource::from_str("example.com:127.0.0.1:9").unwrap();
+        let mut secondary = SecondaryZone::new(source, None);
+        let now = Instant::now();
+
+        // A successful transfer sets the refresh and expire timers
+        let (sender, receiver) = mpsc::channel();
+        sender
+            .send(Ok(Some(Transfer::Full(vec![soa(1), a("www", 1)]))))
+            .unwrap();
+        secondary.pending = Some(receiver);
+
+        let Refresh::Transferred(zone) = secondary.refresh(None, now) else {
+            panic!("not transferred");
+        };
+
+        assert_eq!(secondary.refresh_at, now + Duration::from_secs(3600));
+        assert_eq!(secondary.expire_at, Some(now + Duration::from_secs(86400)));
+
+        // A failed one is retried after the retry interval, the data does not expire sooner
+        let (sender, receiver) = mpsc::channel();
+        sender
+            .send(Err(ServerError::ZoneTransfer("unreachable".to_string())))
+            .unwrap();
+        secondary.pending = Some(receiver);
+
+        let later = now + Duration::from_secs(3600);
+
+        assert!(matches!(
+            secondary.refresh(Some(&zone), later),
+            Refresh::Unchanged
+        ));
+        assert_eq!(secondary.refresh_at, later + Duration::from_secs(600));
+        assert_eq!(secondary.expire_at, Some(now + Duration::from_secs(86400)));
+
+        // Past the expire interval the data is no longer served
+        let expired = now + Duration::from_secs(86400);
+
+        assert!(matches!(
+            secondary.refresh(Some(&zone), expired),
+            Refresh::Expired
+        ));
+        assert_eq!(secondary.expire_at, None);
+    }
+
+    #[test]
+    fn secondary_sources() {
+        let source = SecondarySource::from_str("Example.COM.:[::1]:53,key=transfer").unwrap();
+
+        assert_eq!(source.origin, "example.com");
+        assert_eq!(source.primary, "[::1]:53".parse().unwrap());
+        assert_eq!(source.key.as_deref(), Some("transfer"));
+
+        assert!(SecondarySource::from_str("example.com").is_err());
+        assert!(SecondarySource::from_str(":127.0.0.1:53").is_err());
+        assert!(SecondarySource::from_str("example.com:127.0.0.1:53,tsig=x").is_err());
+    }
+}
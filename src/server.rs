@@ -1,88 +1,2151 @@
 use std::{
+    mem,
     net::{SocketAddr, UdpSocket},
     str::FromStr,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
 };
 
+use bytes::Bytes;
+
+use crate::authority::{minimal_any, reverse_address, AnyPolicy, AuthoritativeZone, Lookup};
 use crate::error::ServerError;
+use crate::listener::{Listener, Request, Socket, Transport};
 use crate::message::{
-    answer::{Answer, AnswersBuilder},
+    answer::{Answer, AnswersBuilder, AnswersEncoder},
     constants::DNS_MESSAGE_PACKET_SIZE,
-    header::{Header, OperationCode, ResponseCode},
+    dnssec::is_subdomain,
+    edns::{Edns, ExtendedError, InfoCode, EDNS_UDP_PAYLOAD_SIZE},
+    header::{Header, HeaderDecoder, OperationCode, ResponseCode},
     message::{Message, MessageDecoder, MessageEncoder},
+    question::{Question, QuestionClass, QuestionType, QuestionsDecoder},
+    rdata::RData,
+    tsig::TsigErrorCode,
+    types::{DnsClass, DnsType},
+    update::UpdateDecoder,
 };
+use crate::notify::Notifier;
+use crate::secondary::{Refresh, SecondaryZone};
+use crate::tsig::TsigKeyring;
+use crate::update::AddressPrefix;
+use crate::validator::{Security, TrustAnchor, Validator};
+
+/// How long to wait for the resolver before giving up on a question.
+const RESOLVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Size at which zone transfers go on in another message.
+const TRANSFER_MESSAGE_SIZE: usize = 16 * 1024;
+
+/// Answers to the TXT questions in class CH that identify the server. Names without an
+/// answer are refused.
+///
+/// https://www.rfc-editor.org/rfc/rfc4892
+pub struct ServerIdentity {
+    /// Answer to version.bind and version.server.
+    pub version: Option<String>,
+    /// Answer to hostname.bind and id.server.
+    pub hostname: Option<String>,
+}
+
+impl Default for ServerIdentity {
+    fn default() -> Self {
+        Self {
+            version: Some(format!(
+                "{} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )),
+            hostname: None,
+        }
+    }
+}
+
+/// How queries with more than one question are answered. A response has a single RCODE, so
+/// the protocol leaves them undefined in practice (RFC 9619).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuestionPolicy {
+    /// FORMERR, without answering any of them.
+    FormatError,
+    /// Only the first question is answered and echoed.
+    First,
+    /// Every question is answered. The RCODE is the worst of the questions' RCODEs, see
+    /// `combine_codes`.
+    All,
+}
+
+impl FromStr for QuestionPolicy {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "formerr" => Ok(QuestionPolicy::FormatError),
+            "first" => Ok(QuestionPolicy::First),
+            "all" => Ok(QuestionPolicy::All),
+            _ => Err(ServerError::InvalidQuestionPolicy(format!(
+                "{} is not one of formerr, first or all",
+                value
+            ))),
+        }
+    }
+}
 
 pub struct DnsServer {
-    udp_socket: UdpSocket,
+    sockets: Vec<Socket>,
+    tsig_keys: TsigKeyring,
+    trust_anchors: Vec<TrustAnchor>,
+    zones: Vec<AuthoritativeZone>,
+    update_acl: Vec<AddressPrefix>,
+    any_policy: AnyPolicy,
+    identity: ServerIdentity,
+    question_policy: QuestionPolicy,
+    secondaries: Vec<SecondaryZone>,
+    notifiers: Vec<Notifier>,
 }
 
 impl DnsServer {
-    pub fn bind(addr: &str) -> std::io::Result<DnsServer> {
-        let socket = UdpSocket::bind(addr)?;
+    /// Binds the sockets of every listener, requests are only received once listening.
+    pub fn bind(listeners: Vec<Listener>) -> Result<DnsServer, ServerError> {
+        let sockets = listeners
+            .into_iter()
+            .map(Listener::bind)
+            .collect::<Result<_, _>>()?;
 
-        Ok(Self { udp_socket: socket })
+        Ok(Self {
+            sockets,
+            tsig_keys: TsigKeyring::default(),
+            trust_anchors: Vec::new(),
+            zones: Vec::new(),
+            update_acl: Vec::new(),
+            any_policy: AnyPolicy::All,
+            identity: ServerIdentity::default(),
+            question_policy: QuestionPolicy::All,
+            secondaries: Vec::new(),
+            notifiers: Vec::new(),
+        })
     }
 
-    pub fn listen(self, resolver_addr: Option<&str>) -> Result<(), ServerError> {
-        let mut buf = [0; DNS_MESSAGE_PACKET_SIZE];
+    /// Once keys are configured, signed messages are verified and their responses signed, and
+    /// NOTIFY, UPDATE and zone transfer requests are refused unless they are signed.
+    pub fn with_tsig_keys(mut self, tsig_keys: TsigKeyring) -> Self {
+        self.tsig_keys = tsig_keys;
+        self
+    }
+
+    /// Once anchors are configured, resolver responses are validated with DNSSEC unless the
+    /// client sets the CD bit. Bogus responses are answered with SERVFAIL.
+    pub fn with_trust_anchors(mut self, trust_anchors: Vec<TrustAnchor>) -> Self {
+        self.trust_anchors = trust_anchors;
+        self
+    }
+
+    /// Questions about names in these zones are answered from them with the AA bit set, other
+    /// questions go to the resolver.
+    pub fn with_zones(mut self, zones: Vec<AuthoritativeZone>) -> Self {
+        self.zones = zones;
+        self
+    }
+
+    /// Clients in these networks may change the local zones with dynamic updates (RFC 2136).
+    /// Updates from anyone else are refused, as are all updates while the list is empty.
+    pub fn with_update_acl(mut self, update_acl: Vec<AddressPrefix>) -> Self {
+        self.update_acl = update_acl;
+        self
+    }
+
+    /// Local zones answer ANY questions with all their RRsets by default. With the HINFO
+    /// policy every ANY question gets a single HINFO record instead, and none are forwarded.
+    pub fn with_any_policy(mut self, any_policy: AnyPolicy) -> Self {
+        self.any_policy = any_policy;
+        self
+    }
+
+    /// By default only the version is answered in class CH.
+    pub fn with_identity(mut self, identity: ServerIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn with_question_policy(mut self, question_policy: QuestionPolicy) -> Self {
+        self.question_policy = question_policy;
+        self
+    }
+
+    /// Zones transferred from their primaries and served like the local zones once they are.
+    /// Until then, and once their data expires, questions about them get SERVFAIL.
+    pub fn with_secondaries(mut self, secondaries: Vec<SecondaryZone>) -> Self {
+        self.secondaries = secondaries;
+        self
+    }
+
+    /// Secondaries sent a NOTIFY whenever their zone changes, by a dynamic update or a transfer
+    /// from the zone's own primary.
+    pub fn with_notifiers(mut self, notifiers: Vec<Notifier>) -> Self {
+        self.notifiers = notifiers;
+        self
+    }
+
+    /// Answers the requests of every listener until all of them stop receiving. Requests are
+    /// answered one at a time, in the order they arrive.
+    pub fn listen(mut self, resolver_addr: Option<&str>) -> Result<(), ServerError> {
+        // Questions are forwarded from their own socket, so resolver responses never mix with
+        // client queries
+        let resolver = match resolver_addr {
+            Some(addr) => {
+                let addr = SocketAddr::from_str(addr)
+                    .map_err(|err| ServerError::ForwardedServer(format!("{}: {}", addr, err)))?;
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .and_then(|socket| {
+                        socket.set_read_timeout(Some(RESOLVER_TIMEOUT))?;
+                        Ok(socket)
+                    })
+                    .map_err(|err| ServerError::ForwardedServer(err.to_string()))?;
+
+                Some((socket, addr))
+            }
+            None => None,
+        };
+
+        let (sender, receiver) = mpsc::channel();
+
+        for socket in mem::take(&mut self.sockets) {
+            socket.receive(sender.clone());
+        }
+
+        // Only the listeners hold a sender, the loop ends once all of them stopped
+        drop(sender);
 
         loop {
-            match self.udp_socket.recv_from(&mut buf) {
-                Ok((_, source)) => {
-                    let query = MessageDecoder::decode(&buf).unwrap();
-
-                    let answers: Vec<Answer> = match &resolver_addr {
-                        Some(addr) => {
-                            let addr = SocketAddr::from_str(&addr.to_string())
-                                .expect("Invalid resolver address");
-
-                            AnswersBuilder::build_answers_from_resolver(
-                                &query,
-                                &self.udp_socket,
-                                &addr,
-                            )
-                            .unwrap()
+            self.refresh_secondaries();
+
+            // Waiting for requests is interrupted when a secondary zone needs a refresh
+            let deadline = self.secondaries.iter().map(SecondaryZone::deadline).min();
+            let request = match deadline {
+                Some(deadline) => receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    .map_err(|err| err == RecvTimeoutError::Disconnected),
+                None => receiver.recv().map_err(|_| true),
+            };
+
+            match request {
+                Ok(request) => {
+                    for response in self.respond(&request, resolver.as_ref()) {
+                        request.reply(&response);
+                    }
+                }
+                Err(is_disconnected) if is_disconnected => break Ok(()),
+                Err(_) => {}
+            }
+        }
+    }
+
+    // Refreshes the secondary zones that are due, and stops serving the ones that expired
+    fn refresh_secondaries(&mut self) {
+        let now = Instant::now();
+        let mut transferred = Vec::new();
+
+        for secondary in &mut self.secondaries {
+            let index = self
+                .zones
+                .iter()
+                .position(|zone| zone.origin() == secondary.origin());
+
+            match (
+                secondary.refresh(index.map(|index| &self.zones[index]), now),
+                index,
+            ) {
+                (Refresh::Unchanged, _) | (Refresh::Expired, None) => {}
+                (Refresh::Transferred(zone), index) => {
+                    eprintln!(
+                        "Transferred {} from {}",
+                        secondary.origin(),
+                        secondary.primary()
+                    );
+
+                    match index {
+                        Some(index) => self.zones[index] = *zone,
+                        None => self.zones.push(*zone),
+                    }
+
+                    transferred.push(secondary.origin().to_string());
+                }
+                (Refresh::Expired, Some(index)) => {
+                    eprintln!("{} expired, it is no longer served", secondary.origin());
+                    self.zones.remove(index);
+                }
+            }
+        }
+
+        for origin in transferred {
+            self.notify_secondaries(&origin);
+        }
+    }
+
+    // Sends a NOTIFY with the current SOA record of a zone to each of its secondaries
+    fn notify_secondaries(&self, origin: &str) {
+        let Some(soa) = self
+            .zones
+            .iter()
+            .find(|zone| zone.origin() == origin)
+            .and_then(AuthoritativeZone::soa_record)
+        else {
+            return;
+        };
+
+        for notifier in self
+            .notifiers
+            .iter()
+            .filter(|notifier| notifier.origin() == origin)
+        {
+            notifier.notify(soa.clone());
+        }
+    }
+
+    // The response to a request, in several messages for zone transfers and none at all for
+    // requests that get no response
+    fn respond(
+        &mut self,
+        received: &Request,
+        resolver: Option<&(UdpSocket, SocketAddr)>,
+    ) -> Vec<Bytes> {
+        let listener = &received.listener;
+        let source = received.source;
+        let request = &received.message[..];
+
+        let mut query = match MessageDecoder::decode(request) {
+            Ok(query) => query,
+            Err(err) => {
+                eprintln!("Failed to decode request from {}: {}", source, err);
+
+                return Self::error_response(request, &err).into_iter().collect();
+            }
+        };
+
+        // Responses are never answered, e.g. a NOTIFY acknowledgement sent to us
+        if query.header.query_indicator {
+            return Vec::new();
+        }
+
+        let has_extra_questions =
+            matches!(query.header.operation_code, OperationCode::StandardQuery)
+                && query.questions.len() > 1;
+
+        // The others are dropped, the response only echoes the question it answers
+        if has_extra_questions && self.question_policy == QuestionPolicy::First {
+            query.questions.truncate(1);
+        }
+
+        let is_notify = matches!(query.header.operation_code, OperationCode::Notify);
+        let is_update = matches!(query.header.operation_code, OperationCode::Update);
+        let is_zone_transfer = query
+            .questions
+            .iter()
+            .any(|question| question.kind.is_zone_transfer());
+
+        let tsig_error = query
+            .tsig
+            .as_ref()
+            .map(|tsig| self.tsig_keys.verify(request, tsig));
+        let is_authenticated = tsig_error == Some(TsigErrorCode::NoError);
+        let is_allowed = listener.allows(source.ip());
+        let is_refused = !self.tsig_keys.is_empty()
+            && (is_notify || is_update || is_zone_transfer)
+            && !is_authenticated;
+
+        // Reason for a SERVFAIL or REFUSED response, sent to clients that use EDNS
+        let mut extended_error = if !is_allowed {
+            Some(ExtendedError::new(
+                InfoCode::Prohibited,
+                "client is not allowed to use this listener",
+            ))
+        } else if is_refused {
+            Some(ExtendedError::new(
+                InfoCode::Prohibited,
+                "request must be signed",
+            ))
+        } else {
+            None
+        };
+
+        let mut transfer_records = Vec::new();
+
+        let code = match tsig_error {
+            _ if !is_allowed => ResponseCode::Refused,
+            Some(error) if error != TsigErrorCode::NoError => ResponseCode::NotAuth,
+            _ if is_refused => ResponseCode::Refused,
+            _ if is_notify => self.notify(&query, &source),
+            _ if is_zone_transfer => match self.transfer(&query, listener) {
+                Ok(records) => {
+                    transfer_records = records;
+                    ResponseCode::NoErrorCondition
+                }
+                Err((code, error)) => {
+                    extended_error = error;
+                    code
+                }
+            },
+            _ if is_update => {
+                let (code, error) = self.update(request, &source, listener);
+                extended_error = error;
+                code
+            }
+            _ if has_extra_questions && self.question_policy == QuestionPolicy::FormatError => {
+                ResponseCode::FormatError
+            }
+            // The server has IN data and its own identity in CH, nothing in other
+            // classes
+            _ if query.questions.iter().any(|question| {
+                !matches!(
+                    question.class,
+                    QuestionClass::DnsClass(DnsClass::IN | DnsClass::CH) | QuestionClass::ALL
+                )
+            }) =>
+            {
+                ResponseCode::NotImplemented
+            }
+            _ if matches!(query.header.operation_code, OperationCode::StandardQuery) => {
+                ResponseCode::NoErrorCondition
+            }
+            _ => ResponseCode::NotImplemented,
+        };
+
+        let dnssec_ok = query.edns.as_ref().is_some_and(|edns| edns.dnssec_ok);
+        let is_validating = !self.trust_anchors.is_empty() && !query.header.checking_disabled;
+
+        let mut resolver_failed = false;
+        let mut auth_answer = false;
+
+        let local_answer = match code {
+            ResponseCode::NoErrorCondition if !is_zone_transfer && !is_notify && !is_update => {
+                self.answer_locally(&query.questions, dnssec_ok, listener)
+            }
+            _ => None,
+        };
+
+        let (code, mut answers, mut authorities, mut additionals, security) =
+            match (resolver, local_answer) {
+                _ if is_zone_transfer => {
+                    auth_answer = !transfer_records.is_empty();
+
+                    (
+                        code,
+                        transfer_records,
+                        Vec::new(),
+                        Vec::new(),
+                        Security::Insecure,
+                    )
+                }
+                _ if is_notify || is_update => {
+                    (code, Vec::new(), Vec::new(), Vec::new(), Security::Insecure)
+                }
+                _ if matches!(
+                    code,
+                    ResponseCode::FormatError
+                        | ResponseCode::NotAuth
+                        | ResponseCode::Refused
+                        | ResponseCode::NotImplemented
+                ) =>
+                {
+                    (code, Vec::new(), Vec::new(), Vec::new(), Security::Insecure)
+                }
+                (_, Some(lookup)) => {
+                    auth_answer = lookup.authoritative;
+                    extended_error = lookup.extended_error;
+
+                    (
+                        lookup.code,
+                        lookup.answers,
+                        lookup.authorities,
+                        lookup.additionals,
+                        Security::Insecure,
+                    )
+                }
+                (Some((socket, addr)), None) => {
+                    // DNSSEC records are only fetched to validate them or when the
+                    // client asked for them
+                    let edns = (is_validating || dnssec_ok).then(|| Edns::new(true));
+
+                    let responses = match AnswersBuilder::forward_questions(
+                        &query,
+                        edns.as_ref(),
+                        socket,
+                        addr,
+                    ) {
+                        Ok(responses) => responses,
+                        // The resolver timed out or sent something that does not
+                        // decode, which is a server failure from the client's side
+                        Err(err) => {
+                            eprintln!("Failed to resolve query from {}: {}", source, err);
+
+                            extended_error = Some(match err {
+                                ServerError::ResolverTimeout(_) => ExtendedError::new(
+                                    InfoCode::NoReachableAuthority,
+                                    "resolver timed out",
+                                ),
+                                _ => ExtendedError::new(InfoCode::NetworkError, "resolver failed"),
+                            });
+                            resolver_failed = true;
+                            Vec::new()
                         }
-                        None => AnswersBuilder::build_answers(&query).unwrap(),
                     };
 
-                    let response_message = Message {
-                        header: Header {
-                            id: query.header.id,
-                            query_indicator: true,
-                            operation_code: query.header.operation_code,
-                            auth_answer: false,
-                            truncation: false,
-                            recursion_desired: query.header.recursion_desired,
-                            recursion_available: false,
-                            reserve: 0,
-                            code: if matches!(
-                                query.header.operation_code,
-                                OperationCode::StandardQuery
-                            ) {
-                                ResponseCode::NoErrorCondition
-                            } else {
-                                ResponseCode::NotImplemented
-                            },
-                            question_count: query.questions.len() as u16,
-                            answer_record_count: answers.len() as u16,
-                            auth_record_count: 0,
-                            additional_record_count: 0,
-                        },
-                        questions: query.questions,
-                        answers,
-                    };
+                    let mut security = Security::Insecure;
+
+                    if is_validating && !resolver_failed {
+                        let mut validator = Validator::new(&self.trust_anchors, socket, addr);
+
+                        security = Security::Secure;
+
+                        for (question, response) in query.questions.iter().zip(&responses) {
+                            security = security.combine(validator.validate(question, response));
+                        }
+                    }
+
+                    let additionals =
+                        AnswersBuilder::build_additionals(&query, &responses, socket, addr);
 
-                    let response = MessageEncoder::encode(&response_message);
+                    // The resolver's RCODE is passed on, e.g. NXDOMAIN
+                    let code = responses
+                        .iter()
+                        .map(|response| response.header.code)
+                        .fold(code, combine_codes);
 
-                    self.udp_socket
-                        .send_to(&response, source)
-                        .expect("Failed to send response");
+                    let mut answers = Vec::new();
+                    let mut authorities = Vec::new();
+
+                    for response in responses {
+                        answers.extend(response.answers);
+                        authorities.extend(response.authorities);
+                    }
+
+                    (code, answers, authorities, additionals, security)
                 }
-                Err(e) => {
-                    eprintln!("Error receiving data: {}", e);
-                    break Ok(());
+                (None, None) => (
+                    code,
+                    AnswersBuilder::build_answers(&query),
+                    Vec::new(),
+                    Vec::new(),
+                    Security::Insecure,
+                ),
+            };
+
+        if security == Security::Bogus {
+            extended_error = Some(ExtendedError::new(
+                InfoCode::DnssecBogus,
+                "DNSSEC validation failed",
+            ));
+        }
+
+        let code = if security == Security::Bogus || resolver_failed {
+            answers.clear();
+            authorities.clear();
+            additionals.clear();
+            ResponseCode::ServerFailure
+        } else {
+            code
+        };
+
+        // DNSSEC records only go to clients that asked for them, either with the DO
+        // bit or by querying their type (RFC 4035 section 3.2.1)
+        if !dnssec_ok {
+            let is_requested = |answer: &Answer| {
+                !matches!(answer.kind, DnsType::RRSIG | DnsType::NSEC | DnsType::NSEC3)
+                    || query.questions.iter().any(|question| {
+                        matches!(question.kind, QuestionType::DnsType(kind) if kind == answer.kind)
+                    })
+            };
+
+            answers.retain(is_requested);
+            authorities.retain(is_requested);
+            additionals.retain(is_requested);
+        }
+
+        // Clients that sent an OPT record get one back
+        let edns = query.edns.as_ref().map(|_| {
+            let mut edns = Edns::new(dnssec_ok);
+
+            if let Some(error) = extended_error
+                .as_ref()
+                .filter(|_| matches!(code, ResponseCode::ServerFailure | ResponseCode::Refused))
+            {
+                edns.options.push(error.to_option());
+            }
+
+            edns
+        });
+
+        // Zone transfers take as many messages as they need, other responses one (RFC 5936
+        // section 2.2)
+        let sections = if is_zone_transfer {
+            split_records(answers)
+        } else {
+            vec![answers]
+        };
+        let mut responses = Vec::new();
+        let mut previous_tsig = None;
+
+        for answers in sections {
+            let mut response_message = Message {
+                header: Header {
+                    id: query.header.id,
+                    query_indicator: true,
+                    operation_code: query.header.operation_code,
+                    auth_answer,
+                    truncation: false,
+                    recursion_desired: query.header.recursion_desired,
+                    recursion_available: false,
+                    reserve: 0,
+                    // Only set for clients that show they understand it (RFC 6840
+                    // section 5.7)
+                    authentic_data: security == Security::Secure
+                        && (dnssec_ok || query.header.authentic_data),
+                    checking_disabled: query.header.checking_disabled,
+                    code,
+                    question_count: query.questions.len() as u16,
+                    answer_record_count: answers.len() as u16,
+                    auth_record_count: authorities.len() as u16,
+                    additional_record_count: additionals.len() as u16 + edns.is_some() as u16,
+                },
+                questions: query.questions.clone(),
+                answers,
+                authorities: mem::take(&mut authorities),
+                additionals: mem::take(&mut additionals),
+                edns: edns.clone(),
+                tsig: None,
+            };
+
+            // Additional records are dropped first when the response does not fit the
+            // client's buffer (RFC 2181 section 9). If it still does not fit, it is sent
+            // without answer and authority records and with the TC bit set, so the client
+            // can retry over TCP
+            let payload_size = match listener.transport {
+                Transport::Tcp => u16::MAX as usize,
+                Transport::Udp => query.edns.as_ref().map_or(DNS_MESSAGE_PACKET_SIZE, |edns| {
+                    (edns.udp_payload_size as usize)
+                        .clamp(DNS_MESSAGE_PACKET_SIZE, EDNS_UDP_PAYLOAD_SIZE as usize)
+                }),
+            };
+
+            if MessageEncoder::encode(&response_message).len() > payload_size {
+                response_message.header.additional_record_count -=
+                    response_message.additionals.len() as u16;
+                response_message.additionals.clear();
+            }
+
+            if MessageEncoder::encode(&response_message).len() > payload_size {
+                response_message.header.truncation = true;
+                response_message.answers.clear();
+                response_message.authorities.clear();
+                response_message.header.answer_record_count = 0;
+                response_message.header.auth_record_count = 0;
+            }
+
+            // Signed requests get signed responses, the MAC covers the message without
+            // the TSIG record. Later messages of a transfer chain on to the one before.
+            if let (Some(request_tsig), Some(error)) = (&query.tsig, tsig_error) {
+                let unsigned_response = MessageEncoder::encode(&response_message);
+
+                let tsig = match &previous_tsig {
+                    Some(previous) => self
+                        .tsig_keys
+                        .sign_continuation(previous, &unsigned_response),
+                    None => self
+                        .tsig_keys
+                        .sign_response(request_tsig, error, &unsigned_response),
+                };
+
+                previous_tsig = Some(tsig.clone());
+                response_message.tsig = Some(tsig);
+                response_message.header.additional_record_count += 1;
+            }
+
+            responses.push(MessageEncoder::encode(&response_message));
+        }
+
+        responses
+    }
+
+    // Applies a dynamic update to the local zone named in its zone section, returning the RCODE
+    // of the response and why the update was refused
+    fn update(
+        &mut self,
+        request: &[u8],
+        source: &SocketAddr,
+        listener: &Listener,
+    ) -> (ResponseCode, Option<ExtendedError>) {
+        if !self
+            .update_acl
+            .iter()
+            .any(|prefix| prefix.contains(source.ip()))
+        {
+            eprintln!("Refusing UPDATE from {}: not in the update ACL", source);
+
+            return (
+                ResponseCode::Refused,
+                Some(ExtendedError::new(
+                    InfoCode::Prohibited,
+                    "client is not allowed to update",
+                )),
+            );
+        }
+
+        let update = match UpdateDecoder::decode(request) {
+            Ok(update) => update,
+            Err(err) => {
+                eprintln!("Failed to decode UPDATE from {}: {}", source, err);
+                return (ResponseCode::FormatError, None);
+            }
+        };
+
+        let origin = update.zone.name.trim_end_matches('.').to_lowercase();
+
+        // Only IN zones are served, and an update names the zone's apex (RFC 2136 section 3.1)
+        let zone = match update.zone.class {
+            QuestionClass::DnsClass(DnsClass::IN) => self
+                .zones
+                .iter_mut()
+                .find(|zone| zone.origin() == origin && listener.serves(&origin)),
+            _ => None,
+        };
+
+        let Some(zone) = zone else {
+            return (ResponseCode::NotAuth, None);
+        };
+
+        // The primary has the master copy, changes come from there with the next refresh
+        if self
+            .secondaries
+            .iter()
+            .any(|secondary| secondary.origin() == origin)
+        {
+            return (
+                ResponseCode::Refused,
+                Some(ExtendedError::new(
+                    InfoCode::NotSupported,
+                    "zone is a secondary, updates go to its primary",
+                )),
+            );
+        }
+
+        let serial = zone.serial();
+        let result = zone.update(&update);
+
+        if zone.serial() != serial {
+            self.notify_secondaries(&origin);
+        }
+
+        match result {
+            // Zones only refuse updates when they are signed offline
+            Ok(ResponseCode::Refused) => (
+                ResponseCode::Refused,
+                Some(ExtendedError::new(
+                    InfoCode::NotSupported,
+                    "zone is signed offline",
+                )),
+            ),
+            Ok(code) => (code, None),
+            Err(err) => {
+                eprintln!("Failed to update {} for {}: {}", origin, source, err);
+                (ResponseCode::ServerFailure, None)
+            }
+        }
+    }
+
+    // Answers from the local zones when every question falls in one of them. The RCODE is the
+    // most severe one of the answers, and the AA bit is only set when no answer is a referral.
+    fn answer_locally(
+        &self,
+        questions: &[Question],
+        dnssec_ok: bool,
+        listener: &Listener,
+    ) -> Option<Lookup> {
+        let mut lookups = questions
+            .iter()
+            .map(|question| match self.zone_for(question, listener) {
+                Some(zone) => Some(
+                    zone.lookup(
+                        &question.name,
+                        question.kind.into(),
+                        dnssec_ok,
+                        self.any_policy,
+                    )
+                    .unwrap_or_else(|err| {
+                        eprintln!(
+                            "Failed to answer {} from {}: {}",
+                            question.name,
+                            zone.origin(),
+                            err
+                        );
+
+                        Lookup {
+                            code: ResponseCode::ServerFailure,
+                            authoritative: false,
+                            answers: Vec::new(),
+                            authorities: Vec::new(),
+                            additionals: Vec::new(),
+                            extended_error: None,
+                        }
+                    }),
+                ),
+                None => self
+                    .unavailable_secondary(question, listener)
+                    .or_else(|| self.chaos(question))
+                    .or_else(|| self.synthesize_ptr(question, listener))
+                    .or_else(|| self.minimal_any(question)),
+            })
+            .collect::<Option<Vec<Lookup>>>()?
+            .into_iter();
+
+        let mut merged = lookups.next()?;
+
+        for lookup in lookups {
+            merged.code = combine_codes(merged.code, lookup.code);
+            merged.extended_error = merged.extended_error.or(lookup.extended_error);
+
+            merged.authoritative &= lookup.authoritative;
+            merged.answers.extend(lookup.answers);
+            merged.authorities.extend(lookup.authorities);
+            merged.additionals.extend(lookup.additionals);
+        }
+
+        Some(merged)
+    }
+
+    // Secondary zones have no data to answer from before their first transfer and once it
+    // expired (RFC 1034 section 4.3.5)
+    fn unavailable_secondary(&self, question: &Question, listener: &Listener) -> Option<Lookup> {
+        if !matches!(
+            question.class,
+            QuestionClass::DnsClass(DnsClass::IN) | QuestionClass::ALL
+        ) {
+            return None;
+        }
+
+        let name = question.name.trim_end_matches('.').to_lowercase();
+
+        self.secondaries
+            .iter()
+            .find(|secondary| {
+                listener.serves(secondary.origin()) && is_subdomain(&name, secondary.origin())
+            })
+            .map(|secondary| Lookup {
+                code: ResponseCode::ServerFailure,
+                authoritative: false,
+                answers: Vec::new(),
+                authorities: Vec::new(),
+                additionals: Vec::new(),
+                extended_error: Some(ExtendedError::new(
+                    InfoCode::NoReachableAuthority,
+                    &format!("{} has no data from its primary", secondary.origin()),
+                )),
+            })
+    }
+
+    // A NOTIFY from the primary of a secondary zone has it refreshed right away (RFC 1996
+    // section 3.7). Only the primary's address is trusted to send one.
+    fn notify(&mut self, query: &Message, source: &SocketAddr) -> ResponseCode {
+        let origin = query
+            .questions
+            .first()
+            .map(|question| question.name.trim_end_matches('.').to_lowercase())
+            .unwrap_or_default();
+
+        match self
+            .secondaries
+            .iter_mut()
+            .find(|secondary| secondary.origin() == origin)
+        {
+            Some(secondary) if secondary.primary().ip() == source.ip() => {
+                secondary.refresh_now();
+                ResponseCode::NoErrorCondition
+            }
+            Some(_) => {
+                eprintln!(
+                    "Refusing NOTIFY from {}: not the primary of {}",
+                    source, origin
+                );
+                ResponseCode::Refused
+            }
+            None => {
+                eprintln!(
+                    "Refusing NOTIFY from {}: not a secondary for {}",
+                    source, origin
+                );
+                ResponseCode::NotAuth
+            }
+        }
+    }
+
+    // The records answering an AXFR or IXFR query for a zone the listener serves, or the
+    // RCODE refusing it. AXFR is only answered over TCP. IXFR over UDP gets the SOA record
+    // alone, which tells the client to ask again over TCP (RFC 1995 section 2).
+    fn transfer(
+        &self,
+        query: &Message,
+        listener: &Listener,
+    ) -> Result<Vec<Answer>, (ResponseCode, Option<ExtendedError>)> {
+        let [question] = query.questions.as_slice() else {
+            return Err((ResponseCode::FormatError, None));
+        };
+
+        let origin = question.name.trim_end_matches('.').to_lowercase();
+
+        let zone = match question.class {
+            QuestionClass::DnsClass(DnsClass::IN) => self
+                .zones
+                .iter()
+                .find(|zone| zone.origin() == origin && listener.serves(&origin)),
+            _ => None,
+        };
+
+        let Some(zone) = zone else {
+            return Err((ResponseCode::NotAuth, None));
+        };
+
+        let records = match (question.kind, listener.transport) {
+            (QuestionType::AXFR, Transport::Udp) => {
+                return Err((
+                    ResponseCode::Refused,
+                    Some(ExtendedError::new(
+                        InfoCode::NotSupported,
+                        "AXFR is only answered over TCP",
+                    )),
+                ))
+            }
+            (QuestionType::IXFR, transport) => {
+                // The client's version is in the authority section (RFC 1995 section 3)
+                let serial = query
+                    .authorities
+                    .iter()
+                    .find_map(|record| match &record.data {
+                        RData::Soa(soa) if record.name.eq_ignore_ascii_case(&origin) => {
+                            Some(soa.serial)
+                        }
+                        _ => None,
+                    });
+
+                let Some(serial) = serial else {
+                    return Err((ResponseCode::FormatError, None));
+                };
+
+                match transport {
+                    Transport::Udp => zone.soa_record().cloned().into_iter().collect(),
+                    Transport::Tcp => match zone.changes_since(serial) {
+                        Some(records) => records,
+                        None => zone.transfer().map_err(|err| {
+                            eprintln!("Failed to transfer {}: {}", origin, err);
+                            (ResponseCode::ServerFailure, None)
+                        })?,
+                    },
                 }
             }
+            _ => zone.transfer().map_err(|err| {
+                eprintln!("Failed to transfer {}: {}", origin, err);
+                (ResponseCode::ServerFailure, None)
+            })?,
+        };
+
+        Ok(records)
+    }
+
+    // Questions in class CH are only about the server itself. Its names are answered with a
+    // TXT record, everything else in the class is refused.
+    fn chaos(&self, question: &Question) -> Option<Lookup> {
+        if !matches!(question.class, QuestionClass::DnsClass(DnsClass::CH)) {
+            return None;
         }
+
+        let (is_identity, text) = match question.name.trim_end_matches('.').to_lowercase().as_str()
+        {
+            "version.bind" | "version.server" => (true, self.identity.version.as_ref()),
+            "hostname.bind" | "id.server" => (true, self.identity.hostname.as_ref()),
+            _ => (false, None),
+        };
+
+        let Some(text) = text else {
+            let extended_error = if is_identity {
+                ExtendedError::new(InfoCode::Prohibited, "server identity is hidden")
+            } else {
+                ExtendedError::new(InfoCode::NotAuthoritative, "no data in class CH")
+            };
+
+            return Some(Lookup {
+                code: ResponseCode::Refused,
+                authoritative: false,
+                answers: Vec::new(),
+                authorities: Vec::new(),
+                additionals: Vec::new(),
+                extended_error: Some(extended_error),
+            });
+        };
+
+        let answers = match question.kind {
+            QuestionType::DnsType(DnsType::TXT) | QuestionType::ALL => vec![Answer {
+                name: question.name.clone(),
+                kind: DnsType::TXT,
+                class: DnsClass::CH,
+                ttl: 0,
+                data: RData::Strings(text.as_bytes().chunks(255).map(<[u8]>::to_vec).collect()),
+            }],
+            _ => Vec::new(),
+        };
+
+        Some(Lookup {
+            code: ResponseCode::NoErrorCondition,
+            authoritative: true,
+            answers,
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            extended_error: None,
+        })
+    }
+
+    // Reverse lookups without a local reverse zone are answered from the zones that synthesize
+    // PTR records. Addresses none of them has are left to the resolver.
+    fn synthesize_ptr(&self, question: &Question, listener: &Listener) -> Option<Lookup> {
+        if !matches!(question.kind, QuestionType::DnsType(DnsType::PTR))
+            || !matches!(question.class, QuestionClass::DnsClass(DnsClass::IN))
+        {
+            return None;
+        }
+
+        let addr = reverse_address(&question.name)?;
+
+        let answers: Vec<Answer> = self
+            .zones
+            .iter()
+            .filter(|zone| listener.serves(zone.origin()))
+            .flat_map(|zone| zone.reverse_lookup(&question.name, addr))
+            .collect();
+
+        (!answers.is_empty()).then(|| Lookup {
+            code: ResponseCode::NoErrorCondition,
+            authoritative: true,
+            answers,
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            extended_error: None,
+        })
+    }
+
+    // With the HINFO policy, ANY questions outside the local zones are not forwarded. Their
+    // answer is not authoritative, the server has no data for the name.
+    fn minimal_any(&self, question: &Question) -> Option<Lookup> {
+        if self.any_policy != AnyPolicy::Hinfo || !matches!(question.kind, QuestionType::ALL) {
+            return None;
+        }
+
+        Some(Lookup {
+            code: ResponseCode::NoErrorCondition,
+            authoritative: false,
+            answers: vec![minimal_any(&question.name)],
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            extended_error: None,
+        })
+    }
+
+    // The closest enclosing local zone of an IN question among those the listener serves. The
+    // zones hold IN data only, so it is also all there is for a question of class ANY.
+    fn zone_for(&self, question: &Question, listener: &Listener) -> Option<&AuthoritativeZone> {
+        if !matches!(
+            question.class,
+            QuestionClass::DnsClass(DnsClass::IN) | QuestionClass::ALL
+        ) {
+            return None;
+        }
+
+        let name = question.name.trim_end_matches('.').to_lowercase();
+
+        self.zones
+            .iter()
+            .filter(|zone| listener.serves(zone.origin()) && is_subdomain(&name, zone.origin()))
+            .max_by_key(|zone| zone.origin().len())
+    }
+
+    // Requests that cannot be decoded, e.g. because of a malformed or misplaced TSIG record, are
+    // answered with the RCODE of the error, and the question when it decodes. Nothing is sent
+    // back when not even the header decodes, or when the message is itself a response.
+    fn error_response(request: &[u8], err: &ServerError) -> Option<Bytes> {
+        let message = Bytes::copy_from_slice(request);
+        let mut buf = message.clone();
+        let header = HeaderDecoder::decode(&mut buf).ok()?;
+
+        if header.query_indicator {
+            return None;
+        }
+
+        let questions = QuestionsDecoder::new(&mut buf, header.question_count, message.len())
+            .decode()
+            .unwrap_or_default();
+
+        let response = Message {
+            header: Header {
+                query_indicator: true,
+                auth_answer: false,
+                truncation: false,
+                recursion_available: false,
+                reserve: 0,
+                authentic_data: false,
+                code: err.response_code(),
+                question_count: questions.len() as u16,
+                answer_record_count: 0,
+                auth_record_count: 0,
+                additional_record_count: 0,
+                ..header
+            },
+            questions,
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            tsig: None,
+        };
+
+        Some(MessageEncoder::encode(&response))
+    }
+}
+
+// Splits the records of a zone transfer over the answer sections of several messages, each
+// well below the 64 KB a message over TCP can have
+fn split_records(records: Vec<Answer>) -> Vec<Vec<Answer>> {
+    let mut sections = vec![Vec::new()];
+    let mut size = 0;
+
+    for record in records {
+        let record_size = AnswersEncoder.encode_answer(&record).len();
+
+        if size + record_size > TRANSFER_MESSAGE_SIZE && size > 0 {
+            sections.push(Vec::new());
+            size = 0;
+        }
+
+        size += record_size;
+        sections.last_mut().unwrap_or(&mut Vec::new()).push(record);
+    }
+
+    sections
+}
+
+// The RCODE of a response answering several questions. Failing to answer a question outweighs
+// a name error in another, which outweighs a successful answer; between two failures the first
+// one stays.
+fn combine_codes(current: ResponseCode, next: ResponseCode) -> ResponseCode {
+    let severity = |code: &ResponseCode| match code {
+        ResponseCode::NoErrorCondition => 0,
+        ResponseCode::NameError => 1,
+        _ => 2,
+    };
+
+    if severity(&next) > severity(&current) {
+        next
+    } else {
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::Ipv4Addr,
+        sync::{Arc, Mutex},
+        thread,
+        time::SystemTime,
+    };
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+    use crate::message::edns::EDE_OPTION_CODE;
+    use crate::notify::NotifyTarget;
+    use crate::secondary::SecondarySource;
+    use crate::signer::{SigningKey, ZoneSigner};
+    use crate::tsig::TsigKey;
+    use crate::zone::{Zone, ZoneFileParser};
+
+    const SIGNATURE_VALIDITY: Duration = Duration::from_secs(7 * 24 * 3600);
+
+    // The stub resolvers answer right away, a question they drop is not answered at all
+    const STUB_TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn query(name: &str, kind: QuestionType) -> Message {
+        Message {
+            header: Header {
+                id: 7,
+                query_indicator: false,
+                operation_code: OperationCode::StandardQuery,
+                auth_answer: false,
+                truncation: false,
+                recursion_desired: false,
+                recursion_available: false,
+                reserve: 0,
+                authentic_data: false,
+                checking_disabled: false,
+                code: ResponseCode::NoErrorCondition,
+                question_count: 1,
+                answer_record_count: 0,
+                auth_record_count: 0,
+                additional_record_count: 1,
+            },
+            questions: vec![Question {
+                name: name.to_string(),
+                kind,
+                class: QuestionClass::DnsClass(DnsClass::IN),
+            }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: Some(Edns::new(false)),
+            tsig: None,
+        }
+    }
+
+    // The first response to `query` from 127.0.0.1 on a UDP listener
+    fn ask(server: &mut DnsServer, query: &Message) -> Message {
+        ask_from(server, query, "127.0.0.1:5300")
+    }
+
+    fn ask_from(server: &mut DnsServer, query: &Message, source: &str) -> Message {
+        let request = Request::received(
+            Listener::from_str("udp:127.0.0.1:53").unwrap(),
+            &MessageEncoder::encode(query),
+            source.parse().unwrap(),
+        );
+
+        MessageDecoder::decode(&server.respond(&request, None)[0]).unwrap()
+    }
+
+    // The response to `query` when questions go to the resolver at `resolver_addr`
+    fn resolve(server: &mut DnsServer, query: &Message, resolver_addr: SocketAddr) -> Message {
+        let request = Request::received(
+            Listener::from_str("udp:127.0.0.1:53").unwrap(),
+            &MessageEncoder::encode(query),
+            "127.0.0.1:5300".parse().unwrap(),
+        );
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(STUB_TIMEOUT)).unwrap();
+
+        MessageDecoder::decode(&server.respond(&request, Some(&(socket, resolver_addr)))[0])
+            .unwrap()
+    }
+
+    // A resolver answering from `zones` with their DNSSEC records, as a recursive resolver
+    // would: DS questions go to the parent zone, others to the closest zone
+    fn resolver(zones: Vec<Zone>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let zones: Vec<AuthoritativeZone> = zones
+                .into_iter()
+                .map(|zone| AuthoritativeZone::transferred(&zone.origin, zone.records).unwrap())
+                .collect();
+            let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+
+            while let Ok((len, source)) = socket.recv_from(&mut buf) {
+                let query = MessageDecoder::decode(&buf[..len]).unwrap();
+                let name = query.questions[0].name.to_lowercase();
+                let kind = query.questions[0].kind;
+
+                let Some(zone) = zones
+                    .iter()
+                    .filter(|zone| is_subdomain(&name, zone.origin()))
+                    .filter(|zone| {
+                        !matches!(kind, QuestionType::DnsType(DnsType::DS)) || name != zone.origin()
+                    })
+                    .max_by_key(|zone| zone.origin().len())
+                else {
+                    continue;
+                };
+
+                let lookup = zone
+                    .lookup(&name, kind.into(), true, AnyPolicy::All)
+                    .unwrap();
+                let response = Message {
+                    header: Header {
+                        query_indicator: true,
+                        code: lookup.code,
+                        answer_record_count: lookup.answers.len() as u16,
+                        auth_record_count: lookup.authorities.len() as u16,
+                        additional_record_count: lookup.additionals.len() as u16 + 1,
+                        ..query.header
+                    },
+                    questions: query.questions,
+                    answers: lookup.answers,
+                    authorities: lookup.authorities,
+                    additionals: lookup.additionals,
+                    edns: Some(Edns::new(true)),
+                    tsig: None,
+                };
+
+                socket
+                    .send_to(&MessageEncoder::encode(&response), source)
+                    .unwrap();
+            }
+        });
+
+        addr
+    }
+
+    // A resolver answering each question with the answer and additional records `answer` gives
+    // for it, or not at all when it gives none. Returns its address and the questions asked.
+    fn stub_resolver(
+        answer: impl Fn(&Question) -> Option<(Vec<Answer>, Vec<Answer>)> + Send + 'static,
+    ) -> (SocketAddr, Arc<Mutex<Vec<Question>>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let questions = Arc::new(Mutex::new(Vec::new()));
+        let asked = questions.clone();
+
+        thread::spawn(move || {
+            let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+
+            while let Ok((len, source)) = socket.recv_from(&mut buf) {
+                let query = MessageDecoder::decode(&buf[..len]).unwrap();
+
+                asked.lock().unwrap().push(query.questions[0].clone());
+
+                let Some((answers, additionals)) = answer(&query.questions[0]) else {
+                    continue;
+                };
+                let response = Message {
+                    header: Header {
+                        query_indicator: true,
+                        answer_record_count: answers.len() as u16,
+                        auth_record_count: 0,
+                        additional_record_count: additionals.len() as u16,
+                        ..query.header
+                    },
+                    questions: query.questions,
+                    answers,
+                    authorities: Vec::new(),
+                    additionals,
+                    edns: None,
+                    tsig: None,
+                };
+
+                socket
+                    .send_to(&MessageEncoder::encode(&response), source)
+                    .unwrap();
+            }
+        });
+
+        (addr, questions)
+    }
+
+    // The records of a master file for example.com
+    fn records(input: &str) -> Vec<Answer> {
+        ZoneFileParser::new(input, "example.com")
+            .parse()
+            .unwrap()
+            .records
+    }
+
+    // example.com signed at `signed_at`, with an unsigned delegation to insecure.example.com,
+    // and the anchor its key signing key is trusted with
+    fn signed_zone(signed_at: SystemTime) -> (Zone, TrustAnchor) {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     ns1 3600 IN A 192.0.2.1\n\
+                     www 300 IN A 192.0.2.2\n\
+                     insecure 3600 IN NS ns.insecure\n\
+                     ns.insecure 3600 IN A 192.0.2.3\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+
+        let key_signing_key = SigningKey::generate("example.com", true);
+        let anchor = TrustAnchor::from_str(&format!(
+            "example.com. IN DNSKEY {} 3 {} {}",
+            key_signing_key.dnskey.flags,
+            key_signing_key.dnskey.algorithm,
+            STANDARD.encode(&key_signing_key.dnskey.public_key)
+        ))
+        .unwrap();
+
+        let keys = vec![key_signing_key, SigningKey::generate("example.com", false)];
+        let zone = ZoneSigner::new(keys, SIGNATURE_VALIDITY)
+            .sign_at(zone, signed_at)
+            .unwrap();
+
+        (zone, anchor)
+    }
+
+    fn insecure_zone() -> Zone {
+        let input = "@ 3600 IN SOA ns hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns\n\
+                     ns 3600 IN A 192.0.2.3\n\
+                     www 300 IN A 192.0.2.4\n";
+
+        ZoneFileParser::new(input, "insecure.example.com")
+            .parse()
+            .unwrap()
+    }
+
+    // A validating server in front of a resolver for `zones`
+    fn validating_server(zones: Vec<Zone>, anchor: TrustAnchor) -> (DnsServer, SocketAddr) {
+        let server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_trust_anchors(vec![anchor]);
+
+        (server, resolver(zones))
+    }
+
+    fn dnssec_query(name: &str) -> Message {
+        let mut query = query(name, QuestionType::DnsType(DnsType::A));
+        query.edns = Some(Edns::new(true));
+        query
+    }
+
+    fn zone() -> AuthoritativeZone {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     ns1 3600 IN A 192.0.2.1\n\
+                     www 300 IN A 192.0.2.2\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+
+        AuthoritativeZone::transferred("example.com", zone.records).unwrap()
+    }
+
+    // The INFO-CODE of the response's Extended DNS Error
+    fn info_code(response: &Message) -> Option<u16> {
+        response
+            .edns
+            .as_ref()?
+            .options
+            .iter()
+            .find(|option| option.code == EDE_OPTION_CODE)
+            .map(|option| u16::from_be_bytes([option.data[0], option.data[1]]))
+    }
+
+    // A response to `query` with `id` and a single A record
+    fn address_response(query: &Message, id: u16, address: Ipv4Addr) -> Vec<u8> {
+        let response = Message {
+            header: Header {
+                id,
+                query_indicator: true,
+                answer_record_count: 1,
+                additional_record_count: 0,
+                ..query.header
+            },
+            questions: query.questions.clone(),
+            answers: vec![Answer {
+                name: query.questions[0].name.clone(),
+                kind: DnsType::A,
+                class: DnsClass::IN,
+                ttl: 300,
+                data: RData::A(address),
+            }],
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            tsig: None,
+        };
+
+        MessageEncoder::encode(&response).to_vec()
+    }
+
+    #[test]
+    fn secondary_without_data_is_not_served() {
+        let source = SecondarySource::from_str("example.com:127.0.0.1:9").unwrap();
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_secondaries(vec![SecondaryZone::new(source, None)]);
+
+        let response = ask(
+            &mut server,
+            &query("www.example.com", QuestionType::DnsType(DnsType::A)),
+        );
+
+        assert!(matches!(response.header.code, ResponseCode::ServerFailure));
+        assert!(!response.header.auth_answer);
+        assert_eq!(
+            info_code(&response),
+            Some(InfoCode::NoReachableAuthority.into())
+        );
+    }
+
+    #[test]
+    fn update_notifies_the_secondaries() {
+        let secondary = UdpSocket::bind("127.0.0.1:0").unwrap();
+        secondary
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let target = format!("example.com:{}", secondary.local_addr().unwrap());
+
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_zones(vec![zone()])
+            .with_update_acl(vec![AddressPrefix::from_str("127.0.0.1").unwrap()])
+            .with_notifiers(vec![Notifier::new(
+                NotifyTarget::from_str(&target).unwrap(),
+                None,
+            )]);
+
+        let mut update = query("example.com", QuestionType::DnsType(DnsType::SOA));
+        update.header.operation_code = OperationCode::Update;
+        update.header.auth_record_count = 1;
+        update.header.additional_record_count = 0;
+        update.edns = None;
+        update.authorities = vec![Answer {
+            name: "new.example.com".to_string(),
+            kind: DnsType::A,
+            class: DnsClass::IN,
+            ttl: 300,
+            data: RData::A("192.0.2.9".parse().unwrap()),
+        }];
+
+        let response = ask(&mut server, &update);
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+
+        // The NOTIFY carries the new SOA record, and is sent again until it is acknowledged
+        let mut buf = [0; 512];
+        let mut notifies = Vec::new();
+
+        for _ in 0..2 {
+            let (len, source) = secondary.recv_from(&mut buf).unwrap();
+            notifies.push((MessageDecoder::decode(&buf[..len]).unwrap(), source));
+        }
+
+        let (mut notify, source) = notifies.pop().unwrap();
+
+        assert!(matches!(
+            notify.header.operation_code,
+            OperationCode::Notify
+        ));
+        assert_eq!(notify.header.id, notifies[0].0.header.id);
+        assert_eq!(notify.questions[0].name, "example.com");
+        assert!(matches!(
+            &notify.answers[0].data,
+            RData::Soa(soa) if soa.serial.0 == 2
+        ));
+
+        notify.header.query_indicator = true;
+        secondary
+            .send_to(&MessageEncoder::encode(&notify), source)
+            .unwrap();
+    }
+
+    #[test]
+    fn notify_is_only_accepted_from_the_primary() {
+        let source = SecondarySource::from_str("example.com:127.0.0.1:9").unwrap();
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_secondaries(vec![SecondaryZone::new(source, None)]);
+
+        let notify = |name| {
+            let mut notify = query(name, QuestionType::DnsType(DnsType::SOA));
+            notify.header.operation_code = OperationCode::Notify;
+            notify
+        };
+
+        let from_primary = ask_from(&mut server, &notify("example.com"), "127.0.0.1:53");
+        assert!(matches!(
+            from_primary.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(from_primary.header.query_indicator);
+        assert!(server.secondaries[0].deadline() <= Instant::now());
+
+        let from_other = ask_from(&mut server, &notify("example.com"), "192.0.2.1:53");
+        assert!(matches!(from_other.header.code, ResponseCode::Refused));
+
+        let unknown_zone = ask_from(&mut server, &notify("example.org"), "127.0.0.1:53");
+        assert!(matches!(unknown_zone.header.code, ResponseCode::NotAuth));
+    }
+
+    #[test]
+    fn secure_answers_get_the_ad_bit() {
+        let (zone, anchor) = signed_zone(SystemTime::now());
+        let (mut server, resolver_addr) = validating_server(vec![zone, insecure_zone()], anchor);
+
+        let response = resolve(&mut server, &dnssec_query("www.example.com"), resolver_addr);
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(response.header.authentic_data);
+        assert!(response
+            .answers
+            .iter()
+            .any(|answer| answer.data == RData::A("192.0.2.2".parse().unwrap())));
+
+        // Proof that the name does not exist is just as secure
+        let response = resolve(
+            &mut server,
+            &dnssec_query("missing.example.com"),
+            resolver_addr,
+        );
+
+        assert!(matches!(response.header.code, ResponseCode::NameError));
+        assert!(response.header.authentic_data);
+    }
+
+    #[test]
+    fn bogus_answers_are_servfail() {
+        let (mut zone, anchor) = signed_zone(SystemTime::now());
+
+        // The address changes after it was signed
+        for record in &mut zone.records {
+            if record.name == "www.example.com" && record.kind == DnsType::A {
+                record.data = RData::A("192.0.2.99".parse().unwrap());
+            }
+        }
+
+        let (mut server, resolver_addr) = validating_server(vec![zone], anchor);
+
+        let response = resolve(&mut server, &dnssec_query("www.example.com"), resolver_addr);
+
+        assert!(matches!(response.header.code, ResponseCode::ServerFailure));
+        assert!(!response.header.authentic_data);
+        assert!(response.answers.is_empty());
+        assert_eq!(info_code(&response), Some(InfoCode::DnssecBogus.into()));
+
+        // Clients that check signatures themselves get the data as it is
+        let mut query = dnssec_query("www.example.com");
+        query.header.checking_disabled = true;
+
+        let response = resolve(&mut server, &query, resolver_addr);
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(response.header.checking_disabled);
+        assert!(!response.header.authentic_data);
+        assert!(response
+            .answers
+            .iter()
+            .any(|answer| answer.data == RData::A("192.0.2.99".parse().unwrap())));
+    }
+
+    #[test]
+    fn expired_signatures_are_bogus() {
+        let signed_at = SystemTime::now() - 2 * SIGNATURE_VALIDITY;
+        let (zone, anchor) = signed_zone(signed_at);
+        let (mut server, resolver_addr) = validating_server(vec![zone], anchor);
+
+        let response = resolve(&mut server, &dnssec_query("www.example.com"), resolver_addr);
+
+        assert!(matches!(response.header.code, ResponseCode::ServerFailure));
+        assert_eq!(info_code(&response), Some(InfoCode::DnssecBogus.into()));
+    }
+
+    #[test]
+    fn answers_below_insecure_delegations_are_not_bogus() {
+        let (zone, anchor) = signed_zone(SystemTime::now());
+        let (mut server, resolver_addr) = validating_server(vec![zone, insecure_zone()], anchor);
+
+        let response = resolve(
+            &mut server,
+            &dnssec_query("www.insecure.example.com"),
+            resolver_addr,
+        );
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(!response.header.authentic_data);
+        assert!(response
+            .answers
+            .iter()
+            .any(|answer| answer.data == RData::A("192.0.2.4".parse().unwrap())));
+    }
+
+    #[test]
+    fn forwarded_queries_ignore_stray_responses() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver_addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+            let (len, source) = socket.recv_from(&mut buf).unwrap();
+            let forwarded = MessageDecoder::decode(&buf[..len]).unwrap();
+            let id = forwarded.header.id;
+
+            // The client's ID is not reused, so only the resolver knows the one to answer
+            assert_ne!(id, 7);
+
+            let mut undecodable = id.to_be_bytes().to_vec();
+            undecodable.extend_from_slice(&[0x81, 0x80, 0, 1]);
+            let spoofed = address_response(&forwarded, 7, Ipv4Addr::new(192, 0, 2, 66));
+            let elsewhere = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+            socket.send_to(&undecodable, source).unwrap();
+            socket.send_to(&spoofed, source).unwrap();
+            elsewhere
+                .send_to(
+                    &address_response(&forwarded, id, Ipv4Addr::new(192, 0, 2, 67)),
+                    source,
+                )
+                .unwrap();
+            socket
+                .send_to(
+                    &address_response(&forwarded, id, Ipv4Addr::new(192, 0, 2, 1)),
+                    source,
+                )
+                .unwrap();
+        });
+
+        let mut server = DnsServer::bind(Vec::new()).unwrap();
+        let response = resolve(
+            &mut server,
+            &query("www.example.com", QuestionType::DnsType(DnsType::A)),
+            resolver_addr,
+        );
+
+        assert_eq!(response.header.id, 7);
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(
+            response.answers[0].data,
+            RData::A(Ipv4Addr::new(192, 0, 2, 1))
+        );
+    }
+
+    #[test]
+    fn undecodable_upstream_responses_are_servfail() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver_addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+            let (_, source) = socket.recv_from(&mut buf).unwrap();
+            let mut undecodable = buf[..2].to_vec();
+            undecodable.extend_from_slice(&[0x81, 0x80, 0, 1]);
+
+            socket.send_to(&undecodable, source).unwrap();
+        });
+
+        let mut server = DnsServer::bind(Vec::new()).unwrap();
+        let query = query("www.example.com", QuestionType::DnsType(DnsType::A));
+        let request = Request::received(
+            Listener::from_str("udp:127.0.0.1:53").unwrap(),
+            &MessageEncoder::encode(&query),
+            "127.0.0.1:5300".parse().unwrap(),
+        );
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+
+        let response =
+            MessageDecoder::decode(&server.respond(&request, Some(&(socket, resolver_addr)))[0])
+                .unwrap();
+
+        // A broken resolver is the server's failure, not the client's
+        assert!(matches!(response.header.code, ResponseCode::ServerFailure));
+        assert_eq!(info_code(&response), Some(InfoCode::NetworkError.into()));
+    }
+
+    // Browsers ask for HTTPS records (type 65) before connecting, which used to fail decoding
+    #[test]
+    fn https_questions_are_answered() {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     @ 300 IN HTTPS 1 . alpn=h2,h3 ipv4hint=192.0.2.2\n\
+                     www 300 IN A 192.0.2.2\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+        let zone = AuthoritativeZone::transferred("example.com", zone.records).unwrap();
+        let mut server = DnsServer::bind(Vec::new()).unwrap().with_zones(vec![zone]);
+
+        let response = ask(
+            &mut server,
+            &query("example.com", QuestionType::DnsType(DnsType::HTTPS)),
+        );
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].kind, DnsType::HTTPS);
+        assert!(matches!(
+            &response.answers[0].data,
+            RData::Svcb(svcb) if svcb.priority == 1 && svcb.params.len() == 2
+        ));
+
+        let response = ask(
+            &mut server,
+            &query("www.example.com", QuestionType::DnsType(DnsType::HTTPS)),
+        );
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn forwarded_srv_answers_get_the_target_addresses() {
+        let (resolver_addr, questions) =
+            stub_resolver(|question| match (question.name.as_str(), question.kind) {
+                ("_sip._tcp.example.com", QuestionType::DnsType(DnsType::SRV)) => {
+                    Some((records("_sip._tcp 300 IN SRV 10 60 5060 sip\n"), Vec::new()))
+                }
+                ("sip.example.com", QuestionType::DnsType(DnsType::A)) => {
+                    Some((records("sip 300 IN A 192.0.2.5\n"), Vec::new()))
+                }
+                ("sip.example.com", _) => Some((Vec::new(), Vec::new())),
+                _ => None,
+            });
+        let mut server = DnsServer::bind(Vec::new()).unwrap();
+
+        let response = resolve(
+            &mut server,
+            &query("_sip._tcp.example.com", QuestionType::DnsType(DnsType::SRV)),
+            resolver_addr,
+        );
+
+        assert!(matches!(
+            &response.answers[0].data,
+            RData::Srv(srv) if srv.port == 5060 && srv.target == "sip.example.com"
+        ));
+        // The resolver sent no additional records, so the addresses were asked for
+        assert_eq!(questions.lock().unwrap().len(), 3);
+        assert_eq!(
+            response
+                .additionals
+                .iter()
+                .map(|record| &record.data)
+                .collect::<Vec<_>>(),
+            [&RData::A(Ipv4Addr::new(192, 0, 2, 5))]
+        );
+    }
+
+    #[test]
+    fn mx_additionals_stop_at_the_first_failed_lookup() {
+        let (resolver_addr, questions) = stub_resolver(|question| {
+            match (question.name.as_str(), question.kind) {
+                ("example.com", _) => Some((
+                    records("@ 300 IN MX 10 mail1\n@ 300 IN MX 20 mail2\n"),
+                    Vec::new(),
+                )),
+                ("mail1.example.com", QuestionType::DnsType(DnsType::A)) => {
+                    Some((records("mail1 300 IN A 192.0.2.25\n"), Vec::new()))
+                }
+                // The AAAA lookup times out
+                _ => None,
+            }
+        });
+        let mut server = DnsServer::bind(Vec::new()).unwrap();
+
+        let response = resolve(
+            &mut server,
+            &query("example.com", QuestionType::DnsType(DnsType::MX)),
+            resolver_addr,
+        );
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(matches!(
+            &response.answers[0].data,
+            RData::Mx(mx) if mx.preference == 10 && mx.exchange == "mail1.example.com"
+        ));
+        assert_eq!(response.answers.len(), 2);
+        // The address found before the failure is kept, mail2 is not looked up at all
+        assert_eq!(
+            response
+                .additionals
+                .iter()
+                .map(|record| &record.data)
+                .collect::<Vec<_>>(),
+            [&RData::A(Ipv4Addr::new(192, 0, 2, 25))]
+        );
+        assert!(!questions
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|question| question.name == "mail2.example.com"));
+    }
+
+    #[test]
+    fn forwarded_dname_redirections_are_followed() {
+        let (resolver_addr, _) = stub_resolver(|question| match question.name.as_str() {
+            // A server authoritative for the DNAME but not for its target, which sends no
+            // synthesized CNAME
+            "www.old.example.com" => Some((records("old 300 IN DNAME new\n"), Vec::new())),
+            "www.new.example.com" => Some((records("www.new 300 IN A 192.0.2.3\n"), Vec::new())),
+            _ => None,
+        });
+        let mut server = DnsServer::bind(Vec::new()).unwrap();
+
+        let response = resolve(
+            &mut server,
+            &query("www.old.example.com", QuestionType::DnsType(DnsType::A)),
+            resolver_addr,
+        );
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert_eq!(
+            response
+                .answers
+                .iter()
+                .map(|record| (record.name.as_str(), record.kind, &record.data))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "old.example.com",
+                    DnsType::DNAME,
+                    &RData::DomainName("new.example.com".to_string())
+                ),
+                (
+                    "www.old.example.com",
+                    DnsType::CNAME,
+                    &RData::DomainName("www.new.example.com".to_string())
+                ),
+                (
+                    "www.new.example.com",
+                    DnsType::A,
+                    &RData::A(Ipv4Addr::new(192, 0, 2, 3))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn any_questions_follow_the_policy() {
+        let kinds = |response: &Message| {
+            response
+                .answers
+                .iter()
+                .map(|record| record.kind)
+                .collect::<Vec<_>>()
+        };
+
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_zones(vec![zone()]);
+        let response = ask(&mut server, &query("example.com", QuestionType::ALL));
+
+        assert_eq!(kinds(&response), [DnsType::NS, DnsType::SOA]);
+
+        let response = ask(&mut server, &query("nope.example.com", QuestionType::ALL));
+
+        assert!(matches!(response.header.code, ResponseCode::NameError));
+
+        // RFC 8482 section 4.2, also for names outside the local zones, which are not forwarded
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_zones(vec![zone()])
+            .with_any_policy(AnyPolicy::Hinfo);
+
+        for (name, authoritative) in [("example.com", true), ("example.org", false)] {
+            let response = ask(&mut server, &query(name, QuestionType::ALL));
+
+            assert!(matches!(
+                response.header.code,
+                ResponseCode::NoErrorCondition
+            ));
+            assert_eq!(response.header.auth_answer, authoritative, "{}", name);
+            assert_eq!(kinds(&response), [DnsType::HINFO]);
+            assert_eq!(
+                response.answers[0].data,
+                RData::Strings(vec![b"RFC8482".to_vec(), Vec::new()])
+            );
+        }
+    }
+
+    #[test]
+    fn server_identity_is_answered_in_class_ch() {
+        let chaos = |name: &str, class: DnsClass| {
+            let mut query = query(name, QuestionType::DnsType(DnsType::TXT));
+            query.questions[0].class = QuestionClass::DnsClass(class);
+            query
+        };
+
+        let mut server = DnsServer::bind(Vec::new()).unwrap();
+        let response = ask(&mut server, &chaos("VERSION.BIND", DnsClass::CH));
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(response.header.auth_answer);
+        assert!(matches!(response.answers[0].class, DnsClass::CH));
+        assert_eq!(
+            response.answers[0].data,
+            RData::Strings(vec![format!(
+                "{} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )
+            .into_bytes()])
+        );
+
+        // The hostname is only given when configured, other names in CH are not the server's
+        for (name, code) in [
+            ("hostname.bind", InfoCode::Prohibited),
+            ("example.com", InfoCode::NotAuthoritative),
+        ] {
+            let response = ask(&mut server, &chaos(name, DnsClass::CH));
+
+            assert!(
+                matches!(response.header.code, ResponseCode::Refused),
+                "{}",
+                name
+            );
+            assert!(response.answers.is_empty());
+            assert_eq!(info_code(&response), Some(code.into()), "{}", name);
+        }
+
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_identity(ServerIdentity {
+                version: None,
+                hostname: Some("ns1.example.com".to_string()),
+            });
+        let response = ask(&mut server, &chaos("id.server", DnsClass::CH));
+
+        assert_eq!(
+            response.answers[0].data,
+            RData::Strings(vec![b"ns1.example.com".to_vec()])
+        );
+
+        let response = ask(&mut server, &chaos("version.server", DnsClass::CH));
+
+        assert!(matches!(response.header.code, ResponseCode::Refused));
+
+        // The server has no data at all in the other classes
+        let response = ask(&mut server, &chaos("example.com", DnsClass::HS));
+
+        assert!(matches!(response.header.code, ResponseCode::NotImplemented));
+    }
+
+    #[test]
+    fn failures_outweigh_name_errors_which_outweigh_answers() {
+        let combined = |codes: &[ResponseCode]| {
+            codes
+                .iter()
+                .copied()
+                .fold(ResponseCode::NoErrorCondition, combine_codes)
+        };
+
+        assert!(matches!(
+            combined(&[ResponseCode::NoErrorCondition, ResponseCode::NameError]),
+            ResponseCode::NameError
+        ));
+        assert!(matches!(
+            combined(&[
+                ResponseCode::NameError,
+                ResponseCode::ServerFailure,
+                ResponseCode::NoErrorCondition
+            ]),
+            ResponseCode::ServerFailure
+        ));
+        // Between two failures the first one stays
+        assert!(matches!(
+            combined(&[ResponseCode::Refused, ResponseCode::ServerFailure]),
+            ResponseCode::Refused
+        ));
+    }
+
+    #[test]
+    fn multi_question_queries_follow_the_policy() {
+        let mut query = query("www.example.com", QuestionType::DnsType(DnsType::A));
+        query.questions.push(Question {
+            name: "nope.example.com".to_string(),
+            ..query.questions[0].clone()
+        });
+        query.header.question_count = 2;
+
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_zones(vec![zone()]);
+        let response = ask(&mut server, &query);
+
+        // Every question is answered, under the worst RCODE
+        assert!(matches!(response.header.code, ResponseCode::NameError));
+        assert_eq!(response.questions.len(), 2);
+        assert_eq!(response.answers.len(), 1);
+
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_zones(vec![zone()])
+            .with_question_policy(QuestionPolicy::First);
+        let response = ask(&mut server, &query);
+
+        assert!(matches!(
+            response.header.code,
+            ResponseCode::NoErrorCondition
+        ));
+        assert_eq!(response.questions.len(), 1);
+        assert_eq!(response.answers.len(), 1);
+
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_zones(vec![zone()])
+            .with_question_policy(QuestionPolicy::FormatError);
+        let response = ask(&mut server, &query);
+
+        assert!(matches!(response.header.code, ResponseCode::FormatError));
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn refusals_carry_the_reason() {
+        let refusal = |server: &mut DnsServer, query: &Message, listener: &str| {
+            let request = Request::received(
+                Listener::from_str(listener).unwrap(),
+                &MessageEncoder::encode(query),
+                "127.0.0.1:5300".parse().unwrap(),
+            );
+            let response = MessageDecoder::decode(&server.respond(&request, None)[0]).unwrap();
+
+            assert!(matches!(response.header.code, ResponseCode::Refused));
+            info_code(&response)
+        };
+        let a = query("www.example.com", QuestionType::DnsType(DnsType::A));
+        let axfr = query("example.com", QuestionType::AXFR);
+        let mut update = query("example.com", QuestionType::DnsType(DnsType::SOA));
+        update.header.operation_code = OperationCode::Update;
+
+        let mut server = DnsServer::bind(Vec::new())
+            .unwrap()
+            .with_zones(vec![zone()]);
+
+        assert_eq!(
+            refusal(&mut server, &a, "udp:127.0.0.1:53,allow=192.0.2.0/24"),
+            Some(InfoCode::Prohibited.into())
+        );
+        assert_eq!(
+            refusal(&mut server, &update, "udp:127.0.0.1:53"),
+            Some(InfoCode::Prohibited.into())
+        );
+        assert_eq!(
+            refusal(&mut server, &axfr, "udp:127.0.0.1:53"),
+            Some(InfoCode::NotSupported.into())
+        );
+
+        // With TSIG keys, transfers must be signed
+        let key =
+            TsigKey::from_str("transfer:a2tra2tra2tra2tra2tra2tra2tra2tra2tra2tra2s=").unwrap();
+        let mut server = server.with_tsig_keys(TsigKeyring::new(vec![key]));
+
+        assert_eq!(
+            refusal(&mut server, &axfr, "tcp:127.0.0.1:53"),
+            Some(InfoCode::Prohibited.into())
+        );
+
+        // Clients without EDNS get the RCODE alone
+        let mut plain = query("www.example.com", QuestionType::DnsType(DnsType::A));
+        plain.edns = None;
+        plain.header.additional_record_count = 0;
+
+        assert_eq!(
+            refusal(&mut server, &plain, "udp:127.0.0.1:53,allow=192.0.2.0/24"),
+            None
+        );
     }
 }
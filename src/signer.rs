@@ -0,0 +1,746 @@
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    fs,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    rand::SystemRandom,
+    signature::{self, EcdsaKeyPair, Ed25519KeyPair},
+};
+
+use crate::{
+    error::ServerError,
+    message::{
+        answer::Answer,
+        dnssec::{
+            base32hex, is_subdomain, rrsets, Dnskey, Nsec, Nsec3, Nsec3Param, Rrsig, TypeBitmap,
+            ALGORITHM_ECDSAP256SHA256, ALGORITHM_ED25519,
+        },
+        rdata::RData,
+        types::{DnsClass, DnsType, DomainName},
+    },
+    zone::{Zone, ZoneFileParser},
+};
+
+/// Signatures are valid from an hour in the past, so validators with a slow clock accept them.
+const INCEPTION_OFFSET: Duration = Duration::from_secs(3600);
+
+/// Private half of a zone key.
+enum KeyPair {
+    Ecdsa(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+}
+
+/// A key pair that signs the RRsets of a zone, either ECDSA P-256 or Ed25519.
+pub struct SigningKey {
+    /// Zone the key belongs to.
+    pub zone: String,
+    pub dnskey: Dnskey,
+    key_pair: KeyPair,
+}
+
+impl SigningKey {
+    /// Loads a key pair in the format written by BIND's `dnssec-keygen`: `<path>.key` holds the
+    /// DNSKEY record and `<path>.private` the private key.
+    pub fn load(path: &str) -> Result<Self, ServerError> {
+        let invalid =
+            |message: String| ServerError::InvalidSigningKey(format!("{}: {}", path, message));
+
+        let public =
+            fs::read_to_string(format!("{}.key", path)).map_err(|err| invalid(err.to_string()))?;
+        let private = fs::read_to_string(format!("{}.private", path))
+            .map_err(|err| invalid(err.to_string()))?;
+
+        let record = ZoneFileParser::new(&public, "")
+            .parse()?
+            .records
+            .into_iter()
+            .find(|record| record.kind == DnsType::DNSKEY)
+            .ok_or_else(|| invalid("no DNSKEY record in the public key file".to_string()))?;
+
+        let RData::Dnskey(dnskey) = record.data else {
+            return Err(invalid("DNSKEY record is not valid".to_string()));
+        };
+
+        let private_key = private
+            .lines()
+            .find_map(|line| line.strip_prefix("PrivateKey:"))
+            .ok_or_else(|| invalid("no PrivateKey field in the private key file".to_string()))?;
+        let private_key = STANDARD
+            .decode(private_key.trim())
+            .map_err(|err| invalid(err.to_string()))?;
+
+        let key_pair = match dnskey.algorithm {
+            ALGORITHM_ECDSAP256SHA256 => EcdsaKeyPair::from_private_key_and_public_key(
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                &private_key,
+                &dnskey.sec1_public_key(),
+                &SystemRandom::new(),
+            )
+            .map(KeyPair::Ecdsa),
+            ALGORITHM_ED25519 => {
+                Ed25519KeyPair::from_seed_and_public_key(&private_key, &dnskey.public_key)
+                    .map(KeyPair::Ed25519)
+            }
+            algorithm => {
+                return Err(invalid(format!(
+                    "algorithm {} is not supported for signing",
+                    algorithm
+                )))
+            }
+        }
+        .map_err(|err| invalid(err.to_string()))?;
+
+        Ok(Self {
+            zone: record.name.to_lowercase(),
+            dnskey,
+            key_pair,
+        })
+    }
+
+    /// Loads the keys of `zone` from a directory of BIND style key files, the ones named
+    /// `K<zone>.+<algorithm>+<key tag>`.
+    pub fn load_directory(directory: &str, zone: &str) -> Result<Vec<Self>, ServerError> {
+        let prefix = format!("k{}.+", zone.trim_end_matches('.').to_lowercase());
+
+        let entries = fs::read_dir(directory)
+            .map_err(|err| ServerError::InvalidSigningKey(format!("{}: {}", directory, err)))?;
+
+        let mut paths = Vec::new();
+
+        for entry in entries {
+            let path = entry
+                .map_err(|err| ServerError::InvalidSigningKey(format!("{}: {}", directory, err)))?
+                .path();
+
+            let is_zone_key = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.to_lowercase().starts_with(&prefix));
+
+            if is_zone_key && path.extension().is_some_and(|extension| extension == "key") {
+                paths.push(path.with_extension("").to_string_lossy().into_owned());
+            }
+        }
+
+        // Key files are read in a stable order, so signatures come out in the same order
+        paths.sort();
+
+        paths.iter().map(|path| Self::load(path)).collect()
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, ServerError> {
+        match &self.key_pair {
+            KeyPair::Ecdsa(key_pair) => key_pair
+                .sign(&SystemRandom::new(), data)
+                .map(|signature| signature.as_ref().to_vec())
+                .map_err(|err| ServerError::SignZone(err.to_string())),
+            KeyPair::Ed25519(key_pair) => Ok(key_pair.sign(data).as_ref().to_vec()),
+        }
+    }
+
+    // Keys with the secure entry point flag are key signing keys
+    fn is_key_signing_key(&self) -> bool {
+        self.dnskey.flags & 1 > 0
+    }
+}
+
+/// Signs a whole zone: adds the DNSKEY records, builds the NSEC or NSEC3 chain and signs every
+/// authoritative RRset. Delegation NS records and glue are left unsigned.
+///
+/// https://www.rfc-editor.org/rfc/rfc4035#section-2
+pub struct ZoneSigner {
+    keys: Vec<SigningKey>,
+    validity: Duration,
+    nsec3: Option<Nsec3Param>,
+}
+
+impl ZoneSigner {
+    /// Signatures are valid for `validity` from the time they are made.
+    pub fn new(keys: Vec<SigningKey>, validity: Duration) -> Self {
+        Self {
+            keys,
+            validity,
+            nsec3: None,
+        }
+    }
+
+    /// Denies existence with NSEC3 instead of NSEC. RFC 9276 recommends no extra iterations
+    /// and no salt.
+    pub fn with_nsec3(mut self, iterations: u16, salt: Vec<u8>) -> Self {
+        self.nsec3 = Some(Nsec3Param {
+            hash_algorithm: Nsec3::SHA1_HASH,
+            flags: 0,
+            iterations,
+            salt,
+        });
+        self
+    }
+
+    pub fn sign(&self, zone: Zone) -> Result<Zone, ServerError> {
+        self.sign_at(zone, SystemTime::now())
+    }
+
+    /// Signs the zone as if it were `now`: signatures are valid for `validity` from then.
+    pub fn sign_at(&self, zone: Zone, now: SystemTime) -> Result<Zone, ServerError> {
+        let mut zone = self.prepare(zone)?;
+        let origin = zone.origin.to_lowercase();
+        let delegations = delegations(&zone.records, &origin);
+        let validity_period = self.validity_period(now);
+
+        let mut signatures = Vec::new();
+
+        for rrset in rrsets(&zone.records) {
+            let name = rrset[0].name.to_lowercase();
+            let kind = rrset[0].kind;
+
+            let is_delegation_ns = kind == DnsType::NS && delegations.contains(&name);
+
+            if is_delegation_ns || is_occluded(&delegations, &name) {
+                continue;
+            }
+
+            for key in self.keys_for(kind) {
+                signatures.push(self.sign_with_key(&rrset, key, &origin, validity_period)?);
+            }
+        }
+
+        zone.records.extend(signatures);
+
+        // The SOA record comes first and signatures follow the RRset they cover
+        let sort_key = |record: &Answer| -> (bool, u16, bool) {
+            let (kind, is_signature) = match &record.data {
+                RData::Rrsig(rrsig) => (rrsig.type_covered, true),
+                _ => (record.kind.into(), false),
+            };
+
+            (kind != DnsType::SOA.into(), kind, is_signature)
+        };
+
+        zone.records
+            .sort_by(|a, b| match DomainName::canonical_cmp(&a.name, &b.name) {
+                Ordering::Equal => sort_key(a).cmp(&sort_key(b)),
+                ordering => ordering,
+            });
+
+        Ok(zone)
+    }
+
+    /// Signatures over one RRset of the zone `origin`, made now. Online signing uses it to sign
+    /// RRsets as they are sent.
+    pub fn sign_rrset(&self, rrset: &[&Answer], origin: &str) -> Result<Vec<Answer>, ServerError> {
+        let validity_period = self.validity_period(SystemTime::now());
+
+        self.keys_for(rrset[0].kind)
+            .into_iter()
+            .map(|key| self.sign_with_key(rrset, key, origin, validity_period))
+            .collect()
+    }
+
+    /// Everything `sign` does short of signing: the records of an earlier run are replaced,
+    /// TTLs made consistent within RRsets, and the DNSKEY records and the NSEC or NSEC3 chain
+    /// added.
+    pub fn prepare(&self, zone: Zone) -> Result<Zone, ServerError> {
+        let origin = zone.origin.to_lowercase();
+
+        if self.keys.is_empty() {
+            return Err(ServerError::SignZone("no signing keys given".to_string()));
+        }
+
+        if let Some(key) = self.keys.iter().find(|key| key.zone != origin) {
+            return Err(ServerError::SignZone(format!(
+                "key {} belongs to {}, not to {}",
+                key.dnskey.key_tag(),
+                key.zone,
+                origin
+            )));
+        }
+
+        // Signatures and denial records of an earlier run are replaced, as are the DNSKEY
+        // records of the keys in use
+        let mut zone = Zone {
+            origin: zone.origin,
+            records: zone
+                .records
+                .into_iter()
+                .filter(|record| {
+                    !matches!(
+                        record.kind,
+                        DnsType::RRSIG | DnsType::NSEC | DnsType::NSEC3 | DnsType::NSEC3PARAM
+                    )
+                })
+                .filter(|record| match &record.data {
+                    RData::Dnskey(dnskey) => !self.keys.iter().any(|key| key.dnskey == *dnskey),
+                    _ => true,
+                })
+                .collect(),
+        };
+
+        if let Some(record) = zone
+            .records
+            .iter()
+            .find(|record| !is_subdomain(&record.name.to_lowercase(), &origin))
+        {
+            return Err(ServerError::SignZone(format!(
+                "{} is outside of the zone {}",
+                record.name, origin
+            )));
+        }
+
+        zone.normalize_ttls();
+
+        let mut records = zone.records;
+
+        let soa = records
+            .iter()
+            .find(|record| record.kind == DnsType::SOA && record.name.eq_ignore_ascii_case(&origin))
+            .ok_or_else(|| ServerError::SignZone(format!("{} has no SOA record", origin)))?;
+
+        // Denial records use the smaller of the SOA TTL and its minimum field (RFC 9077)
+        let negative_ttl = match &soa.data {
+            RData::Soa(data) => data.minimum.min(soa.ttl),
+            _ => soa.ttl,
+        };
+        let key_ttl = soa.ttl;
+
+        for key in &self.keys {
+            records.push(Answer {
+                name: origin.clone(),
+                kind: DnsType::DNSKEY,
+                class: DnsClass::IN,
+                ttl: key_ttl,
+                data: RData::Dnskey(key.dnskey.clone()),
+            });
+        }
+
+        if let Some(param) = &self.nsec3 {
+            // The parameters record is not cached (RFC 5155 section 4)
+            records.push(Answer {
+                name: origin.clone(),
+                kind: DnsType::NSEC3PARAM,
+                class: DnsClass::IN,
+                ttl: 0,
+                data: RData::Nsec3Param(param.clone()),
+            });
+        }
+
+        let delegations = delegations(&records, &origin);
+
+        let mut names: Vec<String> = records
+            .iter()
+            .map(|record| record.name.to_lowercase())
+            .filter(|name| !is_occluded(&delegations, name))
+            .collect();
+
+        names.sort_by(|a, b| DomainName::canonical_cmp(a, b));
+        names.dedup();
+
+        let types_at = |name: &str| -> Vec<u16> {
+            records
+                .iter()
+                .filter(|record| record.name.eq_ignore_ascii_case(name))
+                .map(|record| record.kind.into())
+                .collect()
+        };
+
+        let denial_records: Vec<Answer> = match &self.nsec3 {
+            None => names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| {
+                    let mut types = types_at(name);
+                    types.extend([u16::from(DnsType::RRSIG), u16::from(DnsType::NSEC)]);
+
+                    Answer {
+                        name: name.clone(),
+                        kind: DnsType::NSEC,
+                        class: DnsClass::IN,
+                        ttl: negative_ttl,
+                        data: RData::Nsec(Nsec {
+                            next_domain_name: names[(index + 1) % names.len()].clone(),
+                            types: TypeBitmap::new(types),
+                        }),
+                    }
+                })
+                .collect(),
+            Some(param) => {
+                let ds: u16 = DnsType::DS.into();
+
+                // Empty non-terminals get a record too, so they are not mistaken for
+                // non-existent names (RFC 5155 section 7.1)
+                let mut hashed_names: Vec<(Vec<u8>, Vec<u16>)> = Vec::new();
+                let mut seen = HashSet::new();
+
+                for name in &names {
+                    let mut ancestor = name.as_str();
+
+                    loop {
+                        if seen.insert(ancestor.to_string()) {
+                            let mut types = types_at(ancestor);
+
+                            // Insecure delegations have no signed RRset at their name
+                            let is_insecure_delegation =
+                                delegations.contains(ancestor) && !types.contains(&ds);
+
+                            if !types.is_empty() && !is_insecure_delegation {
+                                types.push(DnsType::RRSIG.into());
+                            }
+
+                            hashed_names.push((
+                                Nsec3::hash_name(ancestor, param.iterations, &param.salt),
+                                types,
+                            ));
+                        }
+
+                        if ancestor == origin {
+                            break;
+                        }
+
+                        ancestor = ancestor.split_once('.').map_or("", |(_, parent)| parent);
+                    }
+                }
+
+                hashed_names.sort();
+
+                hashed_names
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (hash, types))| Answer {
+                        name: format!("{}.{}", base32hex(hash), origin),
+                        kind: DnsType::NSEC3,
+                        class: DnsClass::IN,
+                        ttl: negative_ttl,
+                        data: RData::Nsec3(Nsec3 {
+                            hash_algorithm: param.hash_algorithm,
+                            flags: param.flags,
+                            iterations: param.iterations,
+                            salt: param.salt.clone(),
+                            next_hashed_owner_name: hashed_names[(index + 1) % hashed_names.len()]
+                                .0
+                                .clone(),
+                            types: TypeBitmap::new(types.clone()),
+                        }),
+                    })
+                    .collect()
+            }
+        };
+
+        records.extend(denial_records);
+
+        Ok(Zone {
+            origin: zone.origin,
+            records,
+        })
+    }
+
+    // Inception and expiration of signatures made at `now`
+    fn validity_period(&self, now: SystemTime) -> (u32, u32) {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        (
+            (now - INCEPTION_OFFSET).as_secs() as u32,
+            (now + self.validity).as_secs() as u32,
+        )
+    }
+
+    // Key signing keys sign the DNSKEY RRset and zone signing keys everything else. A single
+    // key with no zone signing key beside it signs everything.
+    fn keys_for(&self, kind: DnsType) -> Vec<&SigningKey> {
+        let has_zone_signing_key = self.keys.iter().any(|key| !key.is_key_signing_key());
+
+        self.keys
+            .iter()
+            .filter(|key| {
+                !has_zone_signing_key || (kind == DnsType::DNSKEY) == key.is_key_signing_key()
+            })
+            .collect()
+    }
+
+    fn sign_with_key(
+        &self,
+        rrset: &[&Answer],
+        key: &SigningKey,
+        origin: &str,
+        (inception, expiration): (u32, u32),
+    ) -> Result<Answer, ServerError> {
+        let first = rrset[0];
+        let owner = first.name.to_lowercase();
+
+        // The wildcard label is not counted, so validators can tell synthesized answers apart
+        let labels = owner
+            .split('.')
+            .filter(|label| !label.is_empty() && *label != "*")
+            .count();
+
+        let mut rrsig = Rrsig {
+            type_covered: first.kind.into(),
+            algorithm: key.dnskey.algorithm,
+            labels: labels as u8,
+            original_ttl: first.ttl,
+            expiration,
+            inception,
+            key_tag: key.dnskey.key_tag(),
+            signer_name: origin.to_string(),
+            signature: Vec::new(),
+        };
+
+        let data: Vec<&RData> = rrset.iter().map(|record| &record.data).collect();
+
+        rrsig.signature = key.sign(&rrsig.signed_data(&owner, first.class, &data))?;
+
+        Ok(Answer {
+            name: first.name.clone(),
+            kind: DnsType::RRSIG,
+            class: first.class,
+            ttl: first.ttl,
+            data: RData::Rrsig(rrsig),
+        })
+    }
+}
+
+/// Owner names of the zone's delegations, the names with NS records other than the apex.
+pub fn delegations(records: &[Answer], origin: &str) -> HashSet<String> {
+    records
+        .iter()
+        .filter(|record| record.kind == DnsType::NS)
+        .map(|record| record.name.to_lowercase())
+        .filter(|name| name != origin)
+        .collect()
+}
+
+/// Names below a delegation belong to the child zone, only their glue is kept in the parent.
+pub fn is_occluded(delegations: &HashSet<String>, name: &str) -> bool {
+    delegations
+        .iter()
+        .any(|delegation| name != delegation && is_subdomain(name, delegation))
+}
+
+#[cfg(test)]
+impl SigningKey {
+    /// A new Ed25519 key for `zone`, with the secure entry point flag when
+    /// `is_key_signing_key` is set.
+    pub fn generate(zone: &str, is_key_signing_key: bool) -> Self {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+
+        Self {
+            zone: zone.to_string(),
+            dnskey: Dnskey {
+                flags: Dnskey::ZONE_KEY_FLAG | is_key_signing_key as u16,
+                protocol: 3,
+                algorithm: ALGORITHM_ED25519,
+                public_key: signature::KeyPair::public_key(&key_pair).as_ref().to_vec(),
+            },
+            key_pair: KeyPair::Ed25519(key_pair),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::validator::verify_rrsig;
+
+    // The zone of RFC 5155 appendix A in part: w.example is an empty non-terminal and a.example
+    // an unsigned delegation with glue
+    fn zone() -> Zone {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     ns1 3600 IN A 192.0.2.1\n\
+                     a 3600 IN NS ns1.a\n\
+                     ns1.a 3600 IN A 192.0.2.5\n\
+                     x.w 300 IN A 192.0.2.2\n\
+                     xx 300 IN A 192.0.2.3\n";
+
+        ZoneFileParser::new(input, "example").parse().unwrap()
+    }
+
+    fn keys() -> Vec<SigningKey> {
+        vec![
+            SigningKey::generate("example", true),
+            SigningKey::generate("example", false),
+        ]
+    }
+
+    fn types(records: &[Answer], name: &str) -> Vec<u16> {
+        records
+            .iter()
+            .find_map(|record| match &record.data {
+                RData::Nsec(nsec) if record.name == name => Some(nsec.types.clone()),
+                RData::Nsec3(nsec3) if record.name == name => Some(nsec3.types.clone()),
+                _ => None,
+            })
+            .map(|types| {
+                (0..=u16::from(DnsType::NSEC3PARAM))
+                    .filter(|kind| types.contains(*kind))
+                    .collect()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn signatures_verify_with_the_zone_keys() {
+        let signer = ZoneSigner::new(keys(), Duration::from_secs(3600));
+        let zone = signer.sign(zone()).unwrap();
+
+        for rrset in rrsets(&zone.records) {
+            let name = rrset[0].name.as_str();
+            let signatures: Vec<&Rrsig> = zone
+                .records
+                .iter()
+                .filter(|record| record.name == name)
+                .filter_map(|record| match &record.data {
+                    RData::Rrsig(rrsig) if rrsig.type_covered == rrset[0].kind.into() => {
+                        Some(rrsig)
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            // Delegation NS records and glue belong to the child zone
+            if name == "a.example" && rrset[0].kind == DnsType::NS || name == "ns1.a.example" {
+                assert!(signatures.is_empty(), "{} {}", name, rrset[0].kind);
+                continue;
+            }
+
+            // The key signing key signs the DNSKEY RRset, the zone signing key the others
+            let [rrsig] = &signatures[..] else {
+                panic!(
+                    "{} {} has {} signatures",
+                    name,
+                    rrset[0].kind,
+                    signatures.len()
+                );
+            };
+            let key = signer
+                .keys
+                .iter()
+                .find(|key| key.dnskey.key_tag() == rrsig.key_tag)
+                .unwrap();
+
+            assert_eq!(
+                key.is_key_signing_key(),
+                rrset[0].kind == DnsType::DNSKEY,
+                "{} {}",
+                name,
+                rrset[0].kind
+            );
+            assert!(verify_rrsig(&rrset, rrsig, &key.dnskey), "{}", name);
+        }
+    }
+
+    #[test]
+    fn nsec_chain_covers_every_name() {
+        let zone = ZoneSigner::new(keys(), Duration::from_secs(3600))
+            .sign(zone())
+            .unwrap();
+
+        let next: HashMap<&str, &str> = zone
+            .records
+            .iter()
+            .filter_map(|record| match &record.data {
+                RData::Nsec(nsec) => Some((record.name.as_str(), nsec.next_domain_name.as_str())),
+                _ => None,
+            })
+            .collect();
+
+        // Every authoritative name in canonical order, back to the apex
+        let mut chain = vec!["example"];
+
+        while let Some(name) = next
+            .get(chain[chain.len() - 1])
+            .filter(|name| **name != "example")
+        {
+            chain.push(name);
+        }
+
+        assert_eq!(
+            chain,
+            [
+                "example",
+                "a.example",
+                "ns1.example",
+                "x.w.example",
+                "xx.example"
+            ]
+        );
+        assert_eq!(next.len(), chain.len());
+
+        let [ns, soa, a] = [DnsType::NS, DnsType::SOA, DnsType::A].map(u16::from);
+        let [rrsig, nsec, dnskey] = [DnsType::RRSIG, DnsType::NSEC, DnsType::DNSKEY].map(u16::from);
+
+        let mut apex_types = vec![ns, soa, rrsig, nsec, dnskey];
+        apex_types.sort();
+
+        assert_eq!(types(&zone.records, "example"), apex_types);
+        assert_eq!(types(&zone.records, "a.example"), [ns, rrsig, nsec]);
+        assert_eq!(types(&zone.records, "xx.example"), [a, rrsig, nsec]);
+    }
+
+    #[test]
+    fn nsec3_chain_covers_every_name() {
+        let zone = ZoneSigner::new(keys(), Duration::from_secs(3600))
+            .with_nsec3(12, vec![0xaa, 0xbb, 0xcc, 0xdd])
+            .sign(zone())
+            .unwrap();
+
+        let nsec3: Vec<(&str, &Nsec3)> = zone
+            .records
+            .iter()
+            .filter_map(|record| match &record.data {
+                RData::Nsec3(nsec3) => Some((record.name.as_str(), nsec3)),
+                _ => None,
+            })
+            .collect();
+
+        // Hashes from RFC 5155 appendix A, the empty non-terminal w.example included and the
+        // glue left out
+        let mut hashes = vec![
+            "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom", // example
+            "2t7b4g4vsa5smi47k61mv5bv1a22bojr", // ns1.example
+            "35mthgpgcu1qg68fab165klnsnk3dpvl", // a.example
+            "b4um86eghhds6nea196smvmlo4ors995", // x.w.example
+            "k8udemvp1j2f7eg6jebps17vp3n8i58h", // w.example
+            "t644ebqk9bibcna874givr6joj62mlhv", // xx.example
+        ];
+        hashes.sort();
+
+        let owners: Vec<&str> = nsec3
+            .iter()
+            .map(|(name, _)| name.trim_end_matches(".example"))
+            .collect();
+        let mut sorted_owners = owners.clone();
+        sorted_owners.sort();
+
+        assert_eq!(sorted_owners, hashes);
+
+        // Each record points at the next hash, the last one back at the first
+        for (name, record) in &nsec3 {
+            let owner = name.trim_end_matches(".example");
+            let position = hashes.iter().position(|hash| *hash == owner).unwrap();
+
+            assert_eq!(
+                base32hex(&record.next_hashed_owner_name),
+                hashes[(position + 1) % hashes.len()]
+            );
+        }
+
+        let [ns, a, rrsig] = [DnsType::NS, DnsType::A, DnsType::RRSIG].map(u16::from);
+
+        // The unsigned delegation has no RRSIG and the empty non-terminal no types at all
+        assert_eq!(
+            types(&zone.records, "35mthgpgcu1qg68fab165klnsnk3dpvl.example"),
+            [ns]
+        );
+        assert!(types(&zone.records, "k8udemvp1j2f7eg6jebps17vp3n8i58h.example").is_empty());
+        assert_eq!(
+            types(&zone.records, "t644ebqk9bibcna874givr6joj62mlhv.example"),
+            [a, rrsig]
+        );
+    }
+}
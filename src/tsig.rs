@@ -0,0 +1,737 @@
+use std::{
+    fs,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{BufMut, Bytes, BytesMut};
+use ring::hmac;
+
+use crate::{
+    error::ServerError,
+    message::{
+        header::Header,
+        message::{Message, MessageEncoder},
+        tsig::{Tsig, TsigEncoder, TsigErrorCode},
+    },
+};
+
+/// Seconds of clock skew allowed between the signer and the verifier.
+const TSIG_FUDGE: u16 = 300;
+
+/// MAC algorithms supported for transaction signatures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            TsigAlgorithm::HmacSha256 => "hmac-sha256",
+            TsigAlgorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn hmac_algorithm(&self) -> hmac::Algorithm {
+        match self {
+            TsigAlgorithm::HmacSha256 => hmac::HMAC_SHA256,
+            TsigAlgorithm::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+impl FromStr for TsigAlgorithm {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim_end_matches('.').to_lowercase().as_str() {
+            "hmac-sha256" => Ok(TsigAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(TsigAlgorithm::HmacSha512),
+            name => Err(ServerError::InvalidTsigKey(format!(
+                "{} is not a supported TSIG algorithm",
+                name
+            ))),
+        }
+    }
+}
+
+/// A shared secret used to sign and verify messages.
+#[derive(Clone)]
+pub struct TsigKey {
+    pub name: String,
+    pub algorithm: TsigAlgorithm,
+    key: hmac::Key,
+}
+
+impl TsigKey {
+    pub fn new(name: &str, algorithm: TsigAlgorithm, secret: &[u8]) -> Self {
+        Self {
+            name: name.trim_end_matches('.').to_lowercase(),
+            algorithm,
+            key: hmac::Key::new(algorithm.hmac_algorithm(), secret),
+        }
+    }
+
+    fn matches(&self, tsig: &Tsig) -> bool {
+        self.name
+            .eq_ignore_ascii_case(tsig.key_name.trim_end_matches('.'))
+            && TsigAlgorithm::from_str(&tsig.algorithm).is_ok_and(|alg| alg == self.algorithm)
+    }
+
+    // The TSIG record of a request this server sends, e.g. a zone transfer query to a primary.
+    // `request` is the encoded request without the TSIG record (RFC 8945 section 5.1).
+    fn sign_request(&self, request: &[u8]) -> Tsig {
+        let mut tsig = Tsig {
+            key_name: self.name.clone(),
+            algorithm: self.algorithm.name().to_string(),
+            time_signed: now(),
+            fudge: TSIG_FUDGE,
+            mac: Vec::new(),
+            original_id: u16::from_be_bytes([request[0], request[1]]),
+            error: TsigErrorCode::NoError,
+            other_data: Vec::new(),
+            offset: 0,
+        };
+
+        let mut signed = BytesMut::from(request);
+        signed.put(TsigEncoder::encode_variables(&tsig));
+
+        tsig.mac = hmac::sign(&self.key, &signed).as_ref().to_vec();
+
+        tsig
+    }
+
+    /// Encodes `request` with a TSIG record signed with this key, and returns the MAC its
+    /// response follows on from.
+    pub fn encode_signed(&self, request: &Message) -> (Bytes, Vec<u8>) {
+        let tsig = self.sign_request(&MessageEncoder::encode(request));
+        let mac = tsig.mac.clone();
+
+        let signed_request = Message {
+            header: Header {
+                additional_record_count: request.header.additional_record_count + 1,
+                ..request.header
+            },
+            questions: request.questions.clone(),
+            answers: request.answers.clone(),
+            authorities: request.authorities.clone(),
+            additionals: request.additionals.clone(),
+            edns: request.edns.clone(),
+            tsig: Some(tsig),
+        };
+
+        (MessageEncoder::encode(&signed_request), mac)
+    }
+
+    /// Verifies the TSIG record of a response to a request signed with this key. `request_mac`
+    /// is the MAC of the request, and `message` the raw response as received.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc8945#section-5.3
+    pub fn verify_response(
+        &self,
+        request_mac: &[u8],
+        message: &[u8],
+        tsig: &Tsig,
+    ) -> TsigErrorCode {
+        self.verify_chained(
+            request_mac,
+            message,
+            tsig,
+            TsigEncoder::encode_variables(tsig),
+        )
+    }
+
+    /// Verifies the TSIG record of a later message of a multi-message response, whose MAC
+    /// covers the MAC of the message before it and only the timers. Every message is expected
+    /// to be signed, as this server signs them.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc8945#section-5.3.1
+    pub fn verify_continuation(
+        &self,
+        previous_mac: &[u8],
+        message: &[u8],
+        tsig: &Tsig,
+    ) -> TsigErrorCode {
+        self.verify_chained(
+            previous_mac,
+            message,
+            tsig,
+            TsigEncoder::encode_timers(tsig),
+        )
+    }
+
+    // Responses are only accepted without errors, signed with this key over the MAC they
+    // follow on from
+    fn verify_chained(
+        &self,
+        previous_mac: &[u8],
+        message: &[u8],
+        tsig: &Tsig,
+        variables: Bytes,
+    ) -> TsigErrorCode {
+        if !self.matches(tsig) {
+            return TsigErrorCode::BadKey;
+        }
+
+        if tsig.error != TsigErrorCode::NoError {
+            return tsig.error;
+        }
+
+        let mut signed = BytesMut::new();
+        signed.put_u16(previous_mac.len() as u16);
+        signed.put(previous_mac);
+        signed.put(unsigned_message(message, tsig));
+        signed.put(variables);
+
+        if hmac::verify(&self.key, &signed, &tsig.mac).is_err() {
+            return TsigErrorCode::BadSignature;
+        }
+
+        if now().abs_diff(tsig.time_signed) > tsig.fudge as u64 {
+            return TsigErrorCode::BadTime;
+        }
+
+        TsigErrorCode::NoError
+    }
+}
+
+/// Parses keys in the same `[algorithm:]name:secret` form `dig -y` uses, with the secret encoded
+/// in base64. The algorithm defaults to `hmac-sha256`.
+impl FromStr for TsigKey {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.split(':').collect();
+
+        let (algorithm, name, secret) = match parts[..] {
+            [name, secret] => (TsigAlgorithm::HmacSha256, name, secret),
+            [algorithm, name, secret] => (TsigAlgorithm::from_str(algorithm)?, name, secret),
+            _ => {
+                return Err(ServerError::InvalidTsigKey(format!(
+                    "{} is not in the [algorithm:]name:secret form",
+                    value
+                )))
+            }
+        };
+
+        let secret = STANDARD
+            .decode(secret)
+            .map_err(|err| ServerError::InvalidTsigKey(err.to_string()))?;
+
+        Ok(TsigKey::new(name, algorithm, &secret))
+    }
+}
+
+impl TsigKey {
+    /// Reads the keys of a BIND key file, in the form `tsig-keygen` writes them:
+    /// `key "<name>" { algorithm <algorithm>; secret "<base64>"; };`. Comments start with `#`
+    /// or `//`.
+    pub fn load_file(path: &str) -> Result<Vec<Self>, ServerError> {
+        let input = fs::read_to_string(path)
+            .map_err(|err| ServerError::InvalidTsigKey(format!("{}: {}", path, err)))?;
+
+        Self::parse_key_file(&input)
+            .map_err(|message| ServerError::InvalidTsigKey(format!("{}: {}", path, message)))
+    }
+
+    fn parse_key_file(input: &str) -> Result<Vec<Self>, String> {
+        let tokens = key_file_tokens(input);
+        let mut tokens = tokens.iter().map(String::as_str);
+        let mut expect = |expected: &str| match tokens.next() {
+            Some(token) if expected.is_empty() || token == expected => Ok(token),
+            Some(token) => Err(format!("expected {} but found {}", expected, token)),
+            None => Err(format!("expected {} but the file ends", expected)),
+        };
+        let mut keys = Vec::new();
+
+        while let Ok(statement) = expect("") {
+            if statement != "key" {
+                return Err(format!("{} statements are not supported", statement));
+            }
+
+            let name = expect("")?;
+            let (mut algorithm, mut secret) = (None, None);
+
+            expect("{")?;
+
+            loop {
+                match expect("")? {
+                    "}" => break,
+                    "algorithm" => algorithm = Some(TsigAlgorithm::from_str(expect("")?)),
+                    "secret" => secret = Some(expect("")?),
+                    setting => return Err(format!("{} is not a key setting", setting)),
+                }
+
+                expect(";")?;
+            }
+
+            expect(";")?;
+
+            let algorithm = algorithm
+                .ok_or_else(|| format!("key {} has no algorithm", name))?
+                .map_err(|err| err.to_string())?;
+            let secret = secret.ok_or_else(|| format!("key {} has no secret", name))?;
+            let secret = STANDARD
+                .decode(secret)
+                .map_err(|err| format!("key {}: {}", name, err))?;
+
+            keys.push(TsigKey::new(name, algorithm, &secret));
+        }
+
+        Ok(keys)
+    }
+}
+
+// The words, quoted strings and punctuation of a key file, without comments and quotes
+fn key_file_tokens(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|c| *c != '\n').is_some() {},
+            '"' => tokens.push(chars.by_ref().take_while(|c| *c != '"').collect()),
+            '{' | '}' | ';' => tokens.push(c.to_string()),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{};\"".contains(*c)) {
+                    word.push(c);
+                }
+
+                tokens.push(word);
+            }
+        }
+    }
+
+    tokens
+}
+
+/// The set of keys the server accepts signed messages from.
+#[derive(Default)]
+pub struct TsigKeyring {
+    keys: Vec<TsigKey>,
+}
+
+impl TsigKeyring {
+    pub fn new(keys: Vec<TsigKey>) -> Self {
+        Self { keys }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Verifies the TSIG record of a received message. `message` is the raw message as received,
+    /// the MAC covers everything before the TSIG record with the ID restored to the original one
+    /// and the TSIG record removed from the additional count.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc8945#section-5.2
+    pub fn verify(&self, message: &[u8], tsig: &Tsig) -> TsigErrorCode {
+        let Some(key) = self.keys.iter().find(|key| key.matches(tsig)) else {
+            return TsigErrorCode::BadKey;
+        };
+
+        // Only untruncated MACs are accepted
+        if tsig.mac.len()
+            != key
+                .algorithm
+                .hmac_algorithm()
+                .digest_algorithm()
+                .output_len()
+        {
+            return TsigErrorCode::BadTruncation;
+        }
+
+        let mut signed = unsigned_message(message, tsig);
+        signed.put(TsigEncoder::encode_variables(tsig));
+
+        if hmac::verify(&key.key, &signed, &tsig.mac).is_err() {
+            return TsigErrorCode::BadSignature;
+        }
+
+        if now().abs_diff(tsig.time_signed) > tsig.fudge as u64 {
+            return TsigErrorCode::BadTime;
+        }
+
+        TsigErrorCode::NoError
+    }
+
+    /// Builds the TSIG record for the response to a signed request. `response` is the encoded
+    /// response without the TSIG record. Requests that failed verification because of the key or
+    /// the signature get an unsigned record carrying the error, as the client cannot verify it.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc8945#section-5.3
+    pub fn sign_response(&self, request: &Tsig, error: TsigErrorCode, response: &[u8]) -> Tsig {
+        let mut tsig = Tsig {
+            key_name: request.key_name.clone(),
+            algorithm: request.algorithm.clone(),
+            time_signed: now(),
+            fudge: TSIG_FUDGE,
+            mac: Vec::new(),
+            original_id: request.original_id,
+            error,
+            other_data: Vec::new(),
+            offset: 0,
+        };
+
+        if matches!(
+            error,
+            TsigErrorCode::BadKey | TsigErrorCode::BadSignature | TsigErrorCode::BadTruncation
+        ) {
+            return tsig;
+        }
+
+        let Some(key) = self.keys.iter().find(|key| key.matches(request)) else {
+            return tsig;
+        };
+
+        // The client's clock is off, so the server sends its own time for it to compare against
+        if error == TsigErrorCode::BadTime {
+            tsig.time_signed = request.time_signed;
+            tsig.other_data = now().to_be_bytes()[2..].to_vec();
+        }
+
+        let mut signed = BytesMut::new();
+        signed.put_u16(request.mac.len() as u16);
+        signed.put(&request.mac[..]);
+        signed.put(response);
+        signed.put(TsigEncoder::encode_variables(&tsig));
+
+        tsig.mac = hmac::sign(&key.key, &signed).as_ref().to_vec();
+
+        tsig
+    }
+
+    /// Builds the TSIG record for a later message of a multi-message response, e.g. a zone
+    /// transfer. Its MAC covers the MAC of the message before it instead of the request's, and
+    /// only the timers of the TSIG variables.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc8945#section-5.3.1
+    pub fn sign_continuation(&self, previous: &Tsig, response: &[u8]) -> Tsig {
+        let mut tsig = Tsig {
+            time_signed: now(),
+            mac: Vec::new(),
+            error: TsigErrorCode::NoError,
+            other_data: Vec::new(),
+            ..previous.clone()
+        };
+
+        let Some(key) = self.keys.iter().find(|key| key.matches(previous)) else {
+            return tsig;
+        };
+
+        let mut signed = BytesMut::new();
+        signed.put_u16(previous.mac.len() as u16);
+        signed.put(&previous.mac[..]);
+        signed.put(response);
+        signed.put(TsigEncoder::encode_timers(&tsig));
+
+        tsig.mac = hmac::sign(&key.key, &signed).as_ref().to_vec();
+
+        tsig
+    }
+}
+
+// The part of a received message its MAC covers: everything before the TSIG record, with the ID
+// restored to the original one and the TSIG record removed from the additional count
+fn unsigned_message(message: &[u8], tsig: &Tsig) -> BytesMut {
+    let mut unsigned = BytesMut::with_capacity(tsig.offset);
+
+    unsigned.put_u16(tsig.original_id);
+    unsigned.put(&message[2..10]);
+    let additional_record_count = u16::from_be_bytes([message[10], message[11]]);
+    unsigned.put_u16(additional_record_count.saturating_sub(1));
+    unsigned.put(&message[12..tsig.offset]);
+
+    unsigned
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{
+        header::{OperationCode, ResponseCode},
+        message::MessageDecoder,
+        question::{Question, QuestionClass, QuestionType},
+        types::{DnsClass, DnsType},
+    };
+    use crate::zone::decode_hex;
+
+    const SECRET: &[u8] = b"secret-key-for-tests";
+
+    // A query for example.com A signed with hmac-sha256 key test-key and SECRET at 1700000000,
+    // with the MAC computed apart from this code following RFC 8945 section 4.3.3
+    const SIGNED_QUERY: &str = "123401000001000000000001076578616d706c6503636f6d0000010001087465\
+                                73742d6b65790000fa00ff00000000003d0b686d61632d736861323536000000\
+                                6553f100012c00200670759c3739573ec2a5a80c3818fbf704f5c4f81cfd1b16\
+                                1182ed7a78e107f1123400000000";
+
+    fn key() -> TsigKey {
+        TsigKey::new("test-key", TsigAlgorithm::HmacSha256, SECRET)
+    }
+
+    fn keyring() -> TsigKeyring {
+        TsigKeyring::new(vec![key()])
+    }
+
+    fn query() -> Message {
+        Message {
+            header: Header {
+                id: 0x1234,
+                query_indicator: false,
+                operation_code: OperationCode::StandardQuery,
+                auth_answer: false,
+                truncation: false,
+                recursion_desired: true,
+                recursion_available: false,
+                reserve: 0,
+                authentic_data: false,
+                checking_disabled: false,
+                code: ResponseCode::NoErrorCondition,
+                question_count: 1,
+                answer_record_count: 0,
+                auth_record_count: 0,
+                additional_record_count: 0,
+            },
+            questions: vec![Question {
+                name: "example.com".to_string(),
+                kind: QuestionType::DnsType(DnsType::A),
+                class: QuestionClass::DnsClass(DnsClass::IN),
+            }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            tsig: None,
+        }
+    }
+
+    fn response() -> Message {
+        let mut response = query();
+        response.header.query_indicator = true;
+        response
+    }
+
+    // `message` with `tsig` appended, and the TSIG record as decoded from it
+    fn with_tsig(message: &Message, tsig: Tsig) -> (Vec<u8>, Tsig) {
+        let signed = Message {
+            header: Header {
+                additional_record_count: message.header.additional_record_count + 1,
+                ..message.header
+            },
+            questions: message.questions.clone(),
+            answers: message.answers.clone(),
+            authorities: message.authorities.clone(),
+            additionals: message.additionals.clone(),
+            edns: message.edns.clone(),
+            tsig: Some(tsig),
+        };
+
+        let encoded = MessageEncoder::encode(&signed).to_vec();
+        let tsig = MessageDecoder::decode(&encoded).unwrap().tsig.unwrap();
+
+        (encoded, tsig)
+    }
+
+    // The query signed with the key by a client whose clock says `time_signed`
+    fn signed_at(time_signed: u64) -> (Vec<u8>, Tsig) {
+        let unsigned = MessageEncoder::encode(&query());
+        let mut tsig = key().sign_request(&unsigned);
+
+        tsig.time_signed = time_signed;
+
+        let mut signed = BytesMut::from(&unsigned[..]);
+        signed.put(TsigEncoder::encode_variables(&tsig));
+        tsig.mac = hmac::sign(&key().key, &signed).as_ref().to_vec();
+
+        with_tsig(&query(), tsig)
+    }
+
+    #[test]
+    fn signed_requests_are_verified() {
+        let (request, mac) = key().encode_signed(&query());
+        let tsig = MessageDecoder::decode(&request).unwrap().tsig.unwrap();
+
+        assert_eq!(tsig.mac, mac);
+        assert_eq!(keyring().verify(&request, &tsig), TsigErrorCode::NoError);
+    }
+
+    #[test]
+    fn requests_signed_elsewhere_are_verified() {
+        let mut request = decode_hex(SIGNED_QUERY).unwrap();
+        let tsig = MessageDecoder::decode(&request).unwrap().tsig.unwrap();
+
+        // The MAC is right, the signature is just too old
+        assert_eq!(keyring().verify(&request, &tsig), TsigErrorCode::BadTime);
+
+        // example.com becomes fxample.com
+        request[13] = b'f';
+
+        assert_eq!(
+            keyring().verify(&request, &tsig),
+            TsigErrorCode::BadSignature
+        );
+    }
+
+    #[test]
+    fn requests_signed_with_unknown_keys_are_badkey() {
+        let (request, _) = key().encode_signed(&query());
+        let tsig = MessageDecoder::decode(&request).unwrap().tsig.unwrap();
+
+        for key in [
+            TsigKey::new("other-key", TsigAlgorithm::HmacSha256, SECRET),
+            TsigKey::new("test-key", TsigAlgorithm::HmacSha512, SECRET),
+        ] {
+            assert_eq!(
+                TsigKeyring::new(vec![key]).verify(&request, &tsig),
+                TsigErrorCode::BadKey
+            );
+        }
+
+        let other_secret = TsigKey::new("test-key", TsigAlgorithm::HmacSha256, b"other");
+
+        assert_eq!(
+            TsigKeyring::new(vec![other_secret]).verify(&request, &tsig),
+            TsigErrorCode::BadSignature
+        );
+    }
+
+    #[test]
+    fn signatures_are_only_accepted_within_the_fudge() {
+        let fudge = TSIG_FUDGE as u64;
+
+        for (time_signed, error) in [
+            (now() - fudge + 10, TsigErrorCode::NoError),
+            (now() + fudge - 10, TsigErrorCode::NoError),
+            (now() - fudge - 10, TsigErrorCode::BadTime),
+            (now() + fudge + 10, TsigErrorCode::BadTime),
+        ] {
+            let (request, tsig) = signed_at(time_signed);
+
+            assert_eq!(keyring().verify(&request, &tsig), error);
+        }
+    }
+
+    #[test]
+    fn responses_are_signed_over_the_request_mac() {
+        let (request, request_mac) = key().encode_signed(&query());
+        let request_tsig = MessageDecoder::decode(&request).unwrap().tsig.unwrap();
+
+        let tsig = keyring().sign_response(
+            &request_tsig,
+            TsigErrorCode::NoError,
+            &MessageEncoder::encode(&response()),
+        );
+        let (response, tsig) = with_tsig(&response(), tsig);
+
+        assert_eq!(
+            key().verify_response(&request_mac, &response, &tsig),
+            TsigErrorCode::NoError
+        );
+        assert_eq!(
+            key().verify_response(&[0; 32], &response, &tsig),
+            TsigErrorCode::BadSignature
+        );
+    }
+
+    #[test]
+    fn failed_requests_get_the_error_back() {
+        let (_, request_tsig) = signed_at(now() - 3600);
+        let unsigned = MessageEncoder::encode(&response());
+
+        // The client cannot verify a MAC made with a key or over a signature that failed
+        for error in [TsigErrorCode::BadKey, TsigErrorCode::BadSignature] {
+            let tsig = keyring().sign_response(&request_tsig, error, &unsigned);
+
+            assert_eq!(tsig.error, error);
+            assert!(tsig.mac.is_empty());
+        }
+
+        // The client's own time comes back, with the server's time in the other data
+        let tsig = keyring().sign_response(&request_tsig, TsigErrorCode::BadTime, &unsigned);
+
+        assert_eq!(tsig.time_signed, request_tsig.time_signed);
+        assert_eq!(tsig.other_data.len(), 6);
+        assert!(!tsig.mac.is_empty());
+
+        let (response, tsig) = with_tsig(&response(), tsig);
+
+        assert_eq!(
+            key().verify_response(&request_tsig.mac, &response, &tsig),
+            TsigErrorCode::BadTime
+        );
+    }
+
+    #[test]
+    fn later_messages_are_signed_over_the_previous_mac() {
+        let (request, request_mac) = key().encode_signed(&query());
+        let request_tsig = MessageDecoder::decode(&request).unwrap().tsig.unwrap();
+        let unsigned = MessageEncoder::encode(&response());
+
+        let first = keyring().sign_response(&request_tsig, TsigErrorCode::NoError, &unsigned);
+        let second = keyring().sign_continuation(&first, &unsigned);
+        let (second_response, second) = with_tsig(&response(), second);
+
+        assert_eq!(
+            key().verify_continuation(&first.mac, &second_response, &second),
+            TsigErrorCode::NoError
+        );
+
+        // Only the timers are covered, and only the MAC of the message before
+        assert_eq!(
+            key().verify_response(&first.mac, &second_response, &second),
+            TsigErrorCode::BadSignature
+        );
+        assert_eq!(
+            key().verify_continuation(&request_mac, &second_response, &second),
+            TsigErrorCode::BadSignature
+        );
+    }
+
+    #[test]
+    fn keys_are_read_from_bind_key_files() {
+        let input = "# Written by tsig-keygen\n\
+                     key \"transfer\" {\n\
+                     \talgorithm hmac-sha512;\n\
+                     \tsecret \"c2VjcmV0LWtleS1mb3ItdGVzdHM=\"; // not a comment in quotes: //\n\
+                     };\n\
+                     key test-key { algorithm hmac-sha256; secret \"c2VjcmV0LWtleS1mb3ItdGVzdHM=\"; };\n";
+
+        let keys = TsigKey::parse_key_file(input).unwrap();
+
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].name, "transfer");
+        assert_eq!(keys[0].algorithm, TsigAlgorithm::HmacSha512);
+        assert_eq!(keys[1].name, "test-key");
+
+        // The secret is decoded, so the key verifies messages signed with SECRET
+        let (request, _) = key().encode_signed(&query());
+        let tsig = MessageDecoder::decode(&request).unwrap().tsig.unwrap();
+        let keyring = TsigKeyring::new(keys);
+
+        assert_eq!(keyring.verify(&request, &tsig), TsigErrorCode::NoError);
+
+        for input in [
+            "key transfer { algorithm hmac-sha256; };",
+            "key transfer { secret \"c2VjcmV0\"; };",
+            "key transfer { algorithm hmac-md5; secret \"c2VjcmV0\"; };",
+            "key transfer { algorithm hmac-sha256; secret \"c2VjcmV0\" };",
+            "options { };",
+        ] {
+            assert!(TsigKey::parse_key_file(input).is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,550 @@
+use std::{net::IpAddr, str::FromStr};
+
+use crate::{
+    error::ServerError,
+    message::{
+        answer::Answer,
+        dnssec::is_subdomain,
+        header::ResponseCode,
+        question::QuestionType,
+        rdata::RData,
+        types::{DnsClass, DnsType},
+        update::{UpdateClass, UpdateRecord},
+    },
+    zone::Zone,
+};
+
+/// A client address or network, e.g. one allowed to send dynamic updates, in the
+/// `<address>[/<length>]` form.
+#[derive(Debug, Clone, Copy)]
+pub struct AddressPrefix {
+    addr: IpAddr,
+    length: u8,
+}
+
+impl AddressPrefix {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(prefix), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.length as u32).unwrap_or(0);
+
+                u32::from(prefix) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(prefix), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.length as u32).unwrap_or(0);
+
+                u128::from(prefix) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for AddressPrefix {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || ServerError::InvalidAddressPrefix(format!("{} is not valid", value));
+
+        let (addr, length) = match value.split_once('/') {
+            Some((addr, length)) => (addr, Some(length)),
+            None => (value, None),
+        };
+
+        let addr = IpAddr::from_str(addr).map_err(|_| invalid())?;
+        let max_length = if addr.is_ipv4() { 32 } else { 128 };
+
+        let length = match length {
+            Some(length) => length.parse().map_err(|_| invalid())?,
+            None => max_length,
+        };
+
+        if length > max_length {
+            return Err(invalid());
+        }
+
+        Ok(Self { addr, length })
+    }
+}
+
+/// Checks the prerequisite section of an update against the zone. Every prerequisite must
+/// hold for the update to go ahead.
+///
+/// https://www.rfc-editor.org/rfc/rfc2136#section-3.2
+pub fn check_prerequisites(zone: &Zone, prerequisites: &[UpdateRecord]) -> ResponseCode {
+    let origin = zone.origin.to_lowercase();
+    let mut expected: Vec<(String, DnsType, &UpdateRecord)> = Vec::new();
+
+    for prerequisite in prerequisites {
+        let name = normalize(&prerequisite.name);
+
+        if !is_subdomain(&name, &origin) {
+            return ResponseCode::NotZone;
+        }
+
+        if prerequisite.ttl != 0 {
+            return ResponseCode::FormatError;
+        }
+
+        let holds = match (prerequisite.class, prerequisite.kind) {
+            (UpdateClass::ALL | UpdateClass::NONE, _) if prerequisite.data.is_some() => {
+                return ResponseCode::FormatError
+            }
+            (UpdateClass::ALL, QuestionType::ALL) => is_in_use(zone, &name)
+                .then_some(())
+                .ok_or(ResponseCode::NameError),
+            (UpdateClass::ALL, QuestionType::DnsType(kind)) => rrset(zone, &name, kind)
+                .next()
+                .map(|_| ())
+                .ok_or(ResponseCode::NXRRSet),
+            (UpdateClass::NONE, QuestionType::ALL) => (!is_in_use(zone, &name))
+                .then_some(())
+                .ok_or(ResponseCode::YXDomain),
+            (UpdateClass::NONE, QuestionType::DnsType(kind)) => rrset(zone, &name, kind)
+                .next()
+                .is_none()
+                .then_some(())
+                .ok_or(ResponseCode::YXRRSet),
+            // RRsets that must exist with exactly these records are compared once all of
+            // their records are known
+            (UpdateClass::DnsClass(DnsClass::IN), QuestionType::DnsType(kind))
+                if prerequisite.data.is_some() =>
+            {
+                expected.push((name, kind, prerequisite));
+                Ok(())
+            }
+            _ => return ResponseCode::FormatError,
+        };
+
+        if let Err(code) = holds {
+            return code;
+        }
+    }
+
+    for (name, kind, _) in &expected {
+        let wanted: Vec<&UpdateRecord> = expected
+            .iter()
+            .filter(|(other_name, other_kind, _)| other_name == name && other_kind == kind)
+            .map(|(_, _, record)| *record)
+            .collect();
+
+        let is_match = rrset(zone, name, *kind).all(|record| {
+            wanted
+                .iter()
+                .any(|prerequisite| prerequisite.data.as_ref() == Some(&record.data))
+        }) && wanted.iter().all(|prerequisite| {
+            rrset(zone, name, *kind).any(|record| prerequisite.data.as_ref() == Some(&record.data))
+        });
+
+        if !is_match {
+            return ResponseCode::NXRRSet;
+        }
+    }
+
+    ResponseCode::NoErrorCondition
+}
+
+/// Checks the update section before anything is changed, so an update is applied either whole
+/// or not at all.
+///
+/// https://www.rfc-editor.org/rfc/rfc2136#section-3.4.1
+pub fn prescan(origin: &str, updates: &[UpdateRecord]) -> ResponseCode {
+    for update in updates {
+        if !is_subdomain(&normalize(&update.name), origin) {
+            return ResponseCode::NotZone;
+        }
+
+        let is_valid = match (update.class, update.kind) {
+            (UpdateClass::DnsClass(DnsClass::IN), QuestionType::DnsType(_)) => {
+                update.data.is_some()
+            }
+            (UpdateClass::ALL, QuestionType::ALL | QuestionType::DnsType(_)) => {
+                update.ttl == 0 && update.data.is_none()
+            }
+            (UpdateClass::NONE, QuestionType::DnsType(_)) => {
+                update.ttl == 0 && update.data.is_some()
+            }
+            _ => false,
+        };
+
+        if !is_valid {
+            return ResponseCode::FormatError;
+        }
+    }
+
+    ResponseCode::NoErrorCondition
+}
+
+/// Applies an update section that passed the prescan, returning whether the zone changed.
+///
+/// The SOA record and the apex NS RRset are never deleted, the SOA is only replaced by one with
+/// a newer serial, and a name holds either a CNAME or other records, never both.
+///
+/// https://www.rfc-editor.org/rfc/rfc2136#section-3.4.2
+pub fn apply(zone: &mut Zone, updates: &[UpdateRecord]) -> bool {
+    let origin = zone.origin.to_lowercase();
+    let mut changed = false;
+
+    for update in updates {
+        let name = normalize(&update.name);
+        let is_apex = name == origin;
+        let before = zone.records.len();
+
+        match (update.class, update.kind, &update.data) {
+            (UpdateClass::DnsClass(_), QuestionType::DnsType(kind), Some(data)) => {
+                let is_cname = kind == DnsType::CNAME;
+                let conflicts = zone.records.iter().any(|record| {
+                    normalize(&record.name) == name
+                        && !is_dnssec(record.kind)
+                        && (record.kind == DnsType::CNAME) != is_cname
+                });
+
+                if conflicts || (kind == DnsType::SOA && !is_apex) {
+                    continue;
+                }
+
+                // A name has one CNAME and a zone one SOA, the new record replaces the old one
+                if matches!(kind, DnsType::CNAME | DnsType::SOA) {
+                    let is_newer = zone
+                        .records
+                        .iter()
+                        .all(|record| match (&record.data, data) {
+                            (RData::Soa(old), RData::Soa(new)) if record.kind == kind => {
+                                new.serial > old.serial
+                            }
+                            _ => true,
+                        });
+
+                    if !is_newer {
+                        continue;
+                    }
+
+                    zone.records
+                        .retain(|record| !(record.kind == kind && normalize(&record.name) == name));
+                }
+
+                let mut is_present = false;
+
+                // The records of an RRset share the TTL of the last one added (RFC 2181
+                // section 5.2)
+                for record in zone.records.iter_mut() {
+                    if record.kind == kind && normalize(&record.name) == name {
+                        changed |= record.ttl != update.ttl;
+                        record.ttl = update.ttl;
+                        is_present |= record.data == *data;
+                    }
+                }
+
+                if !is_present {
+                    changed = true;
+                    zone.records.push(Answer {
+                        name: update.name.trim_end_matches('.').to_string(),
+                        kind,
+                        class: DnsClass::IN,
+                        ttl: update.ttl,
+                        data: data.clone(),
+                    });
+                }
+            }
+            (UpdateClass::ALL, QuestionType::ALL, _) => {
+                zone.records.retain(|record| {
+                    normalize(&record.name) != name
+                        || (is_apex && matches!(record.kind, DnsType::SOA | DnsType::NS))
+                });
+            }
+            (UpdateClass::ALL, QuestionType::DnsType(kind), _) => {
+                if is_apex && matches!(kind, DnsType::SOA | DnsType::NS) {
+                    continue;
+                }
+
+                zone.records
+                    .retain(|record| !(record.kind == kind && normalize(&record.name) == name));
+            }
+            (UpdateClass::NONE, QuestionType::DnsType(kind), Some(data)) => {
+                let remaining = rrset(zone, &name, kind).count();
+
+                if kind == DnsType::SOA || (is_apex && kind == DnsType::NS && remaining <= 1) {
+                    continue;
+                }
+
+                zone.records.retain(|record| {
+                    !(record.kind == kind
+                        && normalize(&record.name) == name
+                        && record.data == *data)
+                });
+            }
+            _ => {}
+        }
+
+        changed |= zone.records.len() != before;
+    }
+
+    changed
+}
+
+fn rrset<'z>(zone: &'z Zone, name: &'z str, kind: DnsType) -> impl Iterator<Item = &'z Answer> {
+    zone.records
+        .iter()
+        .filter(move |record| record.kind == kind && normalize(&record.name) == name)
+}
+
+// Empty non-terminals are not in use, only names that own records are (RFC 2136 section 2.4.4)
+fn is_in_use(zone: &Zone, name: &str) -> bool {
+    zone.records
+        .iter()
+        .any(|record| normalize(&record.name) == name)
+}
+
+// Signatures and denial records live alongside CNAME records
+fn is_dnssec(kind: DnsType) -> bool {
+    matches!(kind, DnsType::RRSIG | DnsType::NSEC | DnsType::NSEC3)
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::zone::ZoneFileParser;
+
+    fn zone() -> Zone {
+        let input = "@ 3600 IN SOA ns1 hostmaster 1 7200 1800 1209600 300\n\
+                     @ 3600 IN NS ns1\n\
+                     ns1 3600 IN A 192.0.2.1\n\
+                     www 300 IN A 192.0.2.2\n\
+                     alias 300 IN CNAME www\n";
+
+        ZoneFileParser::new(input, "example.com").parse().unwrap()
+    }
+
+    fn record(
+        name: &str,
+        kind: QuestionType,
+        class: UpdateClass,
+        data: Option<RData>,
+    ) -> UpdateRecord {
+        UpdateRecord {
+            name: name.to_string(),
+            kind,
+            class,
+            ttl: 0,
+            data,
+        }
+    }
+
+    fn a(name: &str, class: UpdateClass, addr: [u8; 4]) -> UpdateRecord {
+        record(
+            name,
+            QuestionType::DnsType(DnsType::A),
+            class,
+            Some(RData::A(Ipv4Addr::from(addr))),
+        )
+    }
+
+    fn addresses(zone: &Zone, name: &str) -> Vec<RData> {
+        rrset(zone, name, DnsType::A)
+            .map(|record| record.data.clone())
+            .collect()
+    }
+
+    #[test]
+    fn address_prefixes() {
+        let prefix = AddressPrefix::from_str("192.0.2.0/24").unwrap();
+
+        assert!(prefix.contains("192.0.2.77".parse().unwrap()));
+        assert!(!prefix.contains("192.0.3.1".parse().unwrap()));
+        assert!(!prefix.contains("::1".parse().unwrap()));
+        assert!(AddressPrefix::from_str("::/0")
+            .unwrap()
+            .contains("2001:db8::1".parse().unwrap()));
+        assert!(AddressPrefix::from_str("127.0.0.1")
+            .unwrap()
+            .contains("127.0.0.1".parse().unwrap()));
+        assert!(AddressPrefix::from_str("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn prerequisites_on_names_and_rrsets() {
+        let zone = zone();
+        let any = |name| record(name, QuestionType::ALL, UpdateClass::ALL, None);
+        let none = |name| record(name, QuestionType::ALL, UpdateClass::NONE, None);
+
+        let check = |prerequisite| check_prerequisites(&zone, &[prerequisite]);
+
+        assert!(matches!(
+            check(any("www.example.com")),
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(matches!(
+            check(any("new.example.com")),
+            ResponseCode::NameError
+        ));
+        assert!(matches!(
+            check(none("new.example.com")),
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(matches!(
+            check(none("www.example.com")),
+            ResponseCode::YXDomain
+        ));
+        assert!(matches!(
+            check(record(
+                "www.example.com",
+                QuestionType::DnsType(DnsType::TXT),
+                UpdateClass::ALL,
+                None
+            )),
+            ResponseCode::NXRRSet
+        ));
+        assert!(matches!(
+            check(record(
+                "www.example.com",
+                QuestionType::DnsType(DnsType::A),
+                UpdateClass::NONE,
+                None
+            )),
+            ResponseCode::YXRRSet
+        ));
+        assert!(matches!(
+            check(any("www.example.org")),
+            ResponseCode::NotZone
+        ));
+    }
+
+    #[test]
+    fn value_dependent_prerequisites_match_the_whole_rrset() {
+        let zone = zone();
+        let in_class = UpdateClass::DnsClass(DnsClass::IN);
+
+        assert!(matches!(
+            check_prerequisites(&zone, &[a("www.example.com", in_class, [192, 0, 2, 2])]),
+            ResponseCode::NoErrorCondition
+        ));
+        assert!(matches!(
+            check_prerequisites(
+                &zone,
+                &[
+                    a("www.example.com", in_class, [192, 0, 2, 2]),
+                    a("www.example.com", in_class, [192, 0, 2, 3]),
+                ]
+            ),
+            ResponseCode::NXRRSet
+        ));
+    }
+
+    #[test]
+    fn prescan_rejects_malformed_updates() {
+        let mut deletion = record(
+            "www.example.com",
+            QuestionType::DnsType(DnsType::A),
+            UpdateClass::ALL,
+            None,
+        );
+        deletion.ttl = 60;
+
+        assert!(matches!(
+            prescan("example.com", &[deletion]),
+            ResponseCode::FormatError
+        ));
+        assert!(matches!(
+            prescan(
+                "example.com",
+                &[record(
+                    "www.example.com",
+                    QuestionType::AXFR,
+                    UpdateClass::ALL,
+                    None
+                )]
+            ),
+            ResponseCode::FormatError
+        ));
+        assert!(matches!(
+            prescan(
+                "example.com",
+                &[a("www.example.org", UpdateClass::NONE, [192, 0, 2, 2])]
+            ),
+            ResponseCode::NotZone
+        ));
+    }
+
+    #[test]
+    fn additions_share_the_rrset_ttl_and_skip_duplicates() {
+        let mut zone = zone();
+        let mut addition = a(
+            "www.example.com",
+            UpdateClass::DnsClass(DnsClass::IN),
+            [192, 0, 2, 3],
+        );
+        addition.ttl = 600;
+
+        assert!(apply(&mut zone, &[addition.clone()]));
+        assert!(!apply(&mut zone, &[addition]));
+
+        assert_eq!(addresses(&zone, "www.example.com").len(), 2);
+        assert!(rrset(&zone, "www.example.com", DnsType::A).all(|record| record.ttl == 600));
+    }
+
+    #[test]
+    fn cname_conflicts_are_ignored() {
+        let mut zone = zone();
+        let in_class = UpdateClass::DnsClass(DnsClass::IN);
+
+        assert!(!apply(
+            &mut zone,
+            &[a("alias.example.com", in_class, [192, 0, 2, 3])]
+        ));
+        assert!(!apply(
+            &mut zone,
+            &[record(
+                "www.example.com",
+                QuestionType::DnsType(DnsType::CNAME),
+                in_class,
+                Some(RData::DomainName("ns1.example.com".to_string()))
+            )]
+        ));
+    }
+
+    #[test]
+    fn deletions_keep_the_apex_soa_and_ns() {
+        let mut zone = zone();
+
+        assert!(!apply(
+            &mut zone,
+            &[record(
+                "example.com",
+                QuestionType::ALL,
+                UpdateClass::ALL,
+                None
+            )]
+        ));
+        assert!(!apply(
+            &mut zone,
+            &[record(
+                "example.com",
+                QuestionType::DnsType(DnsType::NS),
+                UpdateClass::NONE,
+                Some(RData::DomainName("ns1.example.com".to_string()))
+            )]
+        ));
+
+        assert!(apply(
+            &mut zone,
+            &[a("www.example.com", UpdateClass::NONE, [192, 0, 2, 2])]
+        ));
+        assert!(addresses(&zone, "www.example.com").is_empty());
+        assert!(apply(
+            &mut zone,
+            &[record(
+                "alias.example.com",
+                QuestionType::ALL,
+                UpdateClass::ALL,
+                None
+            )]
+        ));
+        assert_eq!(zone.records.len(), 3);
+    }
+}
@@ -0,0 +1,1001 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    net::{SocketAddr, UdpSocket},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{BufMut, BytesMut};
+use ring::{digest, signature};
+
+use crate::{
+    error::ServerError,
+    message::{
+        answer::{Answer, AnswersBuilder},
+        dnssec::{
+            base32hex, is_subdomain, rrsets, Dnskey, Ds, Nsec3, Rrsig, TypeBitmap,
+            ALGORITHM_ECDSAP256SHA256, ALGORITHM_ECDSAP384SHA384, ALGORITHM_ED25519,
+            ALGORITHM_RSASHA256, ALGORITHM_RSASHA512, DIGEST_SHA1, DIGEST_SHA256, DIGEST_SHA384,
+        },
+        edns::Edns,
+        header::{Header, OperationCode, ResponseCode},
+        message::Message,
+        question::{Question, QuestionClass, QuestionType},
+        rdata::{RData, RDataEncoder},
+        types::{DnsClass, DnsType, DomainName},
+    },
+    zone::ZoneFileParser,
+};
+
+// Validators may treat NSEC3 records with more iterations than this as insecure, the hashing
+// cost is otherwise in the hands of the zone (RFC 9276 section 3.2)
+const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// Outcome of validating a response.
+///
+/// https://www.rfc-editor.org/rfc/rfc4035#section-4.3
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Security {
+    /// Every RRset chains up to a trust anchor.
+    Secure,
+    /// The data sits below a delegation that is proven to be unsigned.
+    Insecure,
+    /// A signature or a proof is missing or wrong.
+    Bogus,
+}
+
+impl Security {
+    /// A response is only as secure as its weakest RRset.
+    pub fn combine(self, other: Security) -> Security {
+        match (self, other) {
+            (Security::Bogus, _) | (_, Security::Bogus) => Security::Bogus,
+            (Security::Insecure, _) | (_, Security::Insecure) => Security::Insecure,
+            _ => Security::Secure,
+        }
+    }
+}
+
+/// A DS or DNSKEY record trusted without validation, usually the root zone key signing key.
+#[derive(Debug, Clone)]
+pub struct TrustAnchor {
+    zone: String,
+    ds: Vec<Ds>,
+    dnskeys: Vec<Dnskey>,
+}
+
+/// Parses an anchor in master file form, either
+/// `<zone> [ttl] [IN] DS <key tag> <algorithm> <digest type> <hex digest>` or
+/// `<zone> [ttl] [IN] DNSKEY <flags> <protocol> <algorithm> <base64 key>`.
+impl FromStr for TrustAnchor {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ServerError::InvalidTrustAnchor(format!(
+                "{} is not a single DS or DNSKEY record",
+                value
+            ))
+        };
+
+        let records = ZoneFileParser::new(value, "")
+            .parse()
+            .map_err(|err| ServerError::InvalidTrustAnchor(err.to_string()))?
+            .records;
+
+        let [record] = &records[..] else {
+            return Err(invalid());
+        };
+
+        let zone = normalize(&record.name);
+
+        match (&record.kind, &record.data) {
+            (DnsType::DS, RData::Ds(ds)) => Ok(Self {
+                zone,
+                ds: vec![ds.clone()],
+                dnskeys: Vec::new(),
+            }),
+            (DnsType::DNSKEY, RData::Dnskey(dnskey)) => Ok(Self {
+                zone,
+                ds: Vec::new(),
+                dnskeys: vec![dnskey.clone()],
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Keys of a zone once its DNSKEY RRset has been checked against the parent
+#[derive(Debug, Clone)]
+enum ZoneKeys {
+    Secure(Vec<Dnskey>),
+    Insecure,
+    Bogus,
+}
+
+// What the parent says about a name when asked for its DS records
+enum DelegationSigners {
+    Secure(Vec<Ds>),
+    /// The name is a delegation without DS records, so the child zone is unsigned.
+    Insecure,
+    /// The name is not a delegation point.
+    NotDelegated,
+    Bogus,
+}
+
+// What the NSEC or NSEC3 records of a negative response prove
+enum Denial {
+    /// The name exists without the requested type, these are the types it has.
+    NoData(TypeBitmap),
+    NameError,
+    /// The name falls in an NSEC3 opt-out span, so it may be an unsigned delegation.
+    OptOut,
+    Insecure,
+    /// The response has no NSEC or NSEC3 records at all.
+    Unsigned,
+    Bogus,
+}
+
+/// Validates resolver responses, following the chain of trust from the configured anchors.
+/// The DS and DNSKEY records it needs are queried from the same resolver, and are cached for
+/// the lifetime of the validator only.
+///
+/// https://www.rfc-editor.org/rfc/rfc4035#section-5
+pub struct Validator<'a> {
+    anchors: &'a [TrustAnchor],
+    socket: &'a UdpSocket,
+    addr: &'a SocketAddr,
+    zone_keys: HashMap<String, ZoneKeys>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(anchors: &'a [TrustAnchor], socket: &'a UdpSocket, addr: &'a SocketAddr) -> Self {
+        Self {
+            anchors,
+            socket,
+            addr,
+            zone_keys: HashMap::new(),
+        }
+    }
+
+    /// Validates the answer section of a response, or the proof of non-existence in its
+    /// authority section when there are no answers.
+    pub fn validate(&mut self, question: &Question, response: &Message) -> Security {
+        let name = normalize(&question.name);
+
+        if self.closest_anchor(&name).is_none() {
+            return Security::Insecure;
+        }
+
+        let mut security = Security::Secure;
+        let rrsets = rrsets(&response.answers);
+
+        for rrset in &rrsets {
+            let owner = normalize(&rrset[0].name);
+            let signatures = signatures(&response.answers, &owner, rrset[0].kind);
+
+            let rrset_security = if signatures.is_empty() && is_synthesized(rrset, response) {
+                // As secure as the DNAME it follows from, which is validated on its own
+                continue;
+            } else if signatures.is_empty() {
+                self.classify_unsigned(&owner)
+            } else {
+                self.verify_answer(rrset, &signatures, response)
+            };
+
+            security = security.combine(rrset_security);
+        }
+
+        if rrsets.is_empty() {
+            let kind: u16 = question.kind.into();
+            let is_name_error = matches!(response.header.code, ResponseCode::NameError);
+
+            let denial_security = match self.validate_denial(&name, kind, response) {
+                Denial::NoData(_) if !is_name_error => Security::Secure,
+                Denial::NameError if is_name_error => Security::Secure,
+                Denial::OptOut | Denial::Insecure => Security::Insecure,
+                Denial::Unsigned => self.classify_unsigned(&name),
+                _ => Security::Bogus,
+            };
+
+            security = security.combine(denial_security);
+        }
+
+        security
+    }
+
+    // An RRset synthesized from a wildcard is signed with fewer labels than its owner name. It
+    // is only secure along with proof that the owner name itself does not exist, otherwise the
+    // wildcard would not apply (RFC 4035 section 5.3.4)
+    fn verify_answer(
+        &mut self,
+        records: &[&Answer],
+        signatures: &[&Rrsig],
+        response: &Message,
+    ) -> Security {
+        let owner = normalize(&records[0].name);
+        let owner_labels = label_count(owner.strip_prefix("*.").unwrap_or(&owner));
+
+        let (exact, expanded): (Vec<&Rrsig>, Vec<&Rrsig>) = signatures
+            .iter()
+            .partition(|rrsig| rrsig.labels as usize >= owner_labels);
+
+        let mut security = self.verify_rrset(records, &exact);
+
+        if security == Security::Secure {
+            return security;
+        }
+
+        for rrsig in expanded {
+            match self.verify_rrset(records, &[rrsig]) {
+                Security::Secure => {
+                    return self.validate_wildcard_proof(&owner, rrsig.labels, response)
+                }
+                Security::Insecure => security = Security::Insecure,
+                Security::Bogus => {}
+            }
+        }
+
+        security
+    }
+
+    // NSEC proves the owner name does not exist by covering it, NSEC3 by covering the next
+    // closer name, the one label below the wildcard's parent (RFC 5155 section 8.8)
+    fn validate_wildcard_proof(&mut self, name: &str, labels: u8, response: &Message) -> Security {
+        let records = match self.verified_proofs(response) {
+            Ok(records) => records,
+            Err(Denial::Insecure) => return Security::Insecure,
+            Err(_) => return Security::Bogus,
+        };
+
+        if exceeds_iteration_limit(&records) {
+            return Security::Insecure;
+        }
+
+        let next_closer = suffix(name, labels as usize + 1);
+
+        if nsec_covering(name, &records).is_some()
+            || nsec3_covering(&next_closer, &records).is_some()
+        {
+            Security::Secure
+        } else {
+            Security::Bogus
+        }
+    }
+
+    // Checks the RRset against each signature until one verifies with a trusted key
+    fn verify_rrset(&mut self, records: &[&Answer], signatures: &[&Rrsig]) -> Security {
+        let owner = normalize(&records[0].name);
+        let mut security = Security::Bogus;
+
+        for rrsig in signatures {
+            let signer = normalize(&rrsig.signer_name);
+
+            if !is_subdomain(&owner, &signer) {
+                continue;
+            }
+
+            // A DS RRset is signed by the parent zone, never by the zone it points to
+            if records[0].kind == DnsType::DS && signer == owner {
+                continue;
+            }
+
+            if self.closest_anchor(&signer).is_none() {
+                security = Security::Insecure;
+                continue;
+            }
+
+            match self.zone_keys(&signer) {
+                ZoneKeys::Secure(keys) => {
+                    if keys.iter().any(|key| verify_rrsig(records, rrsig, key)) {
+                        return Security::Secure;
+                    }
+                }
+                ZoneKeys::Insecure => security = Security::Insecure,
+                ZoneKeys::Bogus => {}
+            }
+        }
+
+        security
+    }
+
+    fn zone_keys(&mut self, zone: &str) -> ZoneKeys {
+        if let Some(keys) = self.zone_keys.get(zone) {
+            return keys.clone();
+        }
+
+        // Seen as bogus while it is being worked out, so a loop of signatures cannot recurse
+        // forever
+        self.zone_keys.insert(zone.to_string(), ZoneKeys::Bogus);
+
+        let keys = self.find_zone_keys(zone);
+
+        self.zone_keys.insert(zone.to_string(), keys.clone());
+
+        keys
+    }
+
+    // The DNSKEY RRset is trusted when it is signed by a key that matches one of the parent's
+    // DS records, or one of the anchors
+    fn find_zone_keys(&mut self, zone: &str) -> ZoneKeys {
+        let anchors: Vec<&TrustAnchor> = self
+            .anchors
+            .iter()
+            .filter(|anchor| anchor.zone == zone)
+            .collect();
+
+        let ds = if anchors.is_empty() {
+            match self.delegation_signers(zone) {
+                DelegationSigners::Secure(ds) => ds,
+                DelegationSigners::Insecure => return ZoneKeys::Insecure,
+                DelegationSigners::NotDelegated | DelegationSigners::Bogus => {
+                    return ZoneKeys::Bogus
+                }
+            }
+        } else {
+            anchors
+                .iter()
+                .flat_map(|anchor| anchor.ds.iter().cloned())
+                .collect()
+        };
+
+        let anchored_keys: Vec<&Dnskey> = anchors
+            .iter()
+            .flat_map(|anchor| anchor.dnskeys.iter())
+            .collect();
+
+        // A zone signed only with algorithms the server does not support is treated as
+        // unsigned (RFC 4035 section 5.2)
+        let ds: Vec<Ds> = ds
+            .into_iter()
+            .filter(|ds| is_supported_algorithm(ds.algorithm))
+            .filter(|ds| digest_algorithm(ds.digest_type).is_some())
+            .collect();
+
+        if ds.is_empty() && anchored_keys.is_empty() {
+            return ZoneKeys::Insecure;
+        }
+
+        let Some(response) = self.query(zone, DnsType::DNSKEY) else {
+            return ZoneKeys::Bogus;
+        };
+
+        let records: Vec<&Answer> = response
+            .answers
+            .iter()
+            .filter(|answer| answer.kind == DnsType::DNSKEY && normalize(&answer.name) == zone)
+            .collect();
+
+        let dnskeys: Vec<&Dnskey> = records
+            .iter()
+            .filter_map(|record| match &record.data {
+                RData::Dnskey(dnskey) => Some(dnskey),
+                _ => None,
+            })
+            .collect();
+
+        let trusted_keys: Vec<&Dnskey> = dnskeys
+            .iter()
+            .copied()
+            .filter(|key| {
+                anchored_keys.contains(key) || ds.iter().any(|ds| ds_matches(zone, key, ds))
+            })
+            .collect();
+
+        let is_signed = signatures(&response.answers, zone, DnsType::DNSKEY)
+            .iter()
+            .filter(|rrsig| normalize(&rrsig.signer_name) == zone)
+            .any(|rrsig| {
+                trusted_keys
+                    .iter()
+                    .any(|key| verify_rrsig(&records, rrsig, key))
+            });
+
+        if !is_signed {
+            return ZoneKeys::Bogus;
+        }
+
+        ZoneKeys::Secure(
+            dnskeys
+                .into_iter()
+                .filter(|key| key.flags & Dnskey::ZONE_KEY_FLAG > 0)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn delegation_signers(&mut self, zone: &str) -> DelegationSigners {
+        let Some(response) = self.query(zone, DnsType::DS) else {
+            return DelegationSigners::Bogus;
+        };
+
+        let records: Vec<&Answer> = response
+            .answers
+            .iter()
+            .filter(|answer| answer.kind == DnsType::DS && normalize(&answer.name) == zone)
+            .collect();
+
+        if !records.is_empty() {
+            let signatures = signatures(&response.answers, zone, DnsType::DS);
+
+            // An unsigned DS RRset is never acceptable, the parent is known to be signed
+            if signatures.is_empty() {
+                return DelegationSigners::Bogus;
+            }
+
+            return match self.verify_rrset(&records, &signatures) {
+                Security::Secure => DelegationSigners::Secure(
+                    records
+                        .iter()
+                        .filter_map(|record| match &record.data {
+                            RData::Ds(ds) => Some(ds.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                ),
+                Security::Insecure => DelegationSigners::Insecure,
+                Security::Bogus => DelegationSigners::Bogus,
+            };
+        }
+
+        let ns: u16 = DnsType::NS.into();
+        let soa: u16 = DnsType::SOA.into();
+
+        match self.validate_denial(zone, DnsType::DS.into(), &response) {
+            Denial::NoData(types) if types.contains(ns) && !types.contains(soa) => {
+                DelegationSigners::Insecure
+            }
+            Denial::NoData(_) | Denial::NameError => DelegationSigners::NotDelegated,
+            Denial::OptOut | Denial::Insecure => DelegationSigners::Insecure,
+            Denial::Unsigned | Denial::Bogus => DelegationSigners::Bogus,
+        }
+    }
+
+    // Validates the NSEC or NSEC3 records of a negative response, then looks for one that
+    // matches the name without the type, or proves that neither the name nor a wildcard that
+    // could have produced it exist
+    fn validate_denial(&mut self, name: &str, kind: u16, response: &Message) -> Denial {
+        let records = match self.verified_proofs(response) {
+            Ok(records) => records,
+            Err(denial) => return denial,
+        };
+
+        if exceeds_iteration_limit(&records) {
+            return Denial::Insecure;
+        }
+
+        if matches!(response.header.code, ResponseCode::NameError) {
+            return if proves_name_error(name, &records) {
+                Denial::NameError
+            } else {
+                Denial::Bogus
+            };
+        }
+
+        let cname: u16 = DnsType::CNAME.into();
+
+        for record in &records {
+            let owner = normalize(&record.name);
+
+            match &record.data {
+                RData::Nsec(nsec)
+                    if owner == name
+                        && !nsec.types.contains(kind)
+                        && !nsec.types.contains(cname) =>
+                {
+                    return Denial::NoData(nsec.types.clone());
+                }
+                // An empty non-terminal has no NSEC record of its own, the span that covers it
+                // ends at one of its descendants (RFC 4035 section 3.1.3.2)
+                RData::Nsec(nsec) => {
+                    let next = normalize(&nsec.next_domain_name);
+
+                    if next != name
+                        && is_subdomain(&next, name)
+                        && covers(&owner, &next, name, DomainName::canonical_cmp)
+                    {
+                        return Denial::NoData(TypeBitmap::default());
+                    }
+                }
+                RData::Nsec3(nsec3) => {
+                    let Some((owner_hash, zone)) = owner.split_once('.') else {
+                        continue;
+                    };
+
+                    if !is_subdomain(name, zone) {
+                        continue;
+                    }
+
+                    let Some(hash) = nsec3_hash(name, nsec3) else {
+                        continue;
+                    };
+
+                    if owner_hash == hash
+                        && !nsec3.types.contains(kind)
+                        && !nsec3.types.contains(cname)
+                    {
+                        return Denial::NoData(nsec3.types.clone());
+                    }
+
+                    let next = base32hex(&nsec3.next_hashed_owner_name);
+
+                    if covers(owner_hash, &next, &hash, |a, b| a.cmp(b))
+                        && nsec3.flags & Nsec3::OPT_OUT_FLAG > 0
+                    {
+                        return Denial::OptOut;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match wildcard_no_data(name, kind, &records) {
+            Some(types) => Denial::NoData(types),
+            None => Denial::Bogus,
+        }
+    }
+
+    // The NSEC and NSEC3 records of the authority section, once their signatures are checked
+    fn verified_proofs<'r>(&mut self, response: &'r Message) -> Result<Vec<&'r Answer>, Denial> {
+        let proofs: Vec<Vec<&Answer>> = rrsets(&response.authorities)
+            .into_iter()
+            .filter(|rrset| matches!(rrset[0].kind, DnsType::NSEC | DnsType::NSEC3))
+            .collect();
+
+        if proofs.is_empty() {
+            return Err(Denial::Unsigned);
+        }
+
+        for rrset in &proofs {
+            let owner = normalize(&rrset[0].name);
+            let signatures = signatures(&response.authorities, &owner, rrset[0].kind);
+
+            match self.verify_rrset(rrset, &signatures) {
+                Security::Secure => {}
+                Security::Insecure => return Err(Denial::Insecure),
+                Security::Bogus => return Err(Denial::Bogus),
+            }
+        }
+
+        Ok(proofs.into_iter().flatten().collect())
+    }
+
+    // Unsigned data is only acceptable below a delegation that is proven to have no DS
+    // records. The delegations between the closest anchor and the name are walked top down.
+    fn classify_unsigned(&mut self, name: &str) -> Security {
+        let Some(anchor) = self.closest_anchor(name) else {
+            return Security::Insecure;
+        };
+
+        let labels: Vec<&str> = labels(name).collect();
+
+        for depth in (label_count(&anchor) + 1)..=labels.len() {
+            let ancestor = labels[labels.len() - depth..].join(".");
+
+            match self.delegation_signers(&ancestor) {
+                DelegationSigners::Secure(_) | DelegationSigners::NotDelegated => {}
+                DelegationSigners::Insecure => return Security::Insecure,
+                DelegationSigners::Bogus => return Security::Bogus,
+            }
+        }
+
+        Security::Bogus
+    }
+
+    fn closest_anchor(&self, name: &str) -> Option<String> {
+        self.anchors
+            .iter()
+            .filter(|anchor| is_subdomain(name, &anchor.zone))
+            .max_by_key(|anchor| label_count(&anchor.zone))
+            .map(|anchor| anchor.zone.clone())
+    }
+
+    // Validation queries set the DO bit to get signatures back and the CD bit so the resolver
+    // hands over data even when it considers it bogus
+    fn query(&self, name: &str, kind: DnsType) -> Option<Message> {
+        let question = Question {
+            name: name.to_string(),
+            kind: QuestionType::DnsType(kind),
+            class: QuestionClass::DnsClass(DnsClass::IN),
+        };
+
+        let header = Header {
+            // Replaced by a random one when the query is sent
+            id: 0,
+            query_indicator: false,
+            operation_code: OperationCode::StandardQuery,
+            auth_answer: false,
+            truncation: false,
+            recursion_desired: true,
+            recursion_available: false,
+            reserve: 0,
+            authentic_data: false,
+            checking_disabled: true,
+            code: ResponseCode::NoErrorCondition,
+            question_count: 1,
+            answer_record_count: 0,
+            auth_record_count: 0,
+            additional_record_count: 0,
+        };
+
+        AnswersBuilder::forward_question(
+            &question,
+            &header,
+            Some(&Edns::new(true)),
+            self.socket,
+            self.addr,
+        )
+        .map_err(|err| eprintln!("DNSSEC validation query for {} failed: {}", name, err))
+        .ok()
+    }
+}
+
+/// Verifies one RRSIG over an RRset with a single key.
+///
+/// https://www.rfc-editor.org/rfc/rfc4035#section-5.3
+pub fn verify_rrsig(records: &[&Answer], rrsig: &Rrsig, key: &Dnskey) -> bool {
+    if key.key_tag() != rrsig.key_tag || key.algorithm != rrsig.algorithm {
+        return false;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or_default();
+
+    // Serial number arithmetic, the validity period may wrap around 2^32
+    if (now.wrapping_sub(rrsig.inception) as i32) < 0
+        || (rrsig.expiration.wrapping_sub(now) as i32) < 0
+    {
+        return false;
+    }
+
+    let Some(first) = records.first() else {
+        return false;
+    };
+
+    let owner = normalize(&first.name);
+    let owner_labels: Vec<&str> = labels(&owner).collect();
+    let signed_labels = rrsig.labels as usize;
+
+    if signed_labels > owner_labels.len() {
+        return false;
+    }
+
+    // Records synthesized from a wildcard are signed under the wildcard name
+    let signed_owner = if signed_labels < owner_labels.len() {
+        let suffix = owner_labels[owner_labels.len() - signed_labels..].join(".");
+
+        format!("*.{}", suffix)
+    } else {
+        owner
+    };
+
+    let data: Vec<&RData> = records.iter().map(|record| &record.data).collect();
+    let data = rrsig.signed_data(&signed_owner, first.class, &data);
+
+    verify_signature(key, &data, &rrsig.signature)
+}
+
+fn verify_signature(key: &Dnskey, message: &[u8], signature: &[u8]) -> bool {
+    match key.algorithm {
+        ALGORITHM_RSASHA256 | ALGORITHM_RSASHA512 => {
+            let Some((exponent, modulus)) = split_rsa_key(&key.public_key) else {
+                return false;
+            };
+
+            let parameters = if key.algorithm == ALGORITHM_RSASHA256 {
+                &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY
+            } else {
+                &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY
+            };
+
+            signature::RsaPublicKeyComponents {
+                n: modulus,
+                e: exponent,
+            }
+            .verify(parameters, message, signature)
+            .is_ok()
+        }
+        ALGORITHM_ECDSAP256SHA256 | ALGORITHM_ECDSAP384SHA384 => {
+            let algorithm = if key.algorithm == ALGORITHM_ECDSAP256SHA256 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+
+            signature::UnparsedPublicKey::new(algorithm, key.sec1_public_key())
+                .verify(message, signature)
+                .is_ok()
+        }
+        ALGORITHM_ED25519 => {
+            signature::UnparsedPublicKey::new(&signature::ED25519, &key.public_key)
+                .verify(message, signature)
+                .is_ok()
+        }
+        _ => false,
+    }
+}
+
+// The exponent length is one octet, or zero followed by two octets for long exponents
+//
+// https://www.rfc-editor.org/rfc/rfc3110#section-2
+fn split_rsa_key(public_key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (length, rest) = match public_key {
+        [0, high, low, rest @ ..] => ((*high as usize) << 8 | *low as usize, rest),
+        [length, rest @ ..] => (*length as usize, rest),
+        [] => return None,
+    };
+
+    if length == 0 || rest.len() <= length {
+        return None;
+    }
+
+    Some(rest.split_at(length))
+}
+
+fn is_supported_algorithm(algorithm: u8) -> bool {
+    matches!(
+        algorithm,
+        ALGORITHM_RSASHA256
+            | ALGORITHM_RSASHA512
+            | ALGORITHM_ECDSAP256SHA256
+            | ALGORITHM_ECDSAP384SHA384
+            | ALGORITHM_ED25519
+    )
+}
+
+fn digest_algorithm(digest_type: u8) -> Option<&'static digest::Algorithm> {
+    match digest_type {
+        DIGEST_SHA1 => Some(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        DIGEST_SHA256 => Some(&digest::SHA256),
+        DIGEST_SHA384 => Some(&digest::SHA384),
+        _ => None,
+    }
+}
+
+// The digest covers the canonical owner name followed by the DNSKEY record data
+//
+// https://www.rfc-editor.org/rfc/rfc4034#section-5.1.4
+fn ds_matches(zone: &str, key: &Dnskey, ds: &Ds) -> bool {
+    if ds.key_tag != key.key_tag() || ds.algorithm != key.algorithm {
+        return false;
+    }
+
+    let Some(algorithm) = digest_algorithm(ds.digest_type) else {
+        return false;
+    };
+
+    let mut data = BytesMut::new();
+    data.put(DomainName::encode(zone));
+    data.put(RDataEncoder::encode_canonical(&RData::Dnskey(key.clone())));
+
+    digest::digest(algorithm, &data).as_ref() == ds.digest
+}
+
+/// Hashed owner name of an NSEC3 record, without the zone. Returns `None` for unknown hash
+/// algorithms.
+fn nsec3_hash(name: &str, nsec3: &Nsec3) -> Option<String> {
+    if nsec3.hash_algorithm != Nsec3::SHA1_HASH {
+        return None;
+    }
+
+    Some(base32hex(&Nsec3::hash_name(
+        name,
+        nsec3.iterations,
+        &nsec3.salt,
+    )))
+}
+
+fn exceeds_iteration_limit(records: &[&Answer]) -> bool {
+    records.iter().any(|record| {
+        matches!(&record.data, RData::Nsec3(nsec3) if nsec3.iterations > MAX_NSEC3_ITERATIONS)
+    })
+}
+
+// A name error needs proof that the name does not exist and that there is no wildcard at its
+// closest encloser, the closest ancestor that does exist
+//
+// https://www.rfc-editor.org/rfc/rfc4035#section-5.4
+// https://www.rfc-editor.org/rfc/rfc5155#section-8.4
+fn proves_name_error(name: &str, records: &[&Answer]) -> bool {
+    let Some(encloser) = closest_encloser(name, records) else {
+        return false;
+    };
+
+    let wildcard = wildcard_name(&encloser);
+
+    nsec_covering(&wildcard, records).is_some() || nsec3_covering(&wildcard, records).is_some()
+}
+
+// A CNAME synthesized from a DNAME is sent unsigned. It is accepted when a DNAME above its
+// owner in the same answer redirects to exactly its target (RFC 6672 section 5.3.1).
+fn is_synthesized(records: &[&Answer], response: &Message) -> bool {
+    let [cname] = records else {
+        return false;
+    };
+
+    let (DnsType::CNAME, RData::DomainName(target)) = (cname.kind, &cname.data) else {
+        return false;
+    };
+
+    let name = normalize(&cname.name);
+
+    response.answers.iter().any(|answer| match &answer.data {
+        RData::DomainName(dname_target) if answer.kind == DnsType::DNAME => {
+            let owner = normalize(&answer.name);
+
+            owner != name
+                && is_subdomain(&name, &owner)
+                && DomainName::substitute(&name, &owner, &normalize(dname_target))
+                    == Some(normalize(target))
+        }
+        _ => false,
+    })
+}
+
+// Types at the wildcard that would have matched `name`, when the records prove that `name`
+// does not exist and that the wildcard has neither the type nor a CNAME
+//
+// https://www.rfc-editor.org/rfc/rfc4035#section-3.1.3.4
+// https://www.rfc-editor.org/rfc/rfc5155#section-8.7
+fn wildcard_no_data(name: &str, kind: u16, records: &[&Answer]) -> Option<TypeBitmap> {
+    let wildcard = wildcard_name(&closest_encloser(name, records)?);
+    let cname: u16 = DnsType::CNAME.into();
+
+    let types = records.iter().find_map(|record| match &record.data {
+        RData::Nsec(nsec) if normalize(&record.name) == wildcard => Some(&nsec.types),
+        _ => None,
+    });
+    let types = types.or_else(|| {
+        find_nsec3(&wildcard, records, |owner_hash, _, hash| owner_hash == hash)
+            .map(|nsec3| &nsec3.types)
+    })?;
+
+    (!types.contains(kind) && !types.contains(cname)).then(|| types.clone())
+}
+
+// The longest existing ancestor of a name the records prove does not exist
+fn closest_encloser(name: &str, records: &[&Answer]) -> Option<String> {
+    match nsec_covering(name, records) {
+        // With NSEC, the closest encloser is the longest ancestor the name shares with either
+        // end of the covering span
+        Some((owner, next)) => {
+            let owner_ancestor = common_ancestor(name, &owner);
+            let next_ancestor = common_ancestor(name, &next);
+
+            if label_count(&owner_ancestor) >= label_count(&next_ancestor) {
+                Some(owner_ancestor)
+            } else {
+                Some(next_ancestor)
+            }
+        }
+        None => nsec3_closest_encloser(name, records),
+    }
+}
+
+fn wildcard_name(encloser: &str) -> String {
+    match encloser {
+        "" => "*".to_string(),
+        encloser => format!("*.{}", encloser),
+    }
+}
+
+// Owner and next name of an NSEC record whose span covers `name`
+fn nsec_covering(name: &str, records: &[&Answer]) -> Option<(String, String)> {
+    records.iter().find_map(|record| match &record.data {
+        RData::Nsec(nsec) => {
+            let owner = normalize(&record.name);
+            let next = normalize(&nsec.next_domain_name);
+
+            covers(&owner, &next, name, DomainName::canonical_cmp).then_some((owner, next))
+        }
+        _ => None,
+    })
+}
+
+// The closest ancestor of `name` with a matching NSEC3 record, along with a record covering
+// the next closer name below it
+//
+// https://www.rfc-editor.org/rfc/rfc5155#section-8.3
+fn nsec3_closest_encloser(name: &str, records: &[&Answer]) -> Option<String> {
+    let count = label_count(name);
+
+    for depth in (0..count).rev() {
+        let encloser = suffix(name, depth);
+
+        if find_nsec3(&encloser, records, |owner_hash, _, hash| owner_hash == hash).is_some() {
+            let next_closer = suffix(name, depth + 1);
+
+            return nsec3_covering(&next_closer, records).map(|_| encloser);
+        }
+    }
+
+    None
+}
+
+fn nsec3_covering<'r>(name: &str, records: &[&'r Answer]) -> Option<&'r Nsec3> {
+    find_nsec3(name, records, |owner_hash, next, hash| {
+        covers(owner_hash, next, hash, |a, b| a.cmp(b))
+    })
+}
+
+// The first NSEC3 record of the zone holding `name` whose owner hash and next hash pass the
+// check against the hash of `name`
+fn find_nsec3<'r, F>(name: &str, records: &[&'r Answer], check: F) -> Option<&'r Nsec3>
+where
+    F: Fn(&str, &str, &str) -> bool,
+{
+    records.iter().find_map(|record| {
+        let RData::Nsec3(nsec3) = &record.data else {
+            return None;
+        };
+
+        let owner = normalize(&record.name);
+        let (owner_hash, zone) = owner.split_once('.')?;
+
+        if !is_subdomain(name, zone) {
+            return None;
+        }
+
+        let hash = nsec3_hash(name, nsec3)?;
+        let next = base32hex(&nsec3.next_hashed_owner_name);
+
+        check(owner_hash, &next, &hash).then_some(nsec3)
+    })
+}
+
+// Whether the span between an NSEC owner and the next name covers `name`. The last record of
+// the zone wraps around to the apex.
+fn covers<F>(owner: &str, next: &str, name: &str, compare: F) -> bool
+where
+    F: Fn(&str, &str) -> Ordering,
+{
+    if compare(owner, next) == Ordering::Less {
+        compare(owner, name) == Ordering::Less && compare(name, next) == Ordering::Less
+    } else {
+        compare(owner, name) == Ordering::Less || compare(name, next) == Ordering::Less
+    }
+}
+
+fn signatures<'b>(records: &'b [Answer], owner: &str, kind: DnsType) -> Vec<&'b Rrsig> {
+    records
+        .iter()
+        .filter(|record| normalize(&record.name) == owner)
+        .filter_map(|record| match &record.data {
+            RData::Rrsig(rrsig) if rrsig.type_covered == kind.into() => Some(rrsig),
+            _ => None,
+        })
+        .collect()
+}
+
+// Names are compared lowercased and without the trailing dot, the root is the empty string
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+fn labels(name: &str) -> impl DoubleEndedIterator<Item = &str> {
+    name.split('.').filter(|label| !label.is_empty())
+}
+
+fn label_count(name: &str) -> usize {
+    labels(name).count()
+}
+
+// The last `count` labels of a name
+fn suffix(name: &str, count: usize) -> String {
+    let labels: Vec<&str> = labels(name).collect();
+
+    labels[labels.len().saturating_sub(count)..].join(".")
+}
+
+fn common_ancestor(a: &str, b: &str) -> String {
+    let shared: Vec<&str> = labels(a)
+        .rev()
+        .zip(labels(b).rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(label, _)| label)
+        .collect();
+
+    shared.into_iter().rev().collect::<Vec<&str>>().join(".")
+}
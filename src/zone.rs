@@ -0,0 +1,1707 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Range,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ServerError,
+    message::{
+        answer::Answer,
+        dnssec::{
+            base32hex, decode_base32hex, Dnskey, Ds, Nsec, Nsec3, Nsec3Param, Rrsig, TypeBitmap,
+        },
+        rdata::{Caa, Loc, Mx, Naptr, RData, RDataDecoder, Serial, Soa, Srv, Sshfp, Tlsa, Uri},
+        svcb::{check_mandatory, SvcParam, Svcb},
+        types::{DnsClass, DnsType, DomainName},
+    },
+};
+
+/// TTL of records without one, when the master file has no `$TTL` directive either.
+const DEFAULT_TTL: u32 = 3600;
+
+/// The records of a zone, as read from or written to a master file.
+#[derive(Clone)]
+pub struct Zone {
+    /// Apex of the zone, without the trailing dot.
+    pub origin: String,
+    pub records: Vec<Answer>,
+}
+
+/// Writes the zone in master file format, one record per line with absolute names.
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in &self.records {
+            writeln!(
+                f,
+                "{}. {} {} {} {}",
+                record.name,
+                record.ttl,
+                record.class,
+                record.kind,
+                format_rdata(&record.data)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Zone {
+    /// The records of an RRset must share a TTL, the lowest one is used (RFC 2181 section 5.2).
+    pub fn normalize_ttls(&mut self) {
+        let mut ttls: HashMap<(String, u16), u32> = HashMap::new();
+
+        for record in &self.records {
+            ttls.entry((record.name.to_lowercase(), record.kind.into()))
+                .and_modify(|ttl| *ttl = (*ttl).min(record.ttl))
+                .or_insert(record.ttl);
+        }
+
+        for record in &mut self.records {
+            record.ttl = ttls[&(record.name.to_lowercase(), record.kind.into())];
+        }
+    }
+
+    /// Gives the apex SOA record a new serial according to the policy, so secondaries pick up
+    /// the change.
+    pub fn update_serial(&mut self, policy: SerialPolicy) -> Result<(), ServerError> {
+        if policy == SerialPolicy::Keep {
+            return Ok(());
+        }
+
+        let soa = self
+            .records
+            .iter_mut()
+            .filter(|record| record.name.eq_ignore_ascii_case(&self.origin))
+            .find_map(|record| match &mut record.data {
+                RData::Soa(soa) => Some(soa),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                ServerError::ParseZoneFile(format!("{} has no SOA record", self.origin))
+            })?;
+
+        soa.serial = policy.next(soa.serial, SystemTime::now());
+
+        Ok(())
+    }
+}
+
+/// How the SOA serial changes when a zone is rewritten.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialPolicy {
+    Keep,
+    /// Adds one to the serial.
+    Increment,
+    /// Uses the `YYYYMMDDnn` convention: the date of the change followed by a two digit
+    /// revision. Serials already at or past today's date are incremented instead.
+    Date,
+}
+
+impl SerialPolicy {
+    pub fn next(self, serial: Serial, now: SystemTime) -> Serial {
+        match self {
+            SerialPolicy::Keep => serial,
+            SerialPolicy::Increment => serial.increment(),
+            SerialPolicy::Date => {
+                let timestamp = now
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs() as u32);
+                // The timestamp format starts with the date as YYYYMMDD
+                let date: u32 = format_timestamp(timestamp)[..8].parse().unwrap();
+                let today = Serial(date * 100);
+
+                if today > serial {
+                    today
+                } else {
+                    serial.increment()
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for SerialPolicy {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "keep" => Ok(SerialPolicy::Keep),
+            "increment" => Ok(SerialPolicy::Increment),
+            "date" => Ok(SerialPolicy::Date),
+            _ => Err(ServerError::InvalidSerialPolicy(format!(
+                "{} is not one of keep, increment or date",
+                value
+            ))),
+        }
+    }
+}
+
+/// Reads a master file. Supports the `$ORIGIN` and `$TTL` directives, `@`, relative names,
+/// owners carried over from the previous record, optional TTL and class fields in either order,
+/// parentheses spanning several lines, comments and quoted strings.
+///
+/// https://www.rfc-editor.org/rfc/rfc1035#section-5
+pub struct ZoneFileParser<'a> {
+    input: &'a str,
+    origin: String,
+}
+
+impl<'a> ZoneFileParser<'a> {
+    /// `origin` is the zone apex, used for `@` and relative names until a `$ORIGIN` directive
+    /// changes it.
+    pub fn new(input: &'a str, origin: &str) -> Self {
+        Self {
+            input,
+            origin: origin.trim_end_matches('.').to_string(),
+        }
+    }
+
+    pub fn parse(self) -> Result<Zone, ServerError> {
+        let mut origin = self.origin.clone();
+        let mut default_ttl = None;
+        let mut last_owner: Option<String> = None;
+        let mut last_ttl = None;
+        let mut last_class = DnsClass::IN;
+        let mut records = Vec::new();
+
+        for entry in tokenize(self.input)? {
+            let error = |message: String| {
+                ServerError::ParseZoneFile(format!("line {}: {}", entry.line, message))
+            };
+
+            let mut fields = Fields::new(&entry.tokens);
+
+            if entry.has_owner && entry.tokens[0].text.starts_with('$') {
+                let directive = fields.next().map_err(error)?.to_uppercase();
+
+                match directive.as_str() {
+                    "$ORIGIN" => origin = absolute_name(fields.next().map_err(error)?, &origin),
+                    "$TTL" => {
+                        default_ttl = Some(parse_ttl(fields.next().map_err(error)?).map_err(error)?)
+                    }
+                    _ => return Err(error(format!("{} is not supported", directive))),
+                }
+
+                continue;
+            }
+
+            let owner = if entry.has_owner {
+                absolute_name(fields.next().map_err(error)?, &origin)
+            } else {
+                last_owner
+                    .clone()
+                    .ok_or_else(|| error("the first record has no owner".to_string()))?
+            };
+
+            let mut ttl = None;
+            let mut class = None;
+
+            // The TTL and the class are optional and may come in either order
+            let kind = loop {
+                let field = fields
+                    .next()
+                    .map_err(|_| error("record type is missing".to_string()))?;
+
+                if ttl.is_none() {
+                    if let Ok(value) = parse_ttl(field) {
+                        ttl = Some(value);
+                        continue;
+                    }
+                }
+
+                if class.is_none() {
+                    if let Ok(value) = DnsClass::from_str(field) {
+                        class = Some(value);
+                        continue;
+                    }
+                }
+
+                break DnsType::from_str(field).map_err(|err| error(err.to_string()))?;
+            };
+
+            // Files without a $TTL directive rely on the TTL of the previous record
+            let ttl = ttl.or(default_ttl).or(last_ttl).unwrap_or(DEFAULT_TTL);
+            let class = class.unwrap_or(last_class);
+            let data = parse_rdata(kind, &mut fields, &origin).map_err(error)?;
+
+            last_owner = Some(owner.clone());
+            last_ttl = Some(ttl);
+            last_class = class;
+
+            records.push(Answer {
+                name: owner,
+                kind,
+                class,
+                ttl,
+                data,
+            });
+        }
+
+        Ok(Zone {
+            origin: self.origin,
+            records,
+        })
+    }
+}
+
+// A field of a master file entry, with escapes left as written. Quoted fields keep their spaces.
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+// One record or directive, which may span several lines inside parentheses
+struct Entry {
+    line: usize,
+    /// Entries starting with a blank reuse the owner of the previous record.
+    has_owner: bool,
+    tokens: Vec<Token>,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Entry>, ServerError> {
+    let mut entries = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+    let mut depth = 0;
+    let mut is_line_start = true;
+    let mut entry = Entry {
+        line,
+        has_owner: false,
+        tokens: Vec::new(),
+    };
+
+    let error = |line: usize, message: &str| {
+        ServerError::ParseZoneFile(format!("line {}: {}", line, message))
+    };
+
+    while let Some(c) = chars.next() {
+        if is_line_start && depth == 0 {
+            entry.line = line;
+            entry.has_owner = !c.is_whitespace();
+        }
+
+        is_line_start = false;
+
+        match c {
+            '\n' => {
+                line += 1;
+                is_line_start = true;
+
+                if depth == 0 && !entry.tokens.is_empty() {
+                    entries.push(std::mem::replace(
+                        &mut entry,
+                        Entry {
+                            line,
+                            has_owner: false,
+                            tokens: Vec::new(),
+                        },
+                    ));
+                }
+            }
+            ';' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '(' => depth += 1,
+            ')' => {
+                if depth == 0 {
+                    return Err(error(line, "closing parenthesis without an opening one"));
+                }
+
+                depth -= 1;
+            }
+            '"' => {
+                let mut text = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            text.push('\\');
+                            text.extend(chars.next());
+                        }
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+
+                            text.push(c);
+                        }
+                        None => return Err(error(line, "quoted string is not terminated")),
+                    }
+                }
+
+                entry.tokens.push(Token { text, quoted: true });
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut text = String::from(c);
+
+                if c == '\\' {
+                    text.extend(chars.next());
+                }
+
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !matches!(c, ';' | '(' | ')' | '"'))
+                {
+                    text.push(c);
+
+                    if c == '\\' {
+                        text.extend(chars.next());
+                    }
+                }
+
+                entry.tokens.push(Token {
+                    text,
+                    quoted: false,
+                });
+            }
+        }
+    }
+
+    if depth > 0 {
+        return Err(error(line, "parenthesis is not closed"));
+    }
+
+    if !entry.tokens.is_empty() {
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+// Cursor over the fields of an entry
+struct Fields<'a> {
+    tokens: &'a [Token],
+    index: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(tokens: &'a [Token]) -> Self {
+        Self { tokens, index: 0 }
+    }
+
+    fn next_token(&mut self) -> Result<&'a Token, String> {
+        let token = self
+            .tokens
+            .get(self.index)
+            .ok_or_else(|| "record data is incomplete".to_string())?;
+
+        self.index += 1;
+
+        Ok(token)
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        Ok(&self.next_token()?.text)
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, String> {
+        let field = self.next()?;
+
+        field
+            .parse()
+            .map_err(|_| format!("{} is not a valid number", field))
+    }
+
+    fn name(&mut self, origin: &str) -> Result<String, String> {
+        Ok(absolute_name(self.next()?, origin))
+    }
+
+    // Base64 and hex data may be split over several fields
+    fn rest(&mut self) -> String {
+        let rest: String = self.tokens[self.index..]
+            .iter()
+            .map(|token| token.text.as_str())
+            .collect();
+
+        self.index = self.tokens.len();
+
+        rest
+    }
+
+    fn is_empty(&self) -> bool {
+        self.index >= self.tokens.len()
+    }
+
+    fn finish(&self) -> Result<(), String> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("unexpected field {}", self.tokens[self.index].text))
+        }
+    }
+}
+
+fn parse_rdata(kind: DnsType, fields: &mut Fields, origin: &str) -> Result<RData, String> {
+    // Any type may use the generic form of RFC 3597
+    if fields
+        .tokens
+        .get(fields.index)
+        .is_some_and(|token| !token.quoted && token.text == "\\#")
+    {
+        fields.next()?;
+
+        let length: usize = fields.number()?;
+        let data = decode_hex(&fields.rest()).ok_or("record data is not valid hex")?;
+
+        if data.len() != length {
+            return Err(format!(
+                "record data is {} bytes long, {} were announced",
+                data.len(),
+                length
+            ));
+        }
+
+        // Known types are read into their typed form, so they compare equal to records written
+        // in presentation form (RFC 3597 section 5)
+        return match kind {
+            DnsType::Unknown(_) => Ok(RData::Raw(data)),
+            kind => {
+                let message = Bytes::from(data);
+
+                RDataDecoder::new(&mut message.clone(), &message, kind, length as u16)
+                    .decode()
+                    .map_err(|err| err.to_string())
+            }
+        };
+    }
+
+    let data = match kind {
+        DnsType::A => {
+            let field = fields.next()?;
+
+            RData::A(
+                field
+                    .parse()
+                    .map_err(|_| format!("{} is not a valid IPv4 address", field))?,
+            )
+        }
+        DnsType::NS
+        | DnsType::CNAME
+        | DnsType::PTR
+        | DnsType::DNAME
+        | DnsType::MD
+        | DnsType::MF
+        | DnsType::MB
+        | DnsType::MG
+        | DnsType::MR => RData::DomainName(fields.name(origin)?),
+        DnsType::AAAA => {
+            let field = fields.next()?;
+
+            RData::Aaaa(
+                field
+                    .parse()
+                    .map_err(|_| format!("{} is not a valid IPv6 address", field))?,
+            )
+        }
+        DnsType::MX => RData::Mx(Mx {
+            preference: fields.number()?,
+            exchange: fields.name(origin)?,
+        }),
+        DnsType::SRV => RData::Srv(Srv {
+            priority: fields.number()?,
+            weight: fields.number()?,
+            port: fields.number()?,
+            target: fields.name(origin)?,
+        }),
+        DnsType::NAPTR => RData::Naptr(Naptr {
+            order: fields.number()?,
+            preference: fields.number()?,
+            flags: character_string(fields.next_token()?)?,
+            services: character_string(fields.next_token()?)?,
+            regexp: character_string(fields.next_token()?)?,
+            replacement: fields.name(origin)?,
+        }),
+        // The target is a single string, which unlike character-strings has no length limit
+        DnsType::URI => RData::Uri(Uri {
+            priority: fields.number()?,
+            weight: fields.number()?,
+            target: unescape(fields.next()?)?,
+        }),
+        DnsType::LOC => RData::Loc(parse_loc(fields)?),
+        // The timers accept TTL units, e.g. `1h` or `2w`
+        DnsType::SOA => RData::Soa(Soa {
+            mname: fields.name(origin)?,
+            rname: fields.name(origin)?,
+            serial: Serial(fields.number()?),
+            refresh: parse_ttl(fields.next()?)?,
+            retry: parse_ttl(fields.next()?)?,
+            expire: parse_ttl(fields.next()?)?,
+            minimum: parse_ttl(fields.next()?)?,
+        }),
+        // Types without a model are kept in wire format. Their names are lowercased here, as the
+        // canonical encoding used for signing cannot see inside raw data.
+        DnsType::MINFO => {
+            let mut buf = BytesMut::new();
+
+            buf.put(DomainName::encode(&fields.name(origin)?.to_lowercase()));
+            buf.put(DomainName::encode(&fields.name(origin)?.to_lowercase()));
+
+            RData::Raw(buf.to_vec())
+        }
+        DnsType::TXT | DnsType::HINFO => {
+            let mut strings = Vec::new();
+
+            while !fields.is_empty() {
+                strings.push(character_string(fields.next_token()?)?);
+            }
+
+            if strings.is_empty() || (kind == DnsType::HINFO && strings.len() != 2) {
+                return Err(format!("{} record has the wrong number of strings", kind));
+            }
+
+            RData::Strings(strings)
+        }
+        DnsType::SSHFP => RData::Sshfp(Sshfp {
+            algorithm: fields.number()?,
+            fingerprint_type: fields.number()?,
+            fingerprint: decode_hex(&fields.rest()).ok_or("fingerprint is not valid hex")?,
+        }),
+        DnsType::TLSA => RData::Tlsa(Tlsa {
+            usage: fields.number()?,
+            selector: fields.number()?,
+            matching_type: fields.number()?,
+            data: decode_hex(&fields.rest()).ok_or("certificate data is not valid hex")?,
+        }),
+        // The value is a single string, which unlike character-strings has no length limit
+        DnsType::CAA => {
+            let flags = fields.number()?;
+            let tag = fields.next()?;
+
+            if !Caa::is_valid_tag(tag) {
+                return Err(format!("{} is not a valid CAA property tag", tag));
+            }
+
+            RData::Caa(Caa {
+                flags,
+                tag: tag.to_string(),
+                value: unescape(fields.next()?)?,
+            })
+        }
+        DnsType::SVCB | DnsType::HTTPS => {
+            let priority = fields.number()?;
+            let target = fields.name(origin)?;
+            let mut params = Vec::new();
+
+            while !fields.is_empty() {
+                params.push(parse_svc_param(fields)?);
+            }
+
+            params.sort_by_key(SvcParam::key);
+
+            if let Some(pair) = params
+                .windows(2)
+                .find(|pair| pair[0].key() == pair[1].key())
+            {
+                return Err(format!(
+                    "{} is given more than once",
+                    SvcParam::key_name(pair[0].key())
+                ));
+            }
+
+            if priority == 0 && !params.is_empty() {
+                return Err("AliasMode records cannot have service parameters".to_string());
+            }
+
+            check_mandatory(&params)?;
+
+            RData::Svcb(Svcb {
+                priority,
+                target,
+                params,
+            })
+        }
+        DnsType::DNSKEY | DnsType::CDNSKEY => RData::Dnskey(Dnskey {
+            flags: fields.number()?,
+            protocol: fields.number()?,
+            algorithm: fields.number()?,
+            public_key: STANDARD
+                .decode(fields.rest())
+                .map_err(|err| format!("public key is not valid base64: {}", err))?,
+        }),
+        DnsType::DS | DnsType::CDS => RData::Ds(Ds {
+            key_tag: fields.number()?,
+            algorithm: fields.number()?,
+            digest_type: fields.number()?,
+            digest: decode_hex(&fields.rest()).ok_or("digest is not valid hex")?,
+        }),
+        DnsType::NSEC3PARAM => RData::Nsec3Param(Nsec3Param {
+            hash_algorithm: fields.number()?,
+            flags: fields.number()?,
+            iterations: fields.number()?,
+            salt: parse_salt(fields.next()?)?,
+        }),
+        DnsType::RRSIG => RData::Rrsig(Rrsig {
+            type_covered: parse_type(fields.next()?)?,
+            algorithm: fields.number()?,
+            labels: fields.number()?,
+            original_ttl: fields.number()?,
+            expiration: parse_timestamp(fields.next()?)?,
+            inception: parse_timestamp(fields.next()?)?,
+            key_tag: fields.number()?,
+            signer_name: fields.name(origin)?,
+            signature: STANDARD
+                .decode(fields.rest())
+                .map_err(|err| format!("signature is not valid base64: {}", err))?,
+        }),
+        DnsType::NSEC => RData::Nsec(Nsec {
+            next_domain_name: fields.name(origin)?,
+            types: parse_types(fields)?,
+        }),
+        DnsType::NSEC3 => RData::Nsec3(Nsec3 {
+            hash_algorithm: fields.number()?,
+            flags: fields.number()?,
+            iterations: fields.number()?,
+            salt: parse_salt(fields.next()?)?,
+            next_hashed_owner_name: decode_base32hex(fields.next()?)
+                .ok_or("next hashed owner name is not valid base32hex")?,
+            types: parse_types(fields)?,
+        }),
+        _ => {
+            return Err(format!(
+                "{} records can only be read in the generic \\# form",
+                kind
+            ))
+        }
+    };
+
+    fields.finish()?;
+
+    Ok(data)
+}
+
+fn format_rdata(data: &RData) -> String {
+    match data {
+        RData::A(addr) => addr.to_string(),
+        RData::Aaaa(addr) => addr.to_string(),
+        RData::DomainName(name) => format!("{}.", name),
+        RData::Soa(soa) => format!(
+            "{}. {}. {} {} {} {} {}",
+            soa.mname, soa.rname, soa.serial.0, soa.refresh, soa.retry, soa.expire, soa.minimum
+        ),
+        RData::Mx(mx) => format!("{} {}.", mx.preference, mx.exchange),
+        RData::Srv(srv) => format!(
+            "{} {} {} {}.",
+            srv.priority, srv.weight, srv.port, srv.target
+        ),
+        RData::Strings(strings) => strings
+            .iter()
+            .map(|string| quote_character_string(string))
+            .collect::<Vec<_>>()
+            .join(" "),
+        RData::Naptr(naptr) => format!(
+            "{} {} {} {} {} {}.",
+            naptr.order,
+            naptr.preference,
+            quote_character_string(&naptr.flags),
+            quote_character_string(&naptr.services),
+            quote_character_string(&naptr.regexp),
+            naptr.replacement
+        ),
+        RData::Uri(uri) => format!(
+            "{} {} {}",
+            uri.priority,
+            uri.weight,
+            quote_character_string(&uri.target)
+        ),
+        RData::Loc(loc) => format!(
+            "{} {} {} {} {} {}",
+            format_coordinate(loc.latitude, 'N', 'S'),
+            format_coordinate(loc.longitude, 'E', 'W'),
+            format_altitude(loc.altitude),
+            format_precision(loc.size),
+            format_precision(loc.horizontal_precision),
+            format_precision(loc.vertical_precision)
+        ),
+        RData::Sshfp(sshfp) => format!(
+            "{} {} {}",
+            sshfp.algorithm,
+            sshfp.fingerprint_type,
+            encode_hex(&sshfp.fingerprint)
+        ),
+        RData::Tlsa(tlsa) => format!(
+            "{} {} {} {}",
+            tlsa.usage,
+            tlsa.selector,
+            tlsa.matching_type,
+            encode_hex(&tlsa.data)
+        ),
+        RData::Caa(caa) => format!(
+            "{} {} {}",
+            caa.flags,
+            caa.tag,
+            quote_character_string(&caa.value)
+        ),
+        RData::Svcb(svcb) => std::iter::once(format!("{} {}.", svcb.priority, svcb.target))
+            .chain(svcb.params.iter().map(format_svc_param))
+            .collect::<Vec<_>>()
+            .join(" "),
+        RData::Dnskey(dnskey) => format!(
+            "{} {} {} {}",
+            dnskey.flags,
+            dnskey.protocol,
+            dnskey.algorithm,
+            STANDARD.encode(&dnskey.public_key)
+        ),
+        RData::Ds(ds) => format!(
+            "{} {} {} {}",
+            ds.key_tag,
+            ds.algorithm,
+            ds.digest_type,
+            encode_hex(&ds.digest)
+        ),
+        RData::Rrsig(rrsig) => format!(
+            "{} {} {} {} {} {} {} {}. {}",
+            type_name(rrsig.type_covered),
+            rrsig.algorithm,
+            rrsig.labels,
+            rrsig.original_ttl,
+            format_timestamp(rrsig.expiration),
+            format_timestamp(rrsig.inception),
+            rrsig.key_tag,
+            rrsig.signer_name,
+            STANDARD.encode(&rrsig.signature)
+        ),
+        RData::Nsec(nsec) => format!("{}. {}", nsec.next_domain_name, format_types(&nsec.types)),
+        RData::Nsec3(nsec3) => format!(
+            "{} {} {} {} {} {}",
+            nsec3.hash_algorithm,
+            nsec3.flags,
+            nsec3.iterations,
+            format_salt(&nsec3.salt),
+            base32hex(&nsec3.next_hashed_owner_name).to_uppercase(),
+            format_types(&nsec3.types)
+        )
+        // Empty non-terminals have no types
+        .trim_end()
+        .to_string(),
+        RData::Nsec3Param(param) => format!(
+            "{} {} {} {}",
+            param.hash_algorithm,
+            param.flags,
+            param.iterations,
+            format_salt(&param.salt)
+        ),
+        RData::Raw(data) if data.is_empty() => "\\# 0".to_string(),
+        RData::Raw(data) => format!("\\# {} {}", data.len(), encode_hex(data)),
+    }
+}
+
+// Parameters are written as `key=value`, keys without a value as `key`. Lists are separated by
+// commas, with commas inside items escaped.
+fn parse_svc_param(fields: &mut Fields) -> Result<SvcParam, String> {
+    let field = fields.next()?;
+    let (name, value) = match field.split_once('=') {
+        // Quoted values are tokens of their own, e.g. alpn="h2,h3"
+        Some((name, "")) => (name, Some(fields.next()?)),
+        Some((name, value)) => (name, Some(value)),
+        None => (field, None),
+    };
+
+    let key = SvcParam::parse_key(name)
+        .ok_or_else(|| format!("{} is not a valid service parameter key", name))?;
+    let key_name = SvcParam::key_name(key);
+
+    let Some(value) = value else {
+        return match key_name.as_str() {
+            "no-default-alpn" => Ok(SvcParam::NoDefaultAlpn),
+            _ if key_name.starts_with("key") => Ok(SvcParam::Unknown(key, Vec::new())),
+            _ => Err(format!("{} needs a value", key_name)),
+        };
+    };
+
+    let invalid = || format!("{} is not a valid {} value", value, key_name);
+
+    let param = match key_name.as_str() {
+        "mandatory" => {
+            let mut keys = split_list(value)
+                .iter()
+                .map(|name| SvcParam::parse_key(name).ok_or_else(invalid))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // Duplicates are kept so check_mandatory rejects them
+            keys.sort_unstable();
+
+            SvcParam::Mandatory(keys)
+        }
+        "alpn" => {
+            let ids = parse_value_list(value)?;
+
+            if ids.iter().any(|id| id.is_empty() || id.len() > 255) {
+                return Err(invalid());
+            }
+
+            SvcParam::Alpn(ids)
+        }
+        "no-default-alpn" => return Err("no-default-alpn takes no value".to_string()),
+        "port" => SvcParam::Port(value.parse().map_err(|_| invalid())?),
+        "ipv4hint" => SvcParam::Ipv4Hint(
+            split_list(value)
+                .iter()
+                .map(|addr| addr.parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?,
+        ),
+        "ech" => SvcParam::Ech(STANDARD.decode(value).map_err(|_| invalid())?),
+        "ipv6hint" => SvcParam::Ipv6Hint(
+            split_list(value)
+                .iter()
+                .map(|addr| addr.parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?,
+        ),
+        _ => SvcParam::Unknown(key, unescape(value)?),
+    };
+
+    Ok(param)
+}
+
+fn format_svc_param(param: &SvcParam) -> String {
+    let name = SvcParam::key_name(param.key());
+    let value = match param {
+        SvcParam::Mandatory(keys) => keys
+            .iter()
+            .map(|key| SvcParam::key_name(*key))
+            .collect::<Vec<_>>()
+            .join(","),
+        SvcParam::Alpn(ids) => {
+            let mut list = Vec::new();
+
+            for (index, id) in ids.iter().enumerate() {
+                if index > 0 {
+                    list.push(b',');
+                }
+
+                for &byte in id {
+                    if matches!(byte, b',' | b'\\') {
+                        list.push(b'\\');
+                    }
+
+                    list.push(byte);
+                }
+            }
+
+            quote_character_string(&list)
+        }
+        SvcParam::NoDefaultAlpn => return name,
+        SvcParam::Port(port) => port.to_string(),
+        SvcParam::Ipv4Hint(addrs) => addrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        SvcParam::Ech(config) if config.is_empty() => "\"\"".to_string(),
+        SvcParam::Ech(config) => STANDARD.encode(config),
+        SvcParam::Ipv6Hint(addrs) => addrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        SvcParam::Unknown(_, value) => quote_character_string(value),
+    };
+
+    format!("{}={}", name, value)
+}
+
+// Splits a comma separated value list, keeping the escapes of each item
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let item = items.last_mut().unwrap();
+
+                item.push(c);
+                item.extend(chars.next());
+            }
+            ',' => items.push(String::new()),
+            c => items.last_mut().unwrap().push(c),
+        }
+    }
+
+    items
+}
+
+// Items of a value list are split on commas once the string itself is unescaped, so a comma or
+// a backslash inside an item is escaped twice, e.g. alpn="f\\\\oo\\,bar,h2" for `f\oo,bar` and
+// `h2` (RFC 9460 appendix A.1)
+fn parse_value_list(value: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut items = vec![Vec::new()];
+    let mut bytes = unescape(value)?.into_iter();
+
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\\' => items.last_mut().unwrap().extend(bytes.next()),
+            b',' => items.push(Vec::new()),
+            byte => items.last_mut().unwrap().push(byte),
+        }
+    }
+
+    Ok(items)
+}
+
+// Types without a mnemonic use the TYPE<number> form of RFC 3597
+fn type_name(kind: u16) -> String {
+    DnsType::try_from(kind)
+        .map(|kind| kind.to_string())
+        .unwrap_or_else(|_| format!("TYPE{}", kind))
+}
+
+fn format_types(types: &TypeBitmap) -> String {
+    let names: Vec<String> = types.types().iter().map(|kind| type_name(*kind)).collect();
+
+    names.join(" ")
+}
+
+fn parse_type(value: &str) -> Result<u16, String> {
+    DnsType::from_str(value)
+        .map(u16::from)
+        .map_err(|_| format!("{} is not a valid DNS type", value))
+}
+
+// The types of an NSEC or NSEC3 type bitmap, which take up the rest of the record
+fn parse_types(fields: &mut Fields) -> Result<TypeBitmap, String> {
+    let mut types = Vec::new();
+
+    while !fields.is_empty() {
+        types.push(parse_type(fields.next()?)?);
+    }
+
+    Ok(TypeBitmap::new(types))
+}
+
+fn parse_salt(value: &str) -> Result<Vec<u8>, String> {
+    match value {
+        "-" => Ok(Vec::new()),
+        salt => decode_hex(salt).ok_or_else(|| "salt is not valid hex".to_string()),
+    }
+}
+
+// An empty salt is written as a dash
+fn format_salt(salt: &[u8]) -> String {
+    if salt.is_empty() {
+        "-".to_string()
+    } else {
+        encode_hex(salt)
+    }
+}
+
+// Signature times are either YYYYMMDDHHmmSS in UTC or seconds since the epoch (RFC 4034
+// section 3.2). Times past 2106 wrap around, as serial numbers do.
+fn parse_timestamp(value: &str) -> Result<u32, String> {
+    let invalid = || format!("{} is not a valid signature time", value);
+
+    if value.len() != 14 {
+        return value.parse().map_err(|_| invalid());
+    }
+
+    let field = |range: Range<usize>, valid: Range<i64>| {
+        value
+            .get(range)
+            .and_then(|field| field.parse::<i64>().ok())
+            .filter(|field| valid.contains(field))
+            .ok_or_else(invalid)
+    };
+
+    let (year, month, day) = (
+        field(0..4, 0..10000)?,
+        field(4..6, 1..13)?,
+        field(6..8, 1..32)?,
+    );
+    let (hour, minute, second) = (
+        field(8..10, 0..24)?,
+        field(10..12, 0..60)?,
+        field(12..14, 0..60)?,
+    );
+
+    // Converts a civil date to days since the epoch, see
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    Ok((days * 86400 + hour * 3600 + minute * 60 + second) as u32)
+}
+
+// Signature times are written as YYYYMMDDHHmmSS in UTC (RFC 4034 section 3.2)
+fn format_timestamp(timestamp: u32) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Converts days since the epoch to a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// TTLs are given in seconds, or with the `s`, `m`, `h`, `d` and `w` units BIND accepts, e.g.
+/// `1h30m`.
+pub fn parse_ttl(value: &str) -> Result<u32, String> {
+    if let Ok(ttl) = value.parse() {
+        return Ok(ttl);
+    }
+
+    let invalid = || format!("{} is not a valid TTL", value);
+    let mut ttl: u32 = 0;
+    let mut number = String::new();
+
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return Err(invalid()),
+        };
+
+        ttl = number
+            .parse::<u32>()
+            .ok()
+            .and_then(|number| number.checked_mul(unit))
+            .and_then(|seconds| ttl.checked_add(seconds))
+            .ok_or_else(invalid)?;
+
+        number.clear();
+    }
+
+    if !number.is_empty() || value.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(ttl)
+}
+
+// Names ending with a dot are absolute, others are relative to the origin
+fn absolute_name(name: &str, origin: &str) -> String {
+    if name == "@" {
+        origin.to_string()
+    } else if let Some(name) = name.strip_suffix('.') {
+        name.to_string()
+    } else if origin.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, origin)
+    }
+}
+
+// Wire value of the equator and the prime meridian
+const LOC_EQUATOR: i64 = 1 << 31;
+
+// Wire value of the altitude base, 100000 meters below the reference spheroid
+const LOC_ALTITUDE_BASE: i64 = 10_000_000;
+
+// `d1 [m1 [s1]] N|S d2 [m2 [s2]] E|W alt[m] [size[m] [hp[m] [vp[m]]]]`, where the size
+// defaults to 1m, the horizontal precision to 10000m and the vertical one to 10m (RFC 1876
+// section 3)
+fn parse_loc(fields: &mut Fields) -> Result<Loc, String> {
+    let latitude = parse_coordinate(fields, ('N', 'S'), 90)?;
+    let longitude = parse_coordinate(fields, ('E', 'W'), 180)?;
+
+    let altitude = fields.next()?;
+    let altitude = parse_decimal(altitude.strip_suffix('m').unwrap_or(altitude), 2)
+        .map(|centimeters| centimeters + LOC_ALTITUDE_BASE)
+        .and_then(|altitude| u32::try_from(altitude).ok())
+        .ok_or_else(|| format!("{} is not a valid altitude", altitude))?;
+
+    let mut precisions = [0x12, 0x16, 0x13];
+
+    for precision in &mut precisions {
+        if fields.is_empty() {
+            break;
+        }
+
+        *precision = parse_precision(fields.next()?)?;
+    }
+
+    let [size, horizontal_precision, vertical_precision] = precisions;
+
+    Ok(Loc {
+        size,
+        horizontal_precision,
+        vertical_precision,
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+// Degrees with optional minutes and seconds, then the hemisphere
+fn parse_coordinate(
+    fields: &mut Fields,
+    (positive, negative): (char, char),
+    max_degrees: i64,
+) -> Result<u32, String> {
+    let mut parts = Vec::new();
+
+    let sign = loop {
+        let field = fields.next()?;
+
+        match field.to_uppercase().chars().collect::<Vec<_>>()[..] {
+            [hemisphere] if hemisphere == positive => break 1,
+            [hemisphere] if hemisphere == negative => break -1,
+            _ if parts.len() < 3 => parts.push(field),
+            _ => return Err(format!("{} is not a valid hemisphere", field)),
+        }
+    };
+
+    let invalid = || format!("{} is not a valid coordinate", parts.join(" "));
+
+    let degrees: i64 = parts
+        .first()
+        .ok_or_else(invalid)?
+        .parse()
+        .map_err(|_| invalid())?;
+    let minutes: i64 = parts
+        .get(1)
+        .map_or(Ok(0), |part| part.parse())
+        .map_err(|_| invalid())?;
+    let seconds = parts.get(2).map_or(Some(0), |part| parse_decimal(part, 3));
+    let seconds = seconds
+        .filter(|seconds| (0..60_000).contains(seconds))
+        .ok_or_else(invalid)?;
+
+    let thousandths = (degrees * 3600 + minutes * 60) * 1000 + seconds;
+
+    if !(0..=max_degrees * 3_600_000).contains(&thousandths) || !(0..60).contains(&minutes) {
+        return Err(invalid());
+    }
+
+    Ok((LOC_EQUATOR + sign * thousandths) as u32)
+}
+
+// Meters with up to two decimals, stored as a mantissa and a power of ten of centimeters
+fn parse_precision(field: &str) -> Result<u8, String> {
+    let mut centimeters = parse_decimal(field.strip_suffix('m').unwrap_or(field), 2)
+        .filter(|centimeters| (0..=9_000_000_000).contains(centimeters))
+        .ok_or_else(|| format!("{} is not a valid size or precision", field))?;
+
+    let mut exponent = 0;
+
+    while centimeters > 9 {
+        centimeters /= 10;
+        exponent += 1;
+    }
+
+    Ok(((centimeters as u8) << 4) | exponent)
+}
+
+// Reads a decimal number scaled by 10^digits, e.g. 1.5 with two digits is 150
+fn parse_decimal(text: &str, digits: usize) -> Option<i64> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+
+    if whole.is_empty()
+        || fraction.len() > digits
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let value: i64 = format!("{}{:0<digits$}", whole, fraction).parse().ok()?;
+
+    Some(if negative { -value } else { value })
+}
+
+fn format_coordinate(value: u32, positive: char, negative: char) -> String {
+    let offset = value as i64 - LOC_EQUATOR;
+    let thousandths = offset.abs();
+
+    format!(
+        "{} {} {}.{:03} {}",
+        thousandths / 3_600_000,
+        thousandths / 60_000 % 60,
+        thousandths / 1000 % 60,
+        thousandths % 1000,
+        if offset < 0 { negative } else { positive }
+    )
+}
+
+fn format_altitude(value: u32) -> String {
+    let centimeters = value as i64 - LOC_ALTITUDE_BASE;
+
+    format!(
+        "{}{}.{:02}m",
+        if centimeters < 0 { "-" } else { "" },
+        centimeters.abs() / 100,
+        centimeters.abs() % 100
+    )
+}
+
+fn format_precision(value: u8) -> String {
+    let centimeters = (value >> 4) as u64 * 10u64.pow((value & 0x0f) as u32);
+
+    format!("{}.{:02}m", centimeters / 100, centimeters % 100)
+}
+
+fn quote_character_string(string: &[u8]) -> String {
+    format!("\"{}\"", escape(string))
+}
+
+// Quotes and backslashes are escaped with a backslash, bytes outside printable ASCII with their
+// \DDD decimal value (RFC 1035 section 5.1)
+fn escape(string: &[u8]) -> String {
+    let mut escaped = String::new();
+
+    for &byte in string {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03}", byte)),
+        }
+    }
+
+    escaped
+}
+
+// Decodes the `\X` and `\DDD` escapes of a string
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some(digit) if digit.is_ascii_digit() => {
+                    let digits: String = std::iter::once(digit)
+                        .chain(chars.by_ref().take(2))
+                        .collect();
+
+                    bytes.push(
+                        digits
+                            .parse()
+                            .ok()
+                            .filter(|_| digits.len() == 3)
+                            .ok_or_else(|| format!("\\{} is not a valid escape", digits))?,
+                    );
+
+                    continue;
+                }
+                Some(c) => c,
+                None => return Err("escape is not terminated".to_string()),
+            },
+            c => c,
+        };
+
+        bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+
+    Ok(bytes)
+}
+
+// A character string holds at most 255 bytes
+fn character_string(token: &Token) -> Result<Vec<u8>, String> {
+    let bytes = unescape(&token.text)?;
+
+    if bytes.len() > 255 {
+        return Err(format!(
+            "character string is {} bytes long, the limit is 255",
+            bytes.len()
+        ));
+    }
+
+    Ok(bytes)
+}
+
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+pub fn decode_hex(data: &str) -> Option<Vec<u8>> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..data.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(data.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::message::rdata::RDataEncoder;
+
+    // 2026-10-18 12:00:00 UTC
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_792_324_800)
+    }
+
+    #[test]
+    fn keep_and_increment_policies() {
+        assert_eq!(SerialPolicy::Keep.next(Serial(7), now()), Serial(7));
+        assert_eq!(SerialPolicy::Increment.next(Serial(7), now()), Serial(8));
+        assert_eq!(
+            SerialPolicy::Increment.next(Serial(u32::MAX), now()),
+            Serial(0)
+        );
+    }
+
+    #[test]
+    fn date_policy_moves_an_older_serial_to_today() {
+        assert_eq!(
+            SerialPolicy::Date.next(Serial(2026101503), now()),
+            Serial(2026101800)
+        );
+        assert_eq!(
+            SerialPolicy::Date.next(Serial(1), now()),
+            Serial(2026101800)
+        );
+    }
+
+    #[test]
+    fn date_policy_increments_a_serial_at_or_past_today() {
+        assert_eq!(
+            SerialPolicy::Date.next(Serial(2026101800), now()),
+            Serial(2026101801)
+        );
+        assert_eq!(
+            SerialPolicy::Date.next(Serial(2026101899), now()),
+            Serial(2026101900)
+        );
+        assert_eq!(
+            SerialPolicy::Date.next(Serial(2026112001), now()),
+            Serial(2026112002)
+        );
+    }
+
+    #[test]
+    fn unknown_types_and_classes_use_the_generic_form() {
+        let input = "host 300 CLASS32 TYPE65280 \\# 3 abcdef\n\
+                     host 300 IN TYPE1 \\# 4 c0000201\n\
+                     host 300 IN TYPE65281 \\# 0\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+
+        assert_eq!(zone.records[0].kind, DnsType::Unknown(65280));
+        assert!(matches!(zone.records[0].class, DnsClass::Unknown(32)));
+        assert!(matches!(&zone.records[0].data, RData::Raw(data) if data == &[0xab, 0xcd, 0xef]));
+
+        // Known types given by number are read into their typed form
+        assert_eq!(zone.records[1].kind, DnsType::A);
+        assert!(matches!(zone.records[1].data, RData::A(addr) if addr.octets() == [192, 0, 2, 1]));
+
+        assert_eq!(
+            zone.to_string(),
+            "host.example.com. 300 CLASS32 TYPE65280 \\# 3 ABCDEF\n\
+             host.example.com. 300 IN A 192.0.2.1\n\
+             host.example.com. 300 IN TYPE65281 \\# 0\n"
+        );
+    }
+
+    #[test]
+    fn naptr_uri_and_loc_records() {
+        let input = "@ NAPTR 100 10 \"U\" \"E2U+sip\" \"!^.*$!sip:info@example.com!\" .\n\
+                     _sip._tcp URI 10 1 \"sip:example.com\"\n\
+                     @ LOC 42 21 54 N 71 06 18 W -24m 30m\n\
+                     @ LOC 52 22 23.5 S 4 53 E 2.5m 1m 100m 5m\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+
+        let RData::Loc(loc) = &zone.records[2].data else {
+            panic!("LOC record was not parsed");
+        };
+
+        // The example of RFC 1876 section 3, with the default precisions
+        assert_eq!(loc.size, 0x33);
+        assert_eq!(loc.horizontal_precision, 0x16);
+        assert_eq!(loc.vertical_precision, 0x13);
+        assert_eq!(loc.latitude, (1 << 31) + 152_514_000);
+        assert_eq!(loc.longitude, (1 << 31) - 255_978_000);
+        assert_eq!(loc.altitude, 10_000_000 - 2400);
+
+        let data: Vec<String> = zone
+            .records
+            .iter()
+            .map(|record| format_rdata(&record.data))
+            .collect();
+
+        assert_eq!(
+            data,
+            [
+                "100 10 \"U\" \"E2U+sip\" \"!^.*$!sip:info@example.com!\" .",
+                "10 1 \"sip:example.com\"",
+                "42 21 54.000 N 71 6 18.000 W -24.00m 30.00m 10000.00m 10.00m",
+                "52 22 23.500 S 4 53 0.000 E 2.50m 1.00m 100.00m 5.00m",
+            ]
+        );
+
+        // The wire format decodes to the same data
+        for record in &zone.records {
+            let encoded = RDataEncoder::encode(&record.data);
+            let decoded = RDataDecoder::new(
+                &mut encoded.clone(),
+                &encoded,
+                record.kind,
+                encoded.len() as u16,
+            )
+            .decode()
+            .unwrap();
+
+            assert_eq!(decoded, record.data);
+        }
+    }
+
+    #[test]
+    fn meta_types_are_not_record_types() {
+        assert!(DnsType::from_str("TYPE41").is_err());
+        assert!(DnsType::from_str("TYPE255").is_err());
+        assert!(DnsClass::from_str("CLASS255").is_err());
+        assert!(DnsType::from_str("TYPEA").is_err());
+    }
+
+    #[test]
+    fn serial_policy_names() {
+        assert_eq!(SerialPolicy::from_str("Date").unwrap(), SerialPolicy::Date);
+        assert!(SerialPolicy::from_str("monotonic").is_err());
+    }
+
+    #[test]
+    fn dnssec_records_are_read_in_presentation_form() {
+        // The RRSIG example of RFC 4034 section 3.3, with the NSEC and NSEC3 records written
+        // the way the signer writes them
+        let input = "host.example.com. 86400 IN RRSIG A 5 3 86400 20030322173103 (\n\
+                     \t20030220173103 2642 example.com.\n\
+                     \toJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTrPYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6o\n\
+                     \tB9wfuh3DTJXUAfI/M0zmO/zz8bW0Rznl8O3tGNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkG\n\
+                     \tJ5D6fwFm8nN+6pBzeDQfsS3Ap3o= )\n\
+                     alfa.example.com. 86400 IN NSEC host.example.com. A MX RRSIG NSEC TYPE1234\n\
+                     0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example. 3600 IN NSEC3 1 1 12 aabbccdd \
+                     2T7B4G4VSA5SMI47K61MV5BV1A22BOJR NS SOA MX RRSIG DNSKEY NSEC3PARAM\n\
+                     w.example. 3600 IN NSEC3 1 0 0 - 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR\n";
+        let zone = ZoneFileParser::new(input, "example.com").parse().unwrap();
+
+        let RData::Rrsig(rrsig) = &zone.records[0].data else {
+            panic!("not an RRSIG record");
+        };
+
+        assert_eq!(rrsig.type_covered, u16::from(DnsType::A));
+        assert_eq!(rrsig.expiration, 1048354263);
+        assert_eq!(rrsig.inception, 1045762263);
+        assert_eq!(rrsig.key_tag, 2642);
+        assert_eq!(rrsig.signer_name, "example.com");
+        assert_eq!(rrsig.signature.len(), 128);
+
+        let RData::Nsec(nsec) = &zone.records[1].data else {
+            panic!("not an NSEC record");
+        };
+
+        assert_eq!(nsec.next_domain_name, "host.example.com");
+        assert!(nsec.types.contains(1234));
+
+        let RData::Nsec3(nsec3) = &zone.records[2].data else {
+            panic!("not an NSEC3 record");
+        };
+
+        assert_eq!(nsec3.flags, 1);
+        assert_eq!(nsec3.salt, [0xaa, 0xbb, 0xcc, 0xdd]);
+        assert_eq!(
+            base32hex(&nsec3.next_hashed_owner_name),
+            "2t7b4g4vsa5smi47k61mv5bv1a22bojr"
+        );
+
+        // Written out again, each record reads back the same
+        for record in &zone.records {
+            let written = Zone {
+                origin: "example.com".to_string(),
+                records: vec![record.clone()],
+            }
+            .to_string();
+            let read = ZoneFileParser::new(&written, "example.com")
+                .parse()
+                .unwrap();
+
+            assert_eq!(read.records[0].data, record.data, "{}", written);
+        }
+    }
+
+    #[test]
+    fn signature_times_are_dates_or_seconds() {
+        for time in [0, 1045762263, 1792324800, u32::MAX] {
+            assert_eq!(parse_timestamp(&format_timestamp(time)), Ok(time));
+        }
+
+        assert_eq!(parse_timestamp("1045762263"), Ok(1045762263));
+        assert!(parse_timestamp("20031322173103").is_err());
+        assert!(parse_timestamp("2003022017310x").is_err());
+    }
+
+    #[test]
+    fn txt_strings_round_trip_through_presentation_form() {
+        let long = "a".repeat(255);
+        let input = format!(
+            "@ 300 IN TXT \"v=spf1 -all\" \"say \\\"hi\\\"\" back\\\\slash \"caf\u{e9}\" \
+             \"\\000\\255\\010\" {}\n",
+            long
+        );
+        let zone = ZoneFileParser::new(&input, "example.com").parse().unwrap();
+        let strings = vec![
+            b"v=spf1 -all".to_vec(),
+            b"say \"hi\"".to_vec(),
+            b"back\\slash".to_vec(),
+            // Non-ASCII text is kept as its UTF-8 bytes
+            "caf\u{e9}".as_bytes().to_vec(),
+            vec![0, 255, b'\n'],
+            long.as_bytes().to_vec(),
+        ];
+
+        assert_eq!(zone.records[0].data, RData::Strings(strings));
+
+        // Each string is length prefixed on the wire
+        let encoded = RDataEncoder::encode(&zone.records[0].data);
+        let decoded = RDataDecoder::new(
+            &mut encoded.clone(),
+            &encoded,
+            DnsType::TXT,
+            encoded.len() as u16,
+        )
+        .decode()
+        .unwrap();
+
+        assert_eq!(&encoded[..12], b"\x0bv=spf1 -all");
+        assert_eq!(decoded, zone.records[0].data);
+
+        let written = zone.to_string();
+
+        assert_eq!(
+            written,
+            format!(
+                "example.com. 300 IN TXT \"v=spf1 -all\" \"say \\\"hi\\\"\" \"back\\\\slash\" \
+                 \"caf\\195\\169\" \"\\000\\255\\010\" \"{}\"\n",
+                long
+            )
+        );
+        assert_eq!(
+            ZoneFileParser::new(&written, "example.com")
+                .parse()
+                .unwrap()
+                .records[0]
+                .data,
+            zone.records[0].data
+        );
+    }
+
+    #[test]
+    fn txt_strings_are_limited_to_255_bytes() {
+        for input in [
+            format!("@ TXT {}", "a".repeat(256)),
+            // Escapes count as the byte they stand for
+            format!("@ TXT \"{}\\065\"", "a".repeat(255)),
+            "@ TXT \"\\256\"".to_string(),
+            "@ TXT \"\\06\"".to_string(),
+            "@ TXT abc\\".to_string(),
+        ] {
+            assert!(
+                ZoneFileParser::new(&input, "example.com").parse().is_err(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn caa_tlsa_and_sshfp_records() {
+        let tlsa = "0C72AC70B745AC19998811B131D662C9AC69DBDBE7CB23E5B514B56664C5D3D6";
+        let sshfp = "123456789ABCDEF67890123456789ABCDEF67890123456789ABCDEF123456789";
+        let input = format!(
+            "@ 300 IN CAA 0 issue \"ca.example.net; account=230123\"\n\
+             @ 300 IN CAA 128 tbs \"Unknown\"\n\
+             _443._tcp.www 300 IN TLSA 3 1 1 {}\n\
+             host 300 IN SSHFP 4 2 {}\n",
+            tlsa.to_lowercase(),
+            sshfp
+        );
+        let zone = ZoneFileParser::new(&input, "example.com").parse().unwrap();
+
+        assert_eq!(
+            zone.records[0].data,
+            RData::Caa(Caa {
+                flags: 0,
+                tag: "issue".to_string(),
+                value: b"ca.example.net; account=230123".to_vec(),
+            })
+        );
+        assert!(matches!(
+            &zone.records[2].data,
+            RData::Tlsa(record) if record.usage == 3 && record.data == decode_hex(tlsa).unwrap()
+        ));
+        assert!(matches!(
+            &zone.records[3].data,
+            RData::Sshfp(record) if record.algorithm == 4 && record.fingerprint_type == 2
+        ));
+
+        assert_eq!(
+            zone.to_string(),
+            format!(
+                "example.com. 300 IN CAA 0 issue \"ca.example.net; account=230123\"\n\
+                 example.com. 300 IN CAA 128 tbs \"Unknown\"\n\
+                 _443._tcp.www.example.com. 300 IN TLSA 3 1 1 {}\n\
+                 host.example.com. 300 IN SSHFP 4 2 {}\n",
+                tlsa, sshfp
+            )
+        );
+
+        for record in &zone.records {
+            let encoded = RDataEncoder::encode(&record.data);
+            let decoded = RDataDecoder::new(
+                &mut encoded.clone(),
+                &encoded,
+                record.kind,
+                encoded.len() as u16,
+            )
+            .decode()
+            .unwrap();
+
+            assert_eq!(decoded, record.data);
+        }
+
+        // Tags are letters and digits only (RFC 8659 section 4.1)
+        let input = "@ CAA 0 is-sue \"ca.example.net\"";
+        assert!(ZoneFileParser::new(input, "example.com").parse().is_err());
+    }
+}